@@ -26,6 +26,7 @@ mkdir -p /etc/suplinux
 mkdir -p /var/lib/suplinux
 mkdir -p /var/lib/suplinux/users
 mkdir -p /var/lib/suplinux/enrollment
+mkdir -p /var/lib/suplinux/audit
 mkdir -p /usr/share/suplinux/models
 mkdir -p /usr/local/bin
 mkdir -p /usr/local/lib/suplinux
@@ -152,13 +153,25 @@ if ! id -u suplinux >/dev/null 2>&1; then
     usermod -a -G video suplinux  # Need video group for camera access
 fi
 
+# Shared secret used by the service to sign auth responses for the PAM module
+if [ ! -f /etc/suplinux/auth.secret ]; then
+    echo "Generating authentication secret..."
+    head -c 32 /dev/urandom > /etc/suplinux/auth.secret
+fi
+chown root:suplinux /etc/suplinux/auth.secret
+# Readable by PAM (root) and the service only. PAM stacks that run as the
+# user - screen lockers such as swaylock, i3lock or xscreensaver - can't read
+# it, so pam_suplinux.so returns PAM_AUTHINFO_UNAVAIL there and the stack
+# falls through to the password
+chmod 640 /etc/suplinux/auth.secret
+
 # Install systemd service
 if [ -d "/etc/systemd/system" ]; then
-    echo "Installing systemd service..."
+    echo "Installing systemd service and socket..."
     cp systemd/suplinux.service /etc/systemd/system/
+    cp systemd/suplinux.socket /etc/systemd/system/
     systemctl daemon-reload
-    echo "To enable the service: systemctl enable suplinux"
-    echo "To start the service: systemctl start suplinux"
+    echo "To enable the service: systemctl enable --now suplinux.socket"
 fi
 
 # Create tracking file for uninstall
@@ -171,6 +184,7 @@ cat > /var/lib/suplinux/.installed_files <<EOF
 /usr/local/lib/suplinux
 /lib/security/pam_suplinux.so
 /etc/systemd/system/suplinux.service
+/etc/systemd/system/suplinux.socket
 /etc/suplinux
 /var/lib/suplinux
 /usr/share/suplinux
@@ -210,9 +224,8 @@ echo
 echo "━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━"
 echo "STEP 1: Start the Service"
 echo "━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━"
-echo "1. Start the service:"
-echo "   sudo systemctl start suplinux"
-echo "   sudo systemctl enable suplinux  # (optional: auto-start)"
+echo "1. Enable the service socket (the service starts on first use):"
+echo "   sudo systemctl enable --now suplinux.socket"
 echo
 echo "━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━"
 echo "STEP 2: Enroll and Test (No logout required!)"
@@ -242,8 +255,5 @@ echo "- Any PAM-aware application"
 echo
 echo "To disable later, run 'sudo pam-auth-update' and deselect it."
 echo
-echo "To enable automatic startup:"
-echo "  sudo systemctl enable suplinux"
-echo
 echo "To uninstall, run: sudo ./uninstall.sh"
 echo
\ No newline at end of file
@@ -1,22 +1,42 @@
 #[macro_use]
 extern crate pamsm;
 
-use pamsm::{PamServiceModule, Pam, PamFlags, PamError};
-use sup_linux::protocol::{Request, Response, AuthRequest, SOCKET_PATH};
+use pamsm::{PamServiceModule, Pam, PamFlags, PamError, PamMsgStyle};
+use sup_linux::protocol::{self, Request, Response, AuthRequest, StreamMessage, AuthProgress, FEATURE_SIGNED_AUTH, FEATURE_AUTH_PROGRESS, FEATURE_NOT_APPLICABLE, MSG_TYPE_REQUEST, MSG_TYPE_STREAM, SOCKET_PATH};
+use sup_linux::signing::{self, AUTH_SECRET_PATH};
 use rand::{Rng, thread_rng};
-use std::time::{SystemTime, Duration};
+use std::time::{SystemTime, Duration, Instant};
 use anyhow::Result;
 use std::os::unix::net::UnixStream;
-use std::io::{Read, Write};
+use std::path::Path;
+use std::ffi::CStr;
+
+mod session;
+use session::SessionChecks;
 
 const CHALLENGE_SIZE: usize = 32;
 
 // Protocol types imported from sup_linux::protocol
 
+// Verified result of one authentication round trip
+enum AuthOutcome {
+    Success,
+    Failed,
+    LockedOut,  // Too many recent failures - reported as PAM_MAXTRIES
+    NotApplicable,  // Face auth is off for this PAM service - reported as PAM_IGNORE
+}
+
+// PAM items describing where the authentication comes from
+struct Caller {
+    service: Option<String>,
+    tty: Option<String>,
+    rhost: Option<String>,
+}
+
 pub struct SupLinuxPam;
 
 impl PamServiceModule for SupLinuxPam {
-    fn authenticate(pamh: Pam, _flags: PamFlags, args: Vec<String>) -> PamError {
+    fn authenticate(pamh: Pam, flags: PamFlags, args: Vec<String>) -> PamError {
         // eprintln!("SupLinux: PAM module authenticate() called");
         
         // Parse timeout from args (format: "timeout=10")
@@ -32,9 +52,45 @@ impl PamServiceModule for SupLinuxPam {
             })
             .unwrap_or(10); // Default: wait 10s for service response
         
-        // Get username from PAM handle
+        // Parse freshness window from args (format: "max_age=5")
+        // Signed responses older than this are rejected as possible replays
+        let max_age_secs = args.iter()
+            .find_map(|arg| arg.strip_prefix("max_age=")?.parse::<u64>().ok())
+            .unwrap_or(5);
+        
+        // Socket and secret overrides (format: "socket=/path", "secret=/path"),
+        // for running against a dev-mode service in integration tests
+        let socket_path = args.iter()
+            .find_map(|arg| arg.strip_prefix("socket="))
+            .unwrap_or(SOCKET_PATH)
+            .to_string();
+        let secret_path = args.iter()
+            .find_map(|arg| arg.strip_prefix("secret="))
+            .unwrap_or(AUTH_SECRET_PATH)
+            .to_string();
+        
+        // "quiet" (or PAM_SILENT from the application) keeps the module from
+        // printing prompts and results through the conversation function
+        let quiet = args.iter().any(|arg| arg == "quiet") || flags.contains(PamFlags::SILENT);
+        
         use pamsm::PamLibExt;
         
+        // PAM service name (sudo, login, gdm-password...), terminal and remote
+        // host select the service's policy and go into its audit log
+        let caller = Caller {
+            service: pam_item(pamh.get_service()),
+            tty: pam_item(pamh.get_tty()),
+            rhost: pam_item(pamh.get_rhost()),
+        };
+
+        // Nobody can be in front of the camera over SSH or with the lid shut -
+        // let the next module ask instead of waiting out the timeout
+        if let Some(_reason) = SessionChecks::from_args(&args).skip_reason(caller.rhost.as_deref()) {
+            // eprintln!("SupLinux: Skipping face authentication: {}", _reason);
+            return PamError::IGNORE;
+        }
+        
+        // Get username from PAM handle
         let username = match pamh.get_cached_user() {
             Ok(Some(user_cstr)) => {
                 match user_cstr.to_str() {
@@ -58,16 +114,43 @@ impl PamServiceModule for SupLinuxPam {
             }
         };
 
+        // Without the shared secret no response can be trusted. Stacks running
+        // as the user (screen lockers, polkit agents) can't read it - step aside
+        // so the next module asks instead of failing every time.
+        let secret = match signing::load_auth_secret(Path::new(&secret_path)) {
+            Ok(secret) => secret,
+            Err(_) => {
+                // eprintln!("SupLinux: Cannot load auth secret");
+                return PamError::AUTHINFO_UNAVAIL;
+            }
+        };
+
+        // Progress and results as PAM_TEXT_INFO, e.g. under the sudo prompt or in GDM
+        let feedback = |message: &str| {
+            if !quiet {
+                // Best effort - not every application can show text
+                let _ = pamh.conv(Some(message), PamMsgStyle::TEXT_INFO);
+            }
+        };
+
         // Perform authentication with PAM timeout
-        match perform_authentication(&username, pam_timeout_secs) {
-            Ok(true) => {
+        match perform_authentication(&username, &caller, &socket_path, pam_timeout_secs, max_age_secs, &secret, quiet, &feedback) {
+            Ok(AuthOutcome::Success) => {
                 // eprintln!("SupLinux: Face authentication successful for {}", username);
                 PamError::SUCCESS
             }
-            Ok(false) => {
+            Ok(AuthOutcome::Failed) => {
                 // eprintln!("SupLinux: Face authentication failed for {}", username);
                 PamError::AUTH_ERR
             }
+            Ok(AuthOutcome::LockedOut) => {
+                // eprintln!("SupLinux: {} is locked out after too many failures", username);
+                PamError::MAXTRIES
+            }
+            Ok(AuthOutcome::NotApplicable) => {
+                // eprintln!("SupLinux: Face authentication disabled by policy");
+                PamError::IGNORE
+            }
             Err(e) => {
                 // eprintln!("SupLinux: Authentication error: {}", e);
                 PamError::SERVICE_ERR
@@ -76,16 +159,16 @@ impl PamServiceModule for SupLinuxPam {
     }
 }
 
-fn perform_authentication(username: &str, pam_timeout_secs: u64) -> Result<bool> {
+fn perform_authentication(username: &str, caller: &Caller, socket_path: &str, pam_timeout_secs: u64, max_age_secs: u64, secret: &[u8], quiet: bool, feedback: &dyn Fn(&str)) -> Result<AuthOutcome> {
     // Generate random challenge for security
     let challenge = generate_challenge();
     
     // Connect to embedding service
-    let mut stream = match UnixStream::connect(SOCKET_PATH) {
+    let mut stream = match UnixStream::connect(socket_path) {
         Ok(s) => s,
         Err(e) => {
             // eprintln!("Failed to connect to embedding service: {}", e);
-            return Ok(false);
+            return Ok(AuthOutcome::Failed);
         }
     };
     
@@ -94,58 +177,125 @@ fn perform_authentication(username: &str, pam_timeout_secs: u64) -> Result<bool>
     stream.set_read_timeout(Some(Duration::from_secs(pam_timeout_secs)))?;
     stream.set_write_timeout(Some(Duration::from_secs(5)))?;
     
+    // Agree on protocol version and features before trusting anything we read
+    let client = concat!("pam_suplinux ", env!("CARGO_PKG_VERSION"));
+    let hello = protocol::client_handshake(&mut stream, client, FEATURE_SIGNED_AUTH)?;
+    
+    // Nothing would show the updates when quiet. Older services ignore the
+    // field, but only ask what they offer.
+    let stream_progress = !quiet && hello.features & FEATURE_AUTH_PROGRESS != 0;
+    // Services that say when the camera starts get to turn us away (policy,
+    // lockout) before we ask anyone to look at it
+    let prompt_on_start = stream_progress && hello.features & FEATURE_NOT_APPLICABLE != 0;
+    
     // Create authentication request
+    let request_time = SystemTime::now();
     let request = Request::Authenticate(AuthRequest {
         username: username.to_string(),
         challenge: challenge.clone(),
-        timestamp: SystemTime::now(),
+        timestamp: request_time,
+        pam_service: caller.service.clone(),
+        pam_tty: caller.tty.clone(),
+        pam_rhost: caller.rhost.clone(),
+        stream_progress,
     });
     
     // Send request
-    let request_data = bincode::serialize(&request)?;
-    let request_len = (request_data.len() as u32).to_le_bytes();
-    stream.write_all(&request_len)?;
-    stream.write_all(&request_data)?;
-    stream.flush()?;
-    
-    // Read response
-    let mut len_buf = [0u8; 4];
-    stream.read_exact(&mut len_buf)?;
-    let response_len = u32::from_le_bytes(len_buf) as usize;
-    
-    if response_len > 1024 * 1024 {
-        anyhow::bail!("Response too large");
+    protocol::write_frame(&mut stream, MSG_TYPE_REQUEST, &request)?;
+    if !prompt_on_start {
+        feedback("Look at the camera for face authentication");
     }
     
-    let mut response_buf = vec![0u8; response_len];
-    stream.read_exact(&mut response_buf)?;
-    
-    let response: Response = bincode::deserialize(&response_buf)?;
+    // Status updates arrive before the response; the timeout covers them all
+    let deadline = Instant::now() + Duration::from_secs(pam_timeout_secs);
+    let response = loop {
+        // A zero read timeout would mean no timeout at all
+        let remaining = deadline.saturating_duration_since(Instant::now()).max(Duration::from_millis(1));
+        stream.set_read_timeout(Some(remaining))?;
+        
+        let frame = match protocol::read_frame(&mut stream) {
+            Ok(frame) => frame,
+            Err(e) => {
+                // Gave up waiting - tell the service so it releases the camera now
+                let _ = protocol::write_frame(&mut stream, MSG_TYPE_REQUEST, &Request::Cancel);
+                feedback("Face authentication timed out");
+                return Err(e.into());
+            }
+        };
+        if protocol::is_supported_version(frame.version) && frame.msg_type == MSG_TYPE_STREAM {
+            // Stream messages are unsigned, so only our own wording is shown.
+            // Updates this build doesn't know are skipped, they're only informational.
+            if let Ok(StreamMessage::AuthProgress { step }) = frame.decode::<StreamMessage>() {
+                feedback(match step {
+                    AuthProgress::Started => "Look at the camera for face authentication",
+                    AuthProgress::FaceDetected => "Face detected, verifying...",
+                    AuthProgress::FaceLost => "Face lost - look at the camera",
+                });
+            }
+            continue;
+        }
+        break frame.into_response()?;
+    };
     
     // Extract authentication result
     match response {
         Response::Auth(auth) => {
-            // Verify signature for security (optional but recommended)
-            if !auth.signature.is_empty() {
-                // In production, we might want to verify the signature
-                // For now, we trust the service since it's on the same machine
+            // Anything could be listening on the socket - only accept a reply
+            // signed with the shared secret, for our challenge, sent just now
+            if auth.challenge != challenge {
+                // eprintln!("SupLinux: Challenge mismatch in service response");
+                return Ok(AuthOutcome::Failed);
+            }
+            if !signing::verify_auth_response(secret, username, &auth) {
+                // eprintln!("SupLinux: Invalid signature on service response");
+                return Ok(AuthOutcome::Failed);
+            }
+            if !signing::is_fresh(auth.timestamp, request_time, Duration::from_secs(max_age_secs)) {
+                // eprintln!("SupLinux: Stale service response");
+                return Ok(AuthOutcome::Failed);
             }
             // eprintln!("SupLinux: Authentication {} - {}", 
             //     if auth.success { "succeeded" } else { "failed" },
             //     auth.message);
-            Ok(auth.success)
+            if auth.not_applicable {
+                // Not our call for this service - the next module asks, silently
+                Ok(AuthOutcome::NotApplicable)
+            } else if auth.success {
+                feedback("Face recognized");
+                Ok(AuthOutcome::Success)
+            } else if auth.locked_until.is_some() {
+                feedback(&auth.message);
+                Ok(AuthOutcome::LockedOut)
+            } else {
+                feedback(&format!("Face authentication failed: {}", auth.message));
+                Ok(AuthOutcome::Failed)
+            }
         }
         Response::Error(msg) => {
+            // Unsigned, like everything but AuthResponse - don't show it
             // eprintln!("SupLinux: Service error: {}", msg);
-            Ok(false)
+            feedback("Face authentication unavailable");
+            Ok(AuthOutcome::Failed)
+        }
+        Response::CameraBusy(_msg) => {
+            feedback("Camera is busy - face authentication unavailable");
+            Ok(AuthOutcome::Failed)
         }
         _ => {
             // eprintln!("SupLinux: Unexpected response type");
-            Ok(false)
+            Ok(AuthOutcome::Failed)
         }
     }
 }
 
+// A PAM item as an owned string; unset and non-UTF-8 items are None
+fn pam_item(item: std::result::Result<Option<&CStr>, PamError>) -> Option<String> {
+    match item {
+        Ok(Some(value)) => value.to_str().ok().map(str::to_string),
+        _ => None,
+    }
+}
+
 fn generate_challenge() -> Vec<u8> {
     let mut rng = thread_rng();
     let mut challenge = vec![0u8; CHALLENGE_SIZE];
@@ -0,0 +1,174 @@
+// Detection of sessions where nobody can be in front of the camera, so the
+// module can step aside with PAM_IGNORE instead of waiting out the timeout
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// /proc/self/sessionid when the process has no audit session
+const NO_SESSION: &str = "4294967295";
+
+/// Which checks to run. All but the lid check are on unless turned off by
+/// a module argument; the lid check is opt-in because a docked laptop with
+/// its lid shut may still have an external camera.
+pub struct SessionChecks {
+    pub rhost: bool,    // no_rhost_check
+    pub ssh: bool,      // no_ssh_check
+    pub session: bool,  // no_session_check
+    pub lid: bool,      // lid_check
+}
+
+impl SessionChecks {
+    pub fn from_args(args: &[String]) -> Self {
+        let has = |name: &str| args.iter().any(|arg| arg == name);
+        Self {
+            rhost: !has("no_rhost_check"),
+            ssh: !has("no_ssh_check"),
+            session: !has("no_session_check"),
+            lid: has("lid_check"),
+        }
+    }
+
+    /// Why face auth can't work for this caller, if it can't
+    pub fn skip_reason(&self, rhost: Option<&str>) -> Option<String> {
+        if self.rhost {
+            if let Some(host) = rhost.filter(|host| !is_local_host(host)) {
+                return Some(format!("remote login from {}", host));
+            }
+        }
+        if self.ssh && env::var_os("SSH_CONNECTION").is_some() {
+            return Some("running over SSH".to_string());
+        }
+        if self.session {
+            if let Some(reason) = logind_session_reason() {
+                return Some(reason);
+            }
+        }
+        if self.lid && lid_closed() {
+            return Some("laptop lid is closed".to_string());
+        }
+        None
+    }
+}
+
+fn is_local_host(host: &str) -> bool {
+    matches!(host, "" | "localhost" | "127.0.0.1" | "::1")
+}
+
+// logind records each session under /run/systemd/sessions/<id> with TYPE=
+// (tty, x11, wayland...) and REMOTE=1 for remote logins. A caller outside
+// any session, like the GDM greeter's worker, is left alone.
+fn logind_session_reason() -> Option<String> {
+    let id = fs::read_to_string("/proc/self/sessionid").ok()?;
+    let id = id.trim();
+    if id.is_empty() || id == NO_SESSION {
+        return None;
+    }
+
+    let (session_type, remote) = match fs::read_to_string(Path::new("/run/systemd/sessions").join(id)) {
+        Ok(contents) => parse_session(&contents),
+        // Not a logind system; the session type is still in the environment
+        Err(_) => (env::var("XDG_SESSION_TYPE").ok(), false),
+    };
+    session_reason(id, session_type.as_deref(), remote)
+}
+
+// TYPE= and REMOTE= out of a logind session file
+fn parse_session(contents: &str) -> (Option<String>, bool) {
+    let value = |key: &str| contents.lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+        .map(str::to_string);
+    (value("TYPE"), value("REMOTE").map_or(false, |remote| remote == "1"))
+}
+
+fn session_reason(id: &str, session_type: Option<&str>, remote: bool) -> Option<String> {
+    if remote {
+        return Some(format!("session {} is remote", id));
+    }
+    match session_type {
+        Some("tty") => Some(format!("session {} is a text console", id)),
+        _ => None,
+    }
+}
+
+// /proc/acpi/button/lid/<LID>/state reads "state:      closed"
+fn lid_closed() -> bool {
+    let entries = match fs::read_dir("/proc/acpi/button/lid") {
+        Ok(entries) => entries,
+        Err(_) => return false,  // No lid switch
+    };
+    entries.flatten().any(|entry| {
+        fs::read_to_string(entry.path().join("state"))
+            .map_or(false, |state| lid_state_closed(&state))
+    })
+}
+
+fn lid_state_closed(state: &str) -> bool {
+    state.split_whitespace().last() == Some("closed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn local_hosts() {
+        for host in ["", "localhost", "127.0.0.1", "::1"] {
+            assert!(is_local_host(host), "{:?}", host);
+        }
+        for host in ["192.168.1.10", "example.org", "localhost.example.org", "::2"] {
+            assert!(!is_local_host(host), "{:?}", host);
+        }
+    }
+
+    #[test]
+    fn rhost_check_skips_remote_hosts_only() {
+        let checks = SessionChecks { rhost: true, ssh: false, session: false, lid: false };
+        assert_eq!(checks.skip_reason(Some("10.0.0.5")).as_deref(), Some("remote login from 10.0.0.5"));
+        assert_eq!(checks.skip_reason(Some("localhost")), None);
+        assert_eq!(checks.skip_reason(None), None);
+
+        let off = SessionChecks::from_args(&args(&["no_rhost_check", "no_ssh_check", "no_session_check"]));
+        assert_eq!(off.skip_reason(Some("10.0.0.5")), None);
+    }
+
+    #[test]
+    fn lid_check_is_opt_in() {
+        let defaults = SessionChecks::from_args(&args(&["socket=/run/suplinux.sock"]));
+        assert!(defaults.rhost && defaults.ssh && defaults.session);
+        assert!(!defaults.lid);
+        assert!(SessionChecks::from_args(&args(&["lid_check"])).lid);
+    }
+
+    #[test]
+    fn parses_logind_session_files() {
+        let graphical = "# This is private data. Do not parse.\nUID=1000\nUSER=alice\nTYPE=wayland\nREMOTE=0\n";
+        assert_eq!(parse_session(graphical), (Some("wayland".to_string()), false));
+
+        let remote = "UID=1000\nTYPE=tty\nREMOTE=1\nREMOTE_HOST=10.0.0.5\n";
+        assert_eq!(parse_session(remote), (Some("tty".to_string()), true));
+
+        // Keys are matched whole, not by prefix
+        assert_eq!(parse_session("TYPEX=tty\nREMOTE_USER=1\n"), (None, false));
+        assert_eq!(parse_session(""), (None, false));
+    }
+
+    #[test]
+    fn session_reasons() {
+        assert_eq!(session_reason("3", Some("tty"), false).as_deref(), Some("session 3 is a text console"));
+        assert_eq!(session_reason("3", Some("x11"), true).as_deref(), Some("session 3 is remote"));
+        assert_eq!(session_reason("3", Some("wayland"), false), None);
+        assert_eq!(session_reason("3", None, false), None);
+    }
+
+    #[test]
+    fn parses_lid_state() {
+        assert!(lid_state_closed("state:      closed\n"));
+        assert!(!lid_state_closed("state:      open\n"));
+        assert!(!lid_state_closed(""));
+    }
+}
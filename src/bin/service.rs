@@ -1,25 +1,43 @@
 use sup_linux::{
-    camera::Camera,
-    config::Config,
-    detector::FaceDetector,
-    recognizer::{FaceRecognizer, cosine_similarity},
+    camera::{self, FrameSource},
+    config::{Config, FrameSourceKind, AuthConfig},
+    detector::{FaceDetector, FaceBox},
+    recognizer::{FaceRecognizer, Embedding},
+    quality::QualityMetrics,
+    auth::calculate_best_similarity,
+    LivenessDetector,
+    core::{AuthRecorder, AuthDecision, adaptive, enrollment::{self, EnrollmentSample}},
     error::Result,
+    error::FaceAuthError,
     protocol::{
-        Request, Response, AuthRequest, AuthResponse, EnrollRequest, EnrollResponse, 
-        EnhanceRequest, EnhanceResponse, StreamMessage, MSG_TYPE_RESPONSE, MSG_TYPE_STREAM
+        self, Request, Response, AuthRequest, AuthResponse, EnrollRequest, EnrollResponse, 
+        EnhanceRequest, EnhanceResponse, HelloRequest, HelloResponse, RotateKeyResponse, MigrateTemplatesResponse, StreamMessage, AuthProgress,
+        UnlockRequest, UnlockResponse, ResetAdaptiveRequest, ResetAdaptiveResponse,
+        ProfilesRequest, ProfilesResponse, ProfileInfo, DeleteProfileRequest, DeleteProfileResponse,
+        UserRequest, UsersResponse, UserInfo, UserInfoResponse, DeleteUserResponse,
+        StatusResponse, ServiceStatus, ModelStatus,
+        MSG_TYPE_REQUEST, MSG_TYPE_RESPONSE, MSG_TYPE_STREAM
     },
-    storage::UserStore,
+    signing,
+    storage::{UserStore, UserData, EnrollmentProfile, AdaptiveTemplate, is_valid_profile_name, LockoutStore, AuditLog, AuditRecord, AuditEvent, AuditOutcome, key_provider, encryption},
+    arbiter::CameraArbiter,
+    cancel::ClientWatch,
+    systemd,
+    stats::{ServiceStats, Stage, model_status},
     cli::ascii_preview::AsciiRenderer,
 };
 use clap::Parser;
 use std::os::unix::net::{UnixListener, UnixStream};
-use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::io::Write;
 use std::time::{Duration, SystemTime, Instant};
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::collections::VecDeque;
-use sha2::{Sha256, Digest};
+use std::sync::{Arc, Mutex, RwLock, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
 use anyhow::Context as _;
+use image::DynamicImage;
 
 #[derive(Parser, Debug)]
 #[command(name = "suplinux-service")]
@@ -36,6 +54,14 @@ struct Args {
     /// Data directory in dev mode
     #[arg(long, default_value = "./dev_data")]
     dev_data_dir: String,
+    
+    /// Config file to use instead of the mode's default, e.g. one with a replay camera
+    #[arg(long)]
+    config: Option<PathBuf>,
+    
+    /// Save every authentication attempt to <data dir>/recordings for `suplinux replay` (dev mode only)
+    #[arg(long)]
+    record_sessions: bool,
 }
 
 #[derive(Debug)]
@@ -56,6 +82,14 @@ struct AuthenticationState {
     face_detected_once: bool,            // Reset tracking
 }
 
+// Outcome of perform_authentication plus what the lockout policy needs
+struct AuthAttempt {
+    response: AuthResponse,
+    face_seen: bool,  // A face was in view, so a failure counts towards lockout
+    best_similarity: Option<f32>,  // For the audit log
+    recorder: Option<AuthRecorder>,  // Frames and results, with --record-sessions
+}
+
 impl AuthenticationState {
     fn new(buffer_size: usize) -> Self {
         Self {
@@ -99,7 +133,6 @@ fn get_username_from_uid(uid: u32) -> Result<String> {
 }
 
 fn get_peer_credentials(stream: &UnixStream) -> Result<PeerCredentials> {
-    use std::os::unix::io::AsRawFd;
     use std::mem;
     
     #[repr(C)]
@@ -133,6 +166,33 @@ fn get_peer_credentials(stream: &UnixStream) -> Result<PeerCredentials> {
     }
 }
 
+// Open the user store with the key provider selected in config
+fn open_user_store(config: &Config, data_dir: &Path) -> Result<UserStore> {
+    UserStore::new_with_paths(data_dir.join("users"), data_dir.join("enrollment"), &config.storage)
+}
+
+/// Load the secret used to sign authentication responses
+///
+/// In production the secret is created by install.sh as root:suplinux 0640 so
+/// that the PAM module (root) and the service can read it but users cannot.
+/// Dev mode keeps a per-checkout secret in the data directory instead.
+fn load_or_create_auth_secret(dev_mode: bool, data_dir: &Path) -> Result<Vec<u8>> {
+    if !dev_mode {
+        return signing::load_auth_secret(Path::new(signing::AUTH_SECRET_PATH)).map_err(|e| {
+            anyhow::anyhow!("{} - run install.sh or create it with: \
+                head -c 32 /dev/urandom > {path} && chown root:suplinux {path} && chmod 640 {path}",
+                e, path = signing::AUTH_SECRET_PATH).into()
+        });
+    }
+    
+    let path = data_dir.join("auth.secret");
+    if !path.exists() {
+        encryption::write_private_file(&path, &key_provider::generate_key())?;
+        tracing::info!("Generated dev auth secret at {:?}", path);
+    }
+    signing::load_auth_secret(&path)
+}
+
 fn main() -> Result<()> {
     // Parse command-line arguments
     let args = Args::parse();
@@ -144,6 +204,14 @@ fn main() -> Result<()> {
     
     tracing::info!("Starting SupLinux service (dev_mode: {})", args.dev);
     
+    // SIGHUP reloads the config instead of terminating us
+    unsafe { libc::signal(libc::SIGHUP, on_sighup as libc::sighandler_t) };
+    
+    // Recordings hold raw frames and templates - never on a production install
+    if args.record_sessions && !args.dev {
+        return Err(anyhow::anyhow!("--record-sessions is only available with --dev").into());
+    }
+    
     // Determine paths based on mode
     let (socket_path, data_dir, config_path) = if args.dev {
         (
@@ -159,61 +227,337 @@ fn main() -> Result<()> {
         )
     };
     
-    // Clean up old socket if exists
-    if Path::new(socket_path).exists() {
-        fs::remove_file(socket_path)?;
+    // Under suplinux.socket systemd owns the socket and queues connections
+    // while we start; otherwise bind it ourselves
+    let (listener, socket_activated) = match systemd::listen_fd().context("Failed to take over the socket from systemd")? {
+        Some(listener) => (listener, true),
+        None => (bind_socket(socket_path)?, false),
+    };
+    
+    tracing::info!("Listening on {}{}", socket_path, if socket_activated { " (socket activated)" } else { "" });
+    
+    // Initialize components (but NOT camera - we'll create it per request)
+    let config_file = args.config.clone().or_else(|| config_path.exists().then(|| config_path.clone()));
+    let config = load_config(config_file.as_deref())?;
+    let auth_secret = load_or_create_auth_secret(args.dev, &data_dir)?;
+    let recordings_dir = args.record_sessions.then(|| data_dir.join("recordings"));
+    if let Some(ref dir) = recordings_dir {
+        tracing::warn!("Recording authentication sessions to {}", dir.display());
     }
     
-    // Create socket directory
-    if let Some(parent) = Path::new(socket_path).parent() {
-        fs::create_dir_all(parent)?;
+    // Only initialize models once - they are reused until a reload changes them
+    let runtime = build_runtime(config, None)?;
+    if !runtime.liveness.is_enabled() {
+        tracing::warn!("Liveness checks are disabled - photos and screens will not be rejected");
     }
+    let worker_count = runtime.config.service.worker_threads;
+    let stats = ServiceStats::new(runtime.models.clone(), worker_count);
+    let audit = AuditLog::new(data_dir.join("audit"), runtime.config.audit.max_file_kb * 1024, runtime.config.audit.max_files);
+    let current = RwLock::new(Arc::new(runtime));
+    
+    // Only one request may use the camera at a time
+    let arbiter = CameraArbiter::new();
+    
+    // Hand connections to a fixed pool of workers so a long enrollment
+    // doesn't block sudo prompts or requests that never touch the camera
+    let (sender, receiver) = mpsc::channel::<UnixStream>();
+    let receiver = Mutex::new(receiver);
+    tracing::info!("Handling connections with {} workers", worker_count);
+    
+    let watchdog = systemd::watchdog_interval();
+    let poll_interval = watchdog.map_or(POLL_INTERVAL, |interval| interval.min(POLL_INTERVAL));
+    let activity = Activity::new();
+    
+    // The accept loop wakes up regularly to feed the watchdog and check for idleness
+    listener.set_nonblocking(true)?;
+    notify_systemd("READY=1\nSTATUS=Models loaded, waiting for requests");
+    
+    std::thread::scope(|scope| {
+        for _ in 0..worker_count {
+            scope.spawn(|| loop {
+                let stream = match receiver.lock().unwrap_or_else(|e| e.into_inner()).recv() {
+                    Ok(s) => s,
+                    Err(_) => break,  // Listener is gone
+                };
+                // The whole request runs with the config and models current when it arrived
+                let runtime = Arc::clone(&current.read().unwrap_or_else(|e| e.into_inner()));
+                if let Err(e) = handle_client(stream, &runtime.detector, &runtime.recognizer, &runtime.liveness, &runtime.config, &data_dir, recordings_dir.as_deref(), &auth_secret, &arbiter, &audit, &stats) {
+                    tracing::error!("Client error: {}", e);
+                    stats.record_error(format!("Client error: {}", e));
+                }
+                activity.end();
+            });
+        }
+        
+        let mut last_ping = Instant::now();
+        let result = loop {
+            match wait_for_connection(&listener, poll_interval) {
+                Ok(true) => match listener.accept() {
+                    Ok((stream, _)) => {
+                        // Workers rely on blocking reads with timeouts
+                        if let Err(e) = stream.set_nonblocking(false) {
+                            tracing::error!("Connection error: {}", e);
+                            continue;
+                        }
+                        activity.begin();
+                        if sender.send(stream).is_err() {
+                            break Ok(());
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Err(e) => {
+                        tracing::error!("Connection error: {}", e);
+                    }
+                },
+                Ok(false) => {}
+                Err(e) => break Err(e),
+            }
+            
+            if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+                reload(config_file.as_deref(), &current, &stats);
+            }
+            
+            if let Some(interval) = watchdog {
+                if last_ping.elapsed() >= interval {
+                    notify_systemd("WATCHDOG=1");
+                    last_ping = Instant::now();
+                }
+            }
+            
+            // Idle exit only makes sense when systemd will start us again
+            let idle_exit_secs = current.read().unwrap_or_else(|e| e.into_inner()).config.service.idle_exit_secs;
+            let idle_exit = (socket_activated && idle_exit_secs > 0).then(|| Duration::from_secs(idle_exit_secs));
+            if let (Some(limit), Some(idle)) = (idle_exit, activity.idle_for()) {
+                if idle >= limit {
+                    tracing::info!("No requests for {}s, exiting until the next connection", limit.as_secs());
+                    notify_systemd("STOPPING=1");
+                    break Ok(());
+                }
+            }
+        };
+        
+        // Lets the workers finish what they have and exit
+        drop(sender);
+        result
+    }).context("Failed to wait for connections")?;
     
-    // Create Unix socket
-    let listener = UnixListener::bind(socket_path)
-        .context("Failed to bind Unix socket")?;
+    Ok(())
+}
+
+// How often the accept loop wakes up when nothing happens
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Set by the SIGHUP handler, picked up by the accept loop
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sighup(_signal: libc::c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// Settings only read at startup, for the reload log
+const RESTART_ONLY: [&str; 3] = ["service.worker_threads", "audit.max_file_kb", "audit.max_files"];
+
+// Config and the models built from it. Each request runs against one
+// snapshot; a reload swaps in a new one as a whole.
+struct Runtime {
+    config: Config,
+    detector: FaceDetector,
+    recognizer: FaceRecognizer,
+    liveness: LivenessDetector,
+    models: Vec<ModelStatus>,
+}
+
+// The --config file, the mode's default file, or Config::load()'s search
+fn load_config(config_file: Option<&Path>) -> Result<Config> {
+    match config_file {
+        Some(path) => Config::load_from_path(path),
+        None => Config::load(),
+    }
+}
+
+// Build the models for `config`, reusing the ONNX sessions of `previous`
+// whose model file and session options are unchanged
+fn build_runtime(config: Config, previous: Option<&Runtime>) -> Result<Runtime> {
+    // Hashes pin down exactly which model files this service is running
+    let models = model_status(&config);
+    for model in &models {
+        if let Some(ref error) = model.error {
+            tracing::warn!("Could not hash {} model {}: {}", model.role, model.path.display(), error);
+        }
+    }
     
-    // Set socket permissions (allow all users to connect)
-    // Authorization is handled per-request in handle_enroll_request
-    std::process::Command::new("chmod")
-        .args(&["666", socket_path])
-        .status()?;
+    let reusable = |role: &str| previous.filter(|prev| {
+        prev.config.performance.optimization_level == config.performance.optimization_level
+            && same_model(&prev.models, &models, role)
+    });
+    let log_load = |role: &str| {
+        if let Some(model) = models.iter().find(|m| m.role == role) {
+            tracing::info!("Loading {} model {} (sha256 {})", role, model.path.display(),
+                model.sha256.as_deref().unwrap_or("unknown"));
+        }
+    };
     
-    tracing::info!("Listening on {}", socket_path);
+    let detector = match reusable("detector") {
+        Some(prev) => prev.detector.reconfigure(&config),
+        None => {
+            log_load("detector");
+            FaceDetector::new(&config)?
+        }
+    };
+    let recognizer = match reusable("recognizer") {
+        Some(prev) => prev.recognizer.reconfigure(&config),
+        None => {
+            log_load("recognizer");
+            FaceRecognizer::new(&config)?
+        }
+    };
+    // The liveness model is only loaded while liveness checks are enabled
+    let liveness = match reusable("liveness").filter(|prev| prev.config.liveness.enabled == config.liveness.enabled) {
+        Some(prev) => prev.liveness.reconfigure(&config),
+        None => {
+            if config.liveness.enabled {
+                log_load("liveness");
+            }
+            LivenessDetector::new(&config)?
+        }
+    };
     
-    // Initialize components (but NOT camera - we'll create it per request)
-    let config = if config_path.exists() {
-        Config::load_from_path(&config_path)?
-    } else {
-        Config::load()?
+    Ok(Runtime { config, detector, recognizer, liveness, models })
+}
+
+// Same file contents for `role` in both lists; a model that couldn't be hashed never counts as unchanged
+fn same_model(old: &[ModelStatus], new: &[ModelStatus], role: &str) -> bool {
+    let find = |models: &[ModelStatus]| models.iter()
+        .find(|m| m.role == role)
+        .map(|m| (m.path.clone(), m.sha256.clone()));
+    match (find(old), find(new)) {
+        (Some((_, None)), _) | (_, Some((_, None))) => false,
+        (old, new) => old == new,
+    }
+}
+
+// Re-read the config file and swap it in with its models. Requests already
+// running finish with what they started with; if anything fails, the
+// current config and models stay in place.
+fn reload(config_file: Option<&Path>, current: &RwLock<Arc<Runtime>>, stats: &ServiceStats) {
+    tracing::info!("Reloading configuration");
+    notify_systemd("RELOADING=1");
+    let previous = Arc::clone(&current.read().unwrap_or_else(|e| e.into_inner()));
+    
+    let result = load_config(config_file).and_then(|config| build_runtime(config, Some(&previous)));
+    let runtime = match result {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            tracing::error!("Reload failed, keeping the current configuration: {}", e);
+            stats.record_error(format!("Config reload failed: {}", e));
+            notify_systemd("READY=1\nSTATUS=Reload failed, running the previous configuration");
+            return;
+        }
     };
-    // Only initialize models once - they can be reused
-    let detector = FaceDetector::new(&config)?;
-    let recognizer = FaceRecognizer::new(&config)?;
     
-    // Handle connections
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                if let Err(e) = handle_client(stream, &detector, &recognizer, &config, &data_dir) {
-                    tracing::error!("Client error: {}", e);
-                }
-            }
-            Err(e) => {
-                tracing::error!("Connection error: {}", e);
-            }
+    let changes = previous.config.diff(&runtime.config);
+    for change in &changes {
+        tracing::info!("Config changed: {}", change);
+        if RESTART_ONLY.iter().any(|key| change.starts_with(&format!("{}:", key))) {
+            tracing::warn!("{} only takes effect after a restart", change.split(':').next().unwrap_or(change));
+        }
+    }
+    for model in &runtime.models {
+        if !same_model(&previous.models, &runtime.models, &model.role) {
+            tracing::info!("Model changed: {} is now {} (sha256 {})", model.role, model.path.display(),
+                model.sha256.as_deref().unwrap_or("unknown"));
         }
     }
+    if changes.is_empty() {
+        tracing::info!("Configuration unchanged");
+    }
     
-    Ok(())
+    stats.set_models(runtime.models.clone());
+    *current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(runtime);
+    notify_systemd("READY=1\nSTATUS=Configuration reloaded");
+}
+
+// Requests queued or in progress and when the last one finished, for idle exit
+struct Activity {
+    state: Mutex<(usize, Instant)>,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self { state: Mutex::new((0, Instant::now())) }
+    }
+    
+    fn begin(&self) {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).0 += 1;
+    }
+    
+    fn end(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.0 = state.0.saturating_sub(1);
+        state.1 = Instant::now();
+    }
+    
+    // None while any request is queued or running
+    fn idle_for(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        (state.0 == 0).then(|| state.1.elapsed())
+    }
+}
+
+// Bind the socket when not socket activated. A socket left by a crashed
+// service is replaced, one a running service still answers on is not.
+fn bind_socket(socket_path: &str) -> Result<UnixListener> {
+    let path = Path::new(socket_path);
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(anyhow::anyhow!("Another suplinux-service is already listening on {}", socket_path).into());
+        }
+        fs::remove_file(path)?;
+    }
+    
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    
+    // Create the socket connectable by all users right away rather than
+    // fixing permissions after bind, when early clients would be refused.
+    // Authorization is handled per request from the peer credentials.
+    let old_mask = unsafe { libc::umask(0o111) };
+    let bound = UnixListener::bind(path);
+    unsafe { libc::umask(old_mask) };
+    Ok(bound.context("Failed to bind Unix socket")?)
+}
+
+// Wait up to `timeout` for a connection; false on timeout or a signal
+fn wait_for_connection(listener: &UnixListener, timeout: Duration) -> std::io::Result<bool> {
+    let mut fd = libc::pollfd { fd: listener.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    let ready = unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) };
+    if ready < 0 {
+        let err = std::io::Error::last_os_error();
+        return if err.kind() == std::io::ErrorKind::Interrupted { Ok(false) } else { Err(err) };
+    }
+    Ok(ready > 0)
+}
+
+// Readiness and watchdog are best effort - outside systemd there is nobody to tell
+fn notify_systemd(state: &str) {
+    if let Err(e) = systemd::notify(state) {
+        tracing::warn!("Failed to notify systemd ({}): {}", state.lines().next().unwrap_or(state), e);
+    }
 }
 
 fn handle_client(
     mut stream: UnixStream,
     detector: &FaceDetector,
     recognizer: &FaceRecognizer,
+    liveness: &LivenessDetector,
     config: &Config,
     data_dir: &Path,
+    recordings_dir: Option<&Path>,
+    auth_secret: &[u8],
+    arbiter: &CameraArbiter,
+    audit: &AuditLog,
+    stats: &ServiceStats,
 ) -> Result<()> {
     // Get peer credentials to identify who's connecting
     let peer_cred = get_peer_credentials(&stream)?;
@@ -223,77 +567,1102 @@ fn handle_client(
     stream.set_read_timeout(Some(Duration::from_secs(10)))?;
     stream.set_write_timeout(Some(Duration::from_secs(5)))?;
     
-    // Read request length
-    let mut len_buf = [0u8; 4];
-    stream.read_exact(&mut len_buf)?;
-    let request_len = u32::from_le_bytes(len_buf) as usize;
+    // Answer Hello until the client sends its actual request
+    let request = loop {
+        let request = match read_request(&mut stream, &peer_cred)? {
+            Some(r) => r,
+            None => return Ok(()),  // Client was told what went wrong
+        };
+        match request {
+            Request::Hello(hello) => {
+                let response = handle_hello(&hello, &peer_cred);
+                send_response(&mut stream, &response)?;
+            }
+            other => break other,
+        }
+    };
     
-    // Sanity check
-    if request_len > 1024 * 1024 {  // 1MB max
-        return Err(anyhow::anyhow!("Request too large: {} bytes", request_len).into());
+    // Callers asking about someone else, services with face auth disabled and
+    // locked-out users get an answer straight away, without the camera
+    if let Request::Authenticate(ref auth_req) = request {
+        let early = check_caller(auth_req, &peer_cred, auth_secret)
+            .or_else(|| check_policy(auth_req, config, auth_secret))
+            .map(|response| (response, AuditOutcome::Denied))
+            .or_else(|| check_lockout(auth_req, data_dir, auth_secret)
+                .map(|response| (response, AuditOutcome::LockedOut)));
+        if let Some((response, outcome)) = early {
+            let mut record = audit_record(AuditEvent::Authenticate, &peer_cred, &auth_req.username);
+            set_auth_caller(&mut record, auth_req);
+            record.outcome = match response {
+                Response::Auth(_) => outcome,
+                _ => AuditOutcome::Error,
+            };
+            record.message = response_message(&response);
+            write_audit(audit, config, record);
+            
+            send_response(&mut stream, &response)?;
+            return Ok(());
+        }
     }
     
-    // Read request
-    let mut request_buf = vec![0u8; request_len];
-    stream.read_exact(&mut request_buf)?;
+    // Profile names become directory names
+    let profile = match request {
+        Request::Enroll(ref r) => Some(&r.profile),
+        Request::Enhance(ref r) => Some(&r.profile),
+        Request::DeleteProfile(ref r) => Some(&r.profile),
+        _ => None,
+    };
+    if let Some(profile) = profile.filter(|p| !is_valid_profile_name(p)) {
+        let message = format!("Invalid profile name '{}': use letters, digits, '-' and '_'", profile);
+        send_response(&mut stream, &Response::Error(message))?;
+        return Ok(());
+    }
     
-    // Deserialize request
-    let request: Request = bincode::deserialize(&request_buf)
-        .map_err(|e| anyhow::anyhow!("Failed to deserialize request: {}", e))?;
+    // Camera requests queue briefly behind the current holder, then give up
+    let camera_purpose = match request {
+        Request::Authenticate(ref r) => Some((AuditEvent::Authenticate, r.username.clone(), "authentication")),
+        Request::Enroll(ref r) => Some((AuditEvent::Enroll, r.username.clone(), "enrollment")),
+        Request::Enhance(ref r) => Some((AuditEvent::Enhance, r.username.clone(), "enhancement")),
+        Request::RotateKey | Request::Hello(_) | Request::Cancel | Request::Unlock(_)
+            | Request::ResetAdaptive(_) | Request::ListProfiles(_) | Request::DeleteProfile(_)
+            | Request::ListUsers | Request::GetUserInfo(_) | Request::DeleteUser(_) | Request::Status
+            | Request::MigrateTemplates => None,
+    };
+    let _camera_lease = match camera_purpose {
+        Some((event, username, action)) => {
+            let purpose = format!("{} for {}", action, username);
+            let wait = Duration::from_millis(config.service.camera_wait_ms);
+            match arbiter.acquire(&purpose, wait) {
+                Ok(lease) => Some(lease),
+                Err(busy) => {
+                    tracing::info!("Rejecting {} from UID {}: {}", purpose, peer_cred.uid, busy);
+                    let mut record = audit_record(event, &peer_cred, &username);
+                    if let Request::Authenticate(ref r) = request {
+                        set_auth_caller(&mut record, r);
+                    }
+                    record.outcome = AuditOutcome::CameraBusy;
+                    record.message = busy.to_string();
+                    write_audit(audit, config, record);
+                    
+                    send_response(&mut stream, &Response::CameraBusy(busy.to_string()))?;
+                    return Ok(());
+                }
+            }
+        }
+        None => None,
+    };
+    
+    // Long-running requests poll this to notice hang-up or Cancel
+    let mut watch = ClientWatch::new(&stream)?;
     
     // Process request based on type - enrollment/enhance may stream updates
     match request {
         Request::Authenticate(auth_req) => {
-            tracing::info!("Processing auth request for user: {}", auth_req.username);
-            let response = handle_auth_request(detector, recognizer, auth_req, config, data_dir);
-            
-            // Send response (no streaming for auth)
-            let response_data = bincode::serialize(&response)
-                .map_err(|e| anyhow::anyhow!("Failed to serialize response: {}", e))?;
-            let response_len = (response_data.len() as u32).to_le_bytes();
+            tracing::info!("Processing auth request for user: {} (service {}, tty {}, rhost {})", auth_req.username,
+                auth_req.pam_service.as_deref().unwrap_or("-"),
+                auth_req.pam_tty.as_deref().unwrap_or("-"),
+                auth_req.pam_rhost.as_deref().unwrap_or("-"));
+            let stream_progress = auth_req.stream_progress;
+            let response = {
+                let mut progress = |step: AuthProgress| {
+                    if stream_progress {
+                        // Best effort - a client that went away is noticed by the watch
+                        let update = StreamMessage::AuthProgress { step };
+                        let _ = send_stream_message(&mut stream, &update);
+                    }
+                };
+                handle_auth_request(detector, recognizer, liveness, auth_req, &peer_cred, config, data_dir, recordings_dir, auth_secret, audit, stats, &mut progress, &mut watch)
+            };
             
-            stream.write_all(&response_len)?;
-            stream.write_all(&response_data)?;
-            stream.flush()?;
+            if !watch.is_hung_up() {
+                if stream_progress {
+                    send_stream_message(&mut stream, &StreamMessage::Complete)?;
+                }
+                send_response(&mut stream, &response)?;
+            }
         }
         Request::Enroll(enroll_req) => {
             tracing::info!("Processing enrollment request for user: {}", enroll_req.username);
-            handle_enroll_request_with_stream(&mut stream, detector, recognizer, enroll_req, &peer_cred, config, data_dir)?;
+            let mut record = audit_record(AuditEvent::Enroll, &peer_cred, &enroll_req.username);
+            record.camera_device = Some(camera_label(config));
+            let started = Instant::now();
+            
+            let response = handle_enroll_request_with_stream(&mut stream, detector, recognizer, enroll_req, &peer_cred, config, data_dir, stats, &mut watch)?;
+            
+            record.duration_ms = started.elapsed().as_millis() as u64;
+            record.outcome = match response {
+                Response::Enroll(ref r) if r.success => AuditOutcome::Success,
+                _ if watch.cancelled().is_some() => AuditOutcome::Cancelled,
+                _ => AuditOutcome::Failure,
+            };
+            record.message = response_message(&response);
+            stats.record_enrollment();
+            if record.outcome == AuditOutcome::Failure {
+                stats.record_error(format!("Enrollment for {}: {}", record.username, record.message));
+            }
+            write_audit(audit, config, record);
         }
         Request::Enhance(enhance_req) => {
             tracing::info!("Processing enhance request for user: {}", enhance_req.username);
-            handle_enhance_request_with_stream(&mut stream, detector, recognizer, enhance_req, &peer_cred, config, data_dir)?;
+            let mut record = audit_record(AuditEvent::Enhance, &peer_cred, &enhance_req.username);
+            record.camera_device = Some(camera_label(config));
+            let started = Instant::now();
+            
+            let response = handle_enhance_request_with_stream(&mut stream, detector, recognizer, enhance_req, &peer_cred, config, data_dir, stats, &mut watch)?;
+            
+            record.duration_ms = started.elapsed().as_millis() as u64;
+            record.outcome = match response {
+                Response::Enhance(ref r) if r.success => AuditOutcome::Success,
+                _ if watch.cancelled().is_some() => AuditOutcome::Cancelled,
+                _ => AuditOutcome::Failure,
+            };
+            record.message = response_message(&response);
+            stats.record_enhancement();
+            if record.outcome == AuditOutcome::Failure {
+                stats.record_error(format!("Enhancement for {}: {}", record.username, record.message));
+            }
+            write_audit(audit, config, record);
+        }
+        Request::RotateKey => {
+            tracing::info!("Processing key rotation request");
+            let response = handle_rotate_key_request(&peer_cred, config, data_dir);
+            send_response(&mut stream, &response)?;
+        }
+        Request::Unlock(unlock_req) => {
+            tracing::info!("Processing unlock request for user: {}", unlock_req.username);
+            let response = handle_unlock_request(&unlock_req, &peer_cred, data_dir);
+            
+            let mut record = audit_record(AuditEvent::Unlock, &peer_cred, &unlock_req.username);
+            record.outcome = match response {
+                Response::Unlock(ref r) if r.success => AuditOutcome::Success,
+                _ if peer_cred.uid != 0 => AuditOutcome::Denied,
+                _ => AuditOutcome::Error,
+            };
+            record.message = response_message(&response);
+            write_audit(audit, config, record);
+            
+            send_response(&mut stream, &response)?;
+        }
+        Request::ResetAdaptive(reset_req) => {
+            tracing::info!("Processing adaptive template reset for user: {}", reset_req.username);
+            let response = handle_reset_adaptive_request(&reset_req, &peer_cred, config, data_dir);
+            
+            let mut record = audit_record(AuditEvent::ResetAdaptive, &peer_cred, &reset_req.username);
+            record.outcome = match response {
+                Response::ResetAdaptive(ref r) if r.success => AuditOutcome::Success,
+                Response::ResetAdaptive(ref r) if r.message.starts_with("Permission denied") => AuditOutcome::Denied,
+                _ => AuditOutcome::Error,
+            };
+            record.message = response_message(&response);
+            write_audit(audit, config, record);
+            
+            send_response(&mut stream, &response)?;
+        }
+        Request::ListProfiles(profiles_req) => {
+            tracing::info!("Processing profile list request for user: {}", profiles_req.username);
+            let response = handle_list_profiles_request(&profiles_req, &peer_cred, config, data_dir);
+            send_response(&mut stream, &response)?;
+        }
+        Request::DeleteProfile(delete_req) => {
+            tracing::info!("Processing profile deletion of '{}' for user: {}", delete_req.profile, delete_req.username);
+            let response = handle_delete_profile_request(&delete_req, &peer_cred, config, data_dir);
+            
+            let mut record = audit_record(AuditEvent::DeleteProfile, &peer_cred, &delete_req.username);
+            record.outcome = match response {
+                Response::DeleteProfile(ref r) if r.success => AuditOutcome::Success,
+                Response::DeleteProfile(ref r) if r.message.starts_with("Permission denied") => AuditOutcome::Denied,
+                _ => AuditOutcome::Error,
+            };
+            record.message = response_message(&response);
+            write_audit(audit, config, record);
+            
+            send_response(&mut stream, &response)?;
+        }
+        Request::ListUsers => {
+            tracing::info!("Processing user list request from UID {}", peer_cred.uid);
+            let response = handle_list_users_request(&peer_cred, config, data_dir);
+            send_response(&mut stream, &response)?;
+        }
+        Request::GetUserInfo(info_req) => {
+            tracing::info!("Processing user info request for user: {}", info_req.username);
+            let response = handle_user_info_request(&info_req, &peer_cred, config, data_dir);
+            send_response(&mut stream, &response)?;
+        }
+        Request::DeleteUser(delete_req) => {
+            tracing::info!("Processing deletion of user: {}", delete_req.username);
+            let response = handle_delete_user_request(&delete_req, &peer_cred, config, data_dir, recordings_dir);
+            
+            let mut record = audit_record(AuditEvent::DeleteUser, &peer_cred, &delete_req.username);
+            record.outcome = match response {
+                Response::DeleteUser(ref r) if r.success => AuditOutcome::Success,
+                Response::DeleteUser(ref r) if r.message.starts_with("Permission denied") => AuditOutcome::Denied,
+                _ => AuditOutcome::Error,
+            };
+            record.message = response_message(&response);
+            write_audit(audit, config, record);
+            
+            send_response(&mut stream, &response)?;
+        }
+        Request::Status => {
+            tracing::debug!("Processing status request from UID {}", peer_cred.uid);
+            let response = handle_status_request(&peer_cred, config, liveness, stats);
+            send_response(&mut stream, &response)?;
+        }
+        Request::MigrateTemplates => {
+            tracing::info!("Processing template migration request");
+            let response = handle_migrate_templates_request(&peer_cred, config, data_dir);
+            send_response(&mut stream, &response)?;
+        }
+        Request::Hello(_) => unreachable!("Hello is handled during the handshake"),
+        Request::Cancel => {
+            tracing::debug!("Cancel from UID {} with no request in progress", peer_cred.uid);
+        }
+    }
+    
+    Ok(())
+}
+
+// Where an authentication request came from, as reported by the PAM module
+fn set_auth_caller(record: &mut AuditRecord, request: &AuthRequest) {
+    record.pam_service = request.pam_service.clone();
+    record.pam_tty = request.pam_tty.clone();
+    record.pam_rhost = request.pam_rhost.clone();
+}
+
+// Start an audit record with the caller's identity; the caller fills in the rest
+fn audit_record(event: AuditEvent, peer_cred: &PeerCredentials, username: &str) -> AuditRecord {
+    AuditRecord {
+        timestamp: chrono::Utc::now(),
+        event,
+        uid: peer_cred.uid,
+        pid: peer_cred.pid,
+        pam_service: None,
+        pam_tty: None,
+        pam_rhost: None,
+        username: username.to_string(),
+        outcome: AuditOutcome::Error,
+        best_similarity: None,
+        attempts: 0,
+        liveness_failures: 0,
+        duration_ms: 0,
+        camera_device: None,
+        message: String::new(),
+    }
+}
+
+fn write_audit(audit: &AuditLog, config: &Config, record: AuditRecord) {
+    if !config.audit.enabled {
+        return;
+    }
+    if let Err(e) = audit.append(&record) {
+        tracing::error!("Failed to write audit record: {}", e);
+    }
+}
+
+// Human-readable summary of a response for the audit log
+fn response_message(response: &Response) -> String {
+    match response {
+        Response::Auth(r) => r.message.clone(),
+        Response::Enroll(r) => r.message.clone(),
+        Response::Enhance(r) => r.message.clone(),
+        Response::Unlock(r) => r.message.clone(),
+        Response::ResetAdaptive(r) => r.message.clone(),
+        Response::Profiles(r) => r.message.clone(),
+        Response::DeleteProfile(r) => r.message.clone(),
+        Response::Users(r) => r.message.clone(),
+        Response::UserInfo(r) => r.message.clone(),
+        Response::DeleteUser(r) => r.message.clone(),
+        Response::Status(r) => r.message.clone(),
+        Response::RotateKey(r) => r.message.clone(),
+        Response::MigrateTemplates(r) => r.message.clone(),
+        Response::Error(msg) | Response::CameraBusy(msg) => msg.clone(),
+        Response::Hello(_) => String::new(),
+    }
+}
+
+// Camera as configured, for records written before a device is opened
+fn camera_label(config: &Config) -> String {
+    if let (FrameSourceKind::Replay, Some(path)) = (config.camera.source, &config.camera.replay_path) {
+        return format!("replay:{}", path.display());
+    }
+    match config.camera.device_index {
+        999 => "auto-detect".to_string(),
+        index => format!("/dev/video{}", index),
+    }
+}
+
+/// Read and decode the next request, answering protocol mismatches with a
+/// `Response::Error` the client can still understand. Returns None if the
+/// connection should be closed.
+fn read_request(stream: &mut UnixStream, peer_cred: &PeerCredentials) -> Result<Option<Request>> {
+    let frame = match protocol::read_frame(stream) {
+        Ok(f) => f,
+        Err(FaceAuthError::Protocol(msg)) => {
+            // Pre-framing clients send a bare length prefix - reply in their format
+            tracing::warn!("Rejecting client UID {}: {}", peer_cred.uid, msg);
+            send_legacy_error(stream, &format!(
+                "Protocol version mismatch: this client predates protocol versioning, \
+                 service speaks v{}. Upgrade pam_suplinux.so and suplinux to match suplinux-service.",
+                protocol::PROTOCOL_VERSION
+            ))?;
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    };
+    
+    if !protocol::is_supported_version(frame.version) {
+        let error = protocol::version_mismatch(frame.version, "rejected by suplinux-service");
+        tracing::warn!("Rejecting client UID {}: {}", peer_cred.uid, error);
+        send_response(stream, &Response::Error(error.to_string()))?;
+        return Ok(None);
+    }
+    
+    if frame.msg_type != MSG_TYPE_REQUEST {
+        send_response(stream, &Response::Error(format!("Expected a request, got message type {}", frame.msg_type)))?;
+        return Ok(None);
+    }
+    
+    match frame.decode::<Request>() {
+        Ok(request) => Ok(Some(request)),
+        Err(e) => {
+            tracing::warn!("Malformed request from UID {}: {}", peer_cred.uid, e);
+            send_response(stream, &Response::Error(format!("Malformed request: {}", e)))?;
+            Ok(None)
+        }
+    }
+}
+
+fn handle_hello(hello: &HelloRequest, peer_cred: &PeerCredentials) -> Response {
+    let features = hello.features & protocol::SUPPORTED_FEATURES;
+    tracing::info!("Hello from {} (UID {}, protocol v{}, features {:#x}, common {:#x})",
+        hello.client, peer_cred.uid, hello.protocol_version, hello.features, features);
+    
+    Response::Hello(HelloResponse {
+        protocol_version: protocol::PROTOCOL_VERSION,
+        features,
+        service_version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
+
+fn handle_auth_request(
+    detector: &FaceDetector,
+    recognizer: &FaceRecognizer,
+    liveness: &LivenessDetector,
+    request: AuthRequest,
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    data_dir: &Path,
+    recordings_dir: Option<&Path>,
+    auth_secret: &[u8],
+    audit: &AuditLog,
+    stats: &ServiceStats,
+    progress: &mut dyn FnMut(AuthProgress),
+    watch: &mut ClientWatch,
+) -> Response {
+    let mut record = audit_record(AuditEvent::Authenticate, peer_cred, &request.username);
+    set_auth_caller(&mut record, &request);
+    let started = Instant::now();
+    
+    let response = authenticate_and_update_lockout(detector, recognizer, liveness, &request, peer_cred, config, data_dir, recordings_dir, auth_secret, stats, progress, watch, &mut record);
+    
+    stats.record_stage(Stage::Authentication, started.elapsed());
+    stats.record_authentication(matches!(response, Response::Auth(ref r) if r.success));
+    if let Response::Error(ref msg) = response {
+        stats.record_error(format!("Authentication for {}: {}", request.username, msg));
+    }
+    
+    record.duration_ms = started.elapsed().as_millis() as u64;
+    record.outcome = match response {
+        Response::Auth(ref r) if r.success => AuditOutcome::Success,
+        Response::Auth(ref r) if r.locked_until.is_some() => AuditOutcome::LockedOut,
+        Response::Auth(_) if watch.cancelled().is_some() => AuditOutcome::Cancelled,
+        Response::Auth(_) => AuditOutcome::Failure,
+        _ => AuditOutcome::Error,
+    };
+    record.message = response_message(&response);
+    write_audit(audit, config, record);
+    
+    response
+}
+
+fn authenticate_and_update_lockout(
+    detector: &FaceDetector,
+    recognizer: &FaceRecognizer,
+    liveness: &LivenessDetector,
+    request: &AuthRequest,
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    data_dir: &Path,
+    recordings_dir: Option<&Path>,
+    auth_secret: &[u8],
+    stats: &ServiceStats,
+    progress: &mut dyn FnMut(AuthProgress),
+    watch: &mut ClientWatch,
+    record: &mut AuditRecord,
+) -> Response {
+    // [policy.<service>] overrides [auth] for this request only
+    let service = request.pam_service.as_deref();
+    let policy_config = config.policy_for(service).map(|policy| config.with_policy(policy));
+    if policy_config.is_some() {
+        tracing::info!("Applying [policy.{}] to auth for {}", service.unwrap_or_default(), request.username);
+    }
+    // Learning stays anchored to the strictest threshold whatever the policy
+    let learning_threshold = config.adaptive_threshold();
+    let config = policy_config.as_ref().unwrap_or(config);
+    
+    // Create camera just for this authentication
+    let mut camera = match stats.time(Stage::CameraOpen, || camera::open(config)) {
+        Ok(c) => c,
+        Err(e) => {
+            return Response::Error(format!("Failed to initialize camera: {}", e));
+        }
+    };
+    record.camera_device = Some(camera.label());
+    stats.record_camera(camera.as_ref());
+    
+    let result = perform_authentication(camera.as_mut(), detector, recognizer, liveness, &request.username, &request.challenge, config, learning_threshold, data_dir, recordings_dir.is_some(), stats, progress, watch);
+    
+    // Camera will be dropped here, releasing the device
+    drop(camera);
+    
+    match result {
+        Ok(attempt) => {
+            record.best_similarity = attempt.best_similarity;
+            record.attempts = attempt.response.attempts;
+            record.liveness_failures = attempt.response.liveness_failures;
+            
+            let mut auth_response = attempt.response;
+            
+            let lockouts = LockoutStore::new(data_dir.join("lockout"));
+            let lockout_result = update_lockout(&lockouts, peer_cred, &request.username,
+                auth_response.success, attempt.face_seen, &config.auth);
+            match lockout_result {
+                Ok(Some(duration)) => {
+                    auth_response.message = lockout_message(duration);
+                    auth_response.locked_until = Some(SystemTime::now() + duration);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!("Failed to update lockout state for {}: {}", request.username, e),
+            }
+            
+            if let (Some(recorder), Some(dir)) = (attempt.recorder, recordings_dir) {
+                save_recording(recorder, &auth_response, &request.username, dir);
+            }
+            
+            Response::Auth(sign_auth_response(auth_response, &request.username, auth_secret))
+        }
+        Err(e) => {
+            tracing::error!("Auth failed: {}", e);
+            Response::Error(format!("Authentication failed: {}", e))
+        }
+    }
+}
+
+// Count the attempt towards `username`'s lockout. Only attempts where a face
+// was actually shown count as failures - nobody in front of the camera is not
+// a brute-force attempt - and only the account's owner or root can affect it,
+// so nobody can lock another user out by showing the camera the wrong face.
+fn update_lockout(
+    lockouts: &LockoutStore,
+    peer_cred: &PeerCredentials,
+    username: &str,
+    success: bool,
+    face_seen: bool,
+    auth: &AuthConfig,
+) -> Result<Option<Duration>> {
+    if authorize_for_user(peer_cred, username, "authenticate as").is_err() {
+        return Ok(None);
+    }
+    if success {
+        lockouts.record_success(username).map(|_| None)
+    } else if face_seen {
+        lockouts.record_failure(username, auth)
+    } else {
+        Ok(None)
+    }
+}
+
+// Write a --record-sessions archive; failures only cost the recording
+fn save_recording(recorder: AuthRecorder, response: &AuthResponse, username: &str, dir: &Path) {
+    let recording = recorder.finish(AuthDecision {
+        success: response.success,
+        message: response.message.clone(),
+        attempts: response.attempts,
+        liveness_failures: response.liveness_failures,
+    });
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+    let path = dir.join(format!("{}_{}.suprec", username, timestamp));
+    match recording.save(&path) {
+        Ok(()) => tracing::info!("Recorded authentication session to {}", path.display()),
+        Err(e) => tracing::error!("Failed to save session recording {}: {}", path.display(), e),
+    }
+}
+
+// Answer without touching the camera if the user is locked out
+fn check_lockout(request: &AuthRequest, data_dir: &Path, auth_secret: &[u8]) -> Option<Response> {
+    let lockouts = LockoutStore::new(data_dir.join("lockout"));
+    let remaining = match lockouts.check(&request.username) {
+        Ok(remaining) => remaining?,
+        Err(e) => {
+            // Fail closed - a record we can't read might be hiding a lockout
+            tracing::error!("Failed to read lockout state for {}: {}", request.username, e);
+            return Some(Response::Error(format!("Failed to read lockout state: {}", e)));
+        }
+    };
+    
+    tracing::info!("Rejecting auth for {}: locked out for another {}s", request.username, remaining.as_secs());
+    let response = AuthResponse {
+        success: false,
+        message: lockout_message(remaining),
+        attempts: 0,
+        challenge: request.challenge.clone(),
+        signature: vec![],
+        timestamp: SystemTime::now(),
+        locked_until: Some(SystemTime::now() + remaining),
+        liveness_failures: 0,
+        not_applicable: false,
+    };
+    Some(Response::Auth(sign_auth_response(response, &request.username, auth_secret)))
+}
+
+// Turn away unprivileged callers authenticating as someone else. PAM stacks
+// that switch users (sudo, su, login managers) run as root; screen lockers
+// run as the user they unlock.
+fn check_caller(request: &AuthRequest, peer_cred: &PeerCredentials, auth_secret: &[u8]) -> Option<Response> {
+    let message = authorize_for_user(peer_cred, &request.username, "authenticate as").err()?;
+    
+    let response = AuthResponse {
+        success: false,
+        message,
+        attempts: 0,
+        challenge: request.challenge.clone(),
+        signature: vec![],
+        timestamp: SystemTime::now(),
+        locked_until: None,
+        liveness_failures: 0,
+        not_applicable: false,
+    };
+    Some(Response::Auth(sign_auth_response(response, &request.username, auth_secret)))
+}
+
+// Answer without touching the camera if [policy.<service>] turns face auth off.
+// Not a failed attempt: it doesn't count towards a lockout, and the PAM module
+// steps aside (PAM_IGNORE) instead of failing.
+fn check_policy(request: &AuthRequest, config: &Config, auth_secret: &[u8]) -> Option<Response> {
+    let service = request.pam_service.as_deref()?;
+    if config.policy_for(Some(service))?.enabled {
+        return None;
+    }
+    
+    tracing::info!("Rejecting auth for {} from {}: face authentication disabled by policy", request.username, service);
+    let response = AuthResponse {
+        success: false,
+        message: format!("Face authentication is disabled for {}", service),
+        attempts: 0,
+        challenge: request.challenge.clone(),
+        signature: vec![],
+        timestamp: SystemTime::now(),
+        locked_until: None,
+        liveness_failures: 0,
+        not_applicable: true,
+    };
+    Some(Response::Auth(sign_auth_response(response, &request.username, auth_secret)))
+}
+
+fn lockout_message(remaining: Duration) -> String {
+    format!("Too many failed attempts - face authentication locked for {}s", remaining.as_secs().max(1))
+}
+
+// Sign so the PAM module can tell this reply came from us and not
+// from another process that managed to bind the socket
+fn sign_auth_response(mut response: AuthResponse, username: &str, auth_secret: &[u8]) -> AuthResponse {
+    response.signature = signing::sign_auth_response(auth_secret, username, &response);
+    response
+}
+
+fn handle_unlock_request(
+    request: &UnlockRequest,
+    peer_cred: &PeerCredentials,
+    data_dir: &Path,
+) -> Response {
+    // Only root may lift a lockout
+    if peer_cred.uid != 0 {
+        tracing::warn!("UID {} attempted to unlock {}", peer_cred.uid, request.username);
+        return Response::Unlock(UnlockResponse {
+            success: false,
+            message: "Permission denied: unlocking requires root".to_string(),
+            was_locked: false,
+        });
+    }
+    
+    let lockouts = LockoutStore::new(data_dir.join("lockout"));
+    match lockouts.clear(&request.username) {
+        Ok(was_locked) => {
+            tracing::info!("Cleared lockout state for {} (was locked: {})", request.username, was_locked);
+            Response::Unlock(UnlockResponse {
+                success: true,
+                message: if was_locked {
+                    format!("Unlocked {}", request.username)
+                } else {
+                    format!("{} was not locked out; failure count reset", request.username)
+                },
+                was_locked,
+            })
+        }
+        Err(e) => Response::Unlock(UnlockResponse {
+            success: false,
+            message: format!("Failed to clear lockout: {}", e),
+            was_locked: false,
+        }),
+    }
+}
+
+// Root may manage any user, everyone else only themselves
+fn authorize_for_user(peer_cred: &PeerCredentials, username: &str, action: &str) -> std::result::Result<(), String> {
+    if peer_cred.uid == 0 {
+        return Ok(());
+    }
+    match get_username_from_uid(peer_cred.uid) {
+        Ok(req_user) if req_user == username => Ok(()),
+        Ok(req_user) => {
+            tracing::warn!("User {} (UID {}) attempted to {} {}", req_user, peer_cred.uid, action, username);
+            Err(format!("Permission denied: You can only {} yourself", action))
+        }
+        Err(_) => {
+            tracing::warn!("Could not determine username for UID {}", peer_cred.uid);
+            Err("Failed to verify user identity".to_string())
+        }
+    }
+}
+
+fn handle_list_profiles_request(
+    request: &ProfilesRequest,
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    data_dir: &Path,
+) -> Response {
+    let failure = |message: String| Response::Profiles(ProfilesResponse {
+        success: false,
+        message,
+        profiles: Vec::new(),
+    });
+    
+    if let Err(message) = authorize_for_user(peer_cred, &request.username, "list profiles of") {
+        return failure(message);
+    }
+    
+    let store = match open_user_store(config, data_dir) {
+        Ok(s) => s,
+        Err(e) => return failure(format!("Failed to initialize storage: {}", e)),
+    };
+    let user_data = match store.get_user(&request.username) {
+        Ok(data) => data,
+        Err(e) => return failure(format!("Failed to load {}: {}", request.username, e)),
+    };
+    
+    let profiles: Vec<ProfileInfo> = user_data.profiles.iter().map(profile_info).collect();
+    Response::Profiles(ProfilesResponse {
+        success: true,
+        message: format!("{} has {} profiles", request.username, profiles.len()),
+        profiles,
+    })
+}
+
+fn profile_info(profile: &EnrollmentProfile) -> ProfileInfo {
+    ProfileInfo {
+        name: profile.name.clone(),
+        camera: profile.camera.clone(),
+        created: profile.created,
+        embeddings: profile.embeddings.len(),
+    }
+}
+
+fn handle_list_users_request(
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    data_dir: &Path,
+) -> Response {
+    let failure = |message: String| Response::Users(UsersResponse {
+        success: false,
+        message,
+        users: Vec::new(),
+    });
+    
+    let store = match open_user_store(config, data_dir) {
+        Ok(s) => s,
+        Err(e) => return failure(format!("Failed to initialize storage: {}", e)),
+    };
+    let mut users = match store.list_users() {
+        Ok(users) => users,
+        Err(e) => return failure(format!("Failed to list users: {}", e)),
+    };
+    
+    // Who else is enrolled is only root's business
+    if peer_cred.uid != 0 {
+        match get_username_from_uid(peer_cred.uid) {
+            Ok(req_user) => users.retain(|u| *u == req_user),
+            Err(_) => return failure("Failed to verify user identity".to_string()),
+        }
+    }
+    
+    Response::Users(UsersResponse {
+        success: true,
+        message: format!("{} enrolled users", users.len()),
+        users,
+    })
+}
+
+fn handle_user_info_request(
+    request: &UserRequest,
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    data_dir: &Path,
+) -> Response {
+    let failure = |message: String| Response::UserInfo(UserInfoResponse {
+        success: false,
+        message,
+        info: None,
+    });
+    
+    if let Err(message) = authorize_for_user(peer_cred, &request.username, "inspect enrollment of") {
+        return failure(message);
+    }
+    
+    let store = match open_user_store(config, data_dir) {
+        Ok(s) => s,
+        Err(e) => return failure(format!("Failed to initialize storage: {}", e)),
+    };
+    let user_data = match store.get_user(&request.username) {
+        Ok(data) => data,
+        Err(e) => return failure(format!("Failed to load {}: {}", request.username, e)),
+    };
+    
+    // Extras are best effort; the templates themselves loaded fine
+    let adaptive = store.get_adaptive(&request.username).unwrap_or_else(|e| {
+        tracing::warn!("Failed to load adaptive templates for {}: {}", request.username, e);
+        Vec::new()
+    });
+    let lockout = LockoutStore::new(data_dir.join("lockout")).get(&request.username).unwrap_or_default();
+    let enrollment_images = store.get_enrollment_images_dir(&request.username)
+        .map(|dir| count_files(&dir))
+        .unwrap_or(0);
+    
+    let info = UserInfo {
+        username: user_data.username.clone(),
+        storage_version: user_data.version,
+        profiles: user_data.profiles.iter().map(profile_info).collect(),
+        adaptive_templates: adaptive.len(),
+        last_adaptive: adaptive.iter().map(|t| t.added).max(),
+        enrollment_images,
+        failures: lockout.failures,
+        locked_until: lockout.remaining().and(lockout.locked_until),
+    };
+    Response::UserInfo(UserInfoResponse {
+        success: true,
+        message: format!("{} has {} profiles with {} templates", request.username, 
+            info.profiles.len(), user_data.embedding_count()),
+        info: Some(info),
+    })
+}
+
+// Files under `dir`, one level of subdirectories deep (non-default profiles)
+fn count_files(dir: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .map(|path| if path.is_dir() { count_files(&path) } else { 1 })
+        .sum()
+}
+
+fn handle_delete_user_request(
+    request: &UserRequest,
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    data_dir: &Path,
+    recordings_dir: Option<&Path>,
+) -> Response {
+    let failure = |message: String| Response::DeleteUser(DeleteUserResponse {
+        success: false,
+        message,
+    });
+    
+    if let Err(message) = authorize_for_user(peer_cred, &request.username, "delete enrollment of") {
+        return failure(message);
+    }
+    
+    let store = match open_user_store(config, data_dir) {
+        Ok(s) => s,
+        Err(e) => return failure(format!("Failed to initialize storage: {}", e)),
+    };
+    let existed = match store.delete_user(&request.username) {
+        Ok(existed) => existed,
+        Err(e) => return failure(format!("Failed to delete biometric data of {}: {}", request.username, e)),
+    };
+    
+    if let Err(e) = LockoutStore::new(data_dir.join("lockout")).clear(&request.username) {
+        tracing::warn!("Failed to clear lockout state for {}: {}", request.username, e);
+    }
+    let recordings = recordings_dir.map_or(0, |dir| delete_recordings(dir, &request.username));
+    
+    if !existed {
+        return failure(format!("{} is not enrolled", request.username));
+    }
+    
+    tracing::info!("Deleted all biometric data of {} ({} session recordings)", request.username, recordings);
+    Response::DeleteUser(DeleteUserResponse {
+        success: true,
+        message: if recordings > 0 {
+            format!("Deleted templates, enrollment images and {} session recordings of {}", recordings, request.username)
+        } else {
+            format!("Deleted templates and enrollment images of {}", request.username)
+        },
+    })
+}
+
+// Remove `<user>_<YYYYmmdd_HHMMSS>.suprec` files written by save_recording
+fn delete_recordings(dir: &Path, username: &str) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let prefix = format!("{}_", username);
+    let mut deleted = 0;
+    for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let is_users = name.strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".suprec"))
+            .is_some_and(|stamp| stamp.len() == 15 && stamp.chars().all(|c| c.is_ascii_digit() || c == '_'));
+        if !is_users {
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => deleted += 1,
+            Err(e) => tracing::warn!("Failed to delete recording {}: {}", path.display(), e),
+        }
+    }
+    deleted
+}
+
+fn handle_delete_profile_request(
+    request: &DeleteProfileRequest,
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    data_dir: &Path,
+) -> Response {
+    let failure = |message: String| Response::DeleteProfile(DeleteProfileResponse {
+        success: false,
+        message,
+    });
+    
+    if let Err(message) = authorize_for_user(peer_cred, &request.username, "delete profiles of") {
+        return failure(message);
+    }
+    
+    let store = match open_user_store(config, data_dir) {
+        Ok(s) => s,
+        Err(e) => return failure(format!("Failed to initialize storage: {}", e)),
+    };
+    let mut user_data = match store.get_user(&request.username) {
+        Ok(data) => data,
+        Err(e) => return failure(format!("Failed to load {}: {}", request.username, e)),
+    };
+    
+    if user_data.profile(&request.profile).is_none() {
+        return failure(format!("{} has no profile '{}'", request.username, request.profile));
+    }
+    if user_data.profiles.len() == 1 {
+        return failure(format!("'{}' is the only profile of {}; enroll another profile first", 
+            request.profile, request.username));
+    }
+    
+    user_data.remove_profile(&request.profile);
+    if let Err(e) = store.save_user_data(&user_data) {
+        return failure(format!("Failed to save user data: {}", e));
+    }
+    if let Err(e) = store.remove_profile_images(&request.username, &request.profile) {
+        tracing::warn!("Failed to remove enrollment images of profile '{}' for {}: {}", 
+            request.profile, request.username, e);
+    }
+    if let Err(e) = store.clear_adaptive_profile(&request.username, &request.profile) {
+        tracing::warn!("Failed to clear adaptive templates of profile '{}' for {}: {}", 
+            request.profile, request.username, e);
+    }
+    
+    tracing::info!("Deleted profile '{}' of {}", request.profile, request.username);
+    Response::DeleteProfile(DeleteProfileResponse {
+        success: true,
+        message: format!("Deleted profile '{}' of {}", request.profile, request.username),
+    })
+}
+
+fn handle_reset_adaptive_request(
+    request: &ResetAdaptiveRequest,
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    data_dir: &Path,
+) -> Response {
+    let failure = |message: String| Response::ResetAdaptive(ResetAdaptiveResponse {
+        success: false,
+        message,
+        removed: 0,
+        remaining: 0,
+    });
+    
+    if let Err(message) = authorize_for_user(peer_cred, &request.username, "reset adaptive templates of") {
+        return failure(message);
+    }
+    
+    let store = match open_user_store(config, data_dir) {
+        Ok(s) => s,
+        Err(e) => return failure(format!("Failed to initialize storage: {}", e)),
+    };
+    let mut templates = match store.get_adaptive(&request.username) {
+        Ok(t) => t,
+        Err(e) => return failure(format!("Failed to load adaptive templates: {}", e)),
+    };
+    
+    let before = templates.len();
+    match request.since {
+        Some(since) => templates.retain(|t| t.added < since),
+        None => templates.clear(),
+    }
+    let removed = before - templates.len();
+    
+    if let Err(e) = store.save_adaptive(&request.username, &templates) {
+        return failure(format!("Failed to save adaptive templates: {}", e));
+    }
+    
+    tracing::info!("Removed {} adaptive templates of {} ({} kept)", removed, request.username, templates.len());
+    Response::ResetAdaptive(ResetAdaptiveResponse {
+        success: true,
+        message: format!("Removed {} adaptive templates of {}; {} kept", removed, request.username, templates.len()),
+        removed,
+        remaining: templates.len(),
+    })
+}
+
+// Anyone may ask; the last error can name users, so only root sees it
+fn handle_status_request(
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    liveness: &LivenessDetector,
+    stats: &ServiceStats,
+) -> Response {
+    let (authentications, auth_successes) = stats.authentications();
+    let (enrollments, enhancements) = stats.enrollments();
+    
+    Response::Status(StatusResponse {
+        success: true,
+        message: String::new(),
+        status: Some(ServiceStatus {
+            service_version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol_version: protocol::PROTOCOL_VERSION,
+            started: stats.started_at(),
+            uptime_secs: stats.uptime().as_secs(),
+            workers: stats.workers(),
+            models: stats.models(),
+            liveness_enabled: liveness.is_enabled(),
+            camera_config: camera_label(config),
+            camera: stats.camera(),
+            authentications,
+            auth_successes,
+            enrollments,
+            enhancements,
+            last_error: if peer_cred.uid == 0 { stats.last_error() } else { None },
+            stages: stats.stages(),
+        }),
+    })
+}
+
+fn handle_rotate_key_request(
+    peer_cred: &PeerCredentials,
+    config: &Config,
+    data_dir: &Path,
+) -> Response {
+    // Only root may rotate the template key
+    if peer_cred.uid != 0 {
+        tracing::warn!("UID {} attempted to rotate the template key", peer_cred.uid);
+        return Response::RotateKey(RotateKeyResponse {
+            success: false,
+            message: "Permission denied: key rotation requires root".to_string(),
+            rotated_count: 0,
+            key_id: String::new(),
+        });
+    }
+    
+    let mut store = match open_user_store(config, data_dir) {
+        Ok(s) => s,
+        Err(e) => {
+            return Response::RotateKey(RotateKeyResponse {
+                success: false,
+                message: format!("Failed to initialize storage: {}", e),
+                rotated_count: 0,
+                key_id: String::new(),
+            });
+        }
+    };
+    
+    // Blocks template writes on the other workers until it is done
+    match store.rotate_key() {
+        Ok(report) => {
+            let mut message = format!("Re-encrypted {} files with the new key", report.rotated);
+            if !report.skipped.is_empty() {
+                message.push_str(&format!(". Unreadable and left under the old key (re-enroll these): {}",
+                    report.skipped.join(", ")));
+            }
+            Response::RotateKey(RotateKeyResponse {
+                success: true,
+                message,
+                rotated_count: report.rotated,
+                key_id: store.active_key_id(),
+            })
+        }
+        Err(e) => {
+            tracing::error!("Key rotation failed: {}", e);
+            Response::RotateKey(RotateKeyResponse {
+                success: false,
+                message: format!("Key rotation failed: {}. Run rotate-key again to complete it.", e),
+                rotated_count: 0,
+                key_id: store.active_key_id(),
+            })
         }
     }
-    
-    Ok(())
 }
 
-fn handle_auth_request(
-    detector: &FaceDetector,
-    recognizer: &FaceRecognizer,
-    request: AuthRequest,
+fn handle_migrate_templates_request(
+    peer_cred: &PeerCredentials,
     config: &Config,
     data_dir: &Path,
 ) -> Response {
-    // Create camera just for this authentication
-    let mut camera = match Camera::new(config) {
-        Ok(c) => c,
-        Err(e) => {
-            return Response::Error(format!("Failed to initialize camera: {}", e));
-        }
-    };
+    let failure = |message: String| Response::MigrateTemplates(MigrateTemplatesResponse {
+        success: false,
+        message,
+        migrated: 0,
+    });
     
-    let result = perform_authentication(&mut camera, detector, recognizer, &request.username, &request.challenge, config, data_dir);
+    // Migration trusts plaintext files as they are - only root may ask for it
+    if peer_cred.uid != 0 {
+        tracing::warn!("UID {} attempted to migrate plaintext templates", peer_cred.uid);
+        return failure("Permission denied: template migration requires root".to_string());
+    }
     
-    // Camera will be dropped here, releasing the device
-    drop(camera);
+    let store = match open_user_store(config, data_dir) {
+        Ok(s) => s,
+        Err(e) => return failure(format!("Failed to initialize storage: {}", e)),
+    };
     
-    match result {
-        Ok(auth_response) => Response::Auth(auth_response),
+    match store.migrate_plaintext() {
+        Ok(migrated) => Response::MigrateTemplates(MigrateTemplatesResponse {
+            success: true,
+            message: format!("Encrypted {} plaintext user files", migrated),
+            migrated,
+        }),
         Err(e) => {
-            tracing::error!("Auth failed: {}", e);
-            Response::Error(format!("Authentication failed: {}", e))
+            tracing::error!("Template migration failed: {}", e);
+            failure(format!("Template migration failed: {}", e))
         }
     }
 }
@@ -303,7 +1672,8 @@ fn format_enrollment_report(
     username: &str,
     captured: usize,
     total: usize,
-    quality_scores: &[f32],
+    captures: &[QualityMetrics],
+    last_rejected: Option<&QualityMetrics>,
     consistency: f32,
     success: bool,
     width: usize,
@@ -327,21 +1697,24 @@ fn format_enrollment_report(
     lines.push(String::new());
     
     // Quality scores if we have any captures
-    if !quality_scores.is_empty() {
+    if !captures.is_empty() {
         lines.push("📊 Quality Scores:".to_string());
         let mut total_quality = 0.0;
-        for (i, score) in quality_scores.iter().enumerate() {
-            let percentage = (score * 100.0) as u32;
+        for (i, quality) in captures.iter().enumerate() {
+            let percentage = (quality.overall_score * 100.0) as u32;
             let bar_length = (percentage as usize * 20) / 100;
             let bar = "█".repeat(bar_length);
             let empty = "░".repeat(20_usize.saturating_sub(bar_length));
             lines.push(format!("  Capture {}: [{}{}] {}%", i + 1, bar, empty, percentage));
-            total_quality += score;
+            lines.push(format!("    sharpness {}%, yaw {:+.0}°, pitch {:+.0}°, roll {:+.0}°, unobstructed {}%",
+                (quality.sharpness_score * 100.0) as u32, quality.pose.yaw, quality.pose.pitch, quality.pose.roll,
+                (quality.occlusion_score * 100.0) as u32));
+            total_quality += quality.overall_score;
         }
         lines.push(String::new());
         
         // Average quality
-        let avg_quality = total_quality / quality_scores.len() as f32;
+        let avg_quality = total_quality / captures.len() as f32;
         let avg_percentage = (avg_quality * 100.0) as u32;
         let rating = if avg_quality >= 0.8 {
             "Excellent ⭐⭐⭐⭐⭐"
@@ -386,13 +1759,19 @@ fn format_enrollment_report(
             lines.push("   • Maintain consistent distance from camera".to_string());
             lines.push("   • Try better lighting conditions".to_string());
         }
-        if !quality_scores.is_empty() {
-            let avg_quality = quality_scores.iter().sum::<f32>() / quality_scores.len() as f32;
+        if !captures.is_empty() {
+            let avg_quality = captures.iter().map(|q| q.overall_score).sum::<f32>() / captures.len() as f32;
             if avg_quality < 0.6 {
                 lines.push("   • Image quality was too low".to_string());
                 lines.push("   • Clean the camera lens".to_string());
             }
         }
+        // What kept the last rejected frame from counting
+        if let Some(quality) = last_rejected {
+            for suggestion in quality.get_improvement_suggestions() {
+                lines.push(format!("   • {}", suggestion));
+            }
+        }
     }
     
     // Pad to requested height if needed
@@ -503,27 +1882,22 @@ fn format_enhancement_report(
         .join("\n")
 }
 
+// Helper function to send a response frame
+fn send_response(stream: &mut UnixStream, response: &Response) -> Result<()> {
+    protocol::write_frame(stream, MSG_TYPE_RESPONSE, response)
+}
+
 // Helper function to send stream messages
 fn send_stream_message(stream: &mut UnixStream, msg: &StreamMessage) -> Result<()> {
-    let msg_data = bincode::serialize(msg)
-        .map_err(|e| anyhow::anyhow!("Failed to serialize stream message: {}", e))?;
-    let msg_len = (msg_data.len() as u32).to_le_bytes();
-    
-    stream.write_all(&[MSG_TYPE_STREAM])?;
-    stream.write_all(&msg_len)?;
-    stream.write_all(&msg_data)?;
-    stream.flush()?;
-    
-    Ok(())
+    protocol::write_frame(stream, MSG_TYPE_STREAM, msg)
 }
 
-// Helper function to send final response
-fn send_final_response(stream: &mut UnixStream, response: &Response) -> Result<()> {
-    let response_data = bincode::serialize(response)
+// Reply to a client that predates framing using its bare length-prefixed format
+fn send_legacy_error(stream: &mut UnixStream, message: &str) -> Result<()> {
+    let response_data = bincode::serialize(&Response::Error(message.to_string()))
         .map_err(|e| anyhow::anyhow!("Failed to serialize response: {}", e))?;
     let response_len = (response_data.len() as u32).to_le_bytes();
     
-    stream.write_all(&[MSG_TYPE_RESPONSE])?;
     stream.write_all(&response_len)?;
     stream.write_all(&response_data)?;
     stream.flush()?;
@@ -540,7 +1914,9 @@ fn handle_enroll_request_with_stream(
     peer_cred: &PeerCredentials,
     config: &Config,
     data_dir: &Path,
-) -> Result<()> {
+    stats: &ServiceStats,
+    watch: &mut ClientWatch,
+) -> Result<Response> {
     // Check if preview is enabled
     if request.enable_preview {
         // Call the enhanced version with streaming
@@ -552,11 +1928,18 @@ fn handle_enroll_request_with_stream(
             peer_cred,
             config,
             data_dir,
+            stats,
+            watch,
         )?;
         
-        // Send complete message followed by final response
-        send_stream_message(stream, &StreamMessage::Complete)?;
-        send_final_response(stream, &response)?;
+        // Nobody left to tell
+        if !watch.is_hung_up() {
+            // Send complete message followed by final response
+            send_stream_message(stream, &StreamMessage::Complete)?;
+            send_response(stream, &response)?;
+        }
+        
+        Ok(response)
     } else {
         // Call the original non-streaming version
         let response = handle_enroll_request(
@@ -566,13 +1949,17 @@ fn handle_enroll_request_with_stream(
             peer_cred,
             config,
             data_dir,
+            stats,
+            watch,
         );
         
-        // Send response without streaming
-        send_final_response(stream, &response)?;
+        if !watch.is_hung_up() {
+            // Send response without streaming
+            send_response(stream, &response)?;
+        }
+        
+        Ok(response)
     }
-    
-    Ok(())
 }
 
 // Wrapper function that handles streaming for enhancement
@@ -584,7 +1971,9 @@ fn handle_enhance_request_with_stream(
     peer_cred: &PeerCredentials,
     config: &Config,
     data_dir: &Path,
-) -> Result<()> {
+    stats: &ServiceStats,
+    watch: &mut ClientWatch,
+) -> Result<Response> {
     // Check if preview is enabled
     if request.enable_preview {
         // Call the enhanced version with streaming
@@ -596,11 +1985,18 @@ fn handle_enhance_request_with_stream(
             peer_cred,
             config,
             data_dir,
+            stats,
+            watch,
         )?;
         
-        // Send complete message followed by final response
-        send_stream_message(stream, &StreamMessage::Complete)?;
-        send_final_response(stream, &response)?;
+        // Nobody left to tell
+        if !watch.is_hung_up() {
+            // Send complete message followed by final response
+            send_stream_message(stream, &StreamMessage::Complete)?;
+            send_response(stream, &response)?;
+        }
+        
+        Ok(response)
     } else {
         // Call the original non-streaming version
         let response = handle_enhance_request(
@@ -610,13 +2006,17 @@ fn handle_enhance_request_with_stream(
             peer_cred,
             config,
             data_dir,
+            stats,
+            watch,
         );
         
-        // Send response without streaming
-        send_final_response(stream, &response)?;
+        if !watch.is_hung_up() {
+            // Send response without streaming
+            send_response(stream, &response)?;
+        }
+        
+        Ok(response)
     }
-    
-    Ok(())
 }
 
 // Streaming version of enrollment that sends ASCII preview frames
@@ -628,8 +2028,9 @@ fn handle_enroll_request_streaming(
     peer_cred: &PeerCredentials,
     config: &Config,
     data_dir: &Path,
+    stats: &ServiceStats,
+    watch: &mut ClientWatch,
 ) -> Result<Response> {
-    use sup_linux::quality::QualityMetrics;
     
     // Authorization check: Users can only enroll themselves unless they're root
     if peer_cred.uid != 0 {
@@ -656,10 +2057,7 @@ fn handle_enroll_request_streaming(
         request.username, peer_cred.uid);
     
     // Create user store with appropriate paths
-    let store = match UserStore::new_with_paths(
-        data_dir.join("users"),
-        data_dir.join("enrollment"),
-    ) {
+    let store = match open_user_store(config, data_dir) {
         Ok(s) => s,
         Err(e) => {
             return Ok(Response::Enroll(EnrollResponse {
@@ -669,8 +2067,8 @@ fn handle_enroll_request_streaming(
         }
     };
     
-    // Create enrollment images directory for this user
-    let enrollment_dir = match store.get_enrollment_images_dir(&request.username) {
+    // Create enrollment images directory for this profile
+    let enrollment_dir = match store.get_profile_images_dir(&request.username, &request.profile) {
         Ok(dir) => {
             if let Err(e) = std::fs::create_dir_all(&dir) {
                 return Ok(Response::Enroll(EnrollResponse {
@@ -689,8 +2087,11 @@ fn handle_enroll_request_streaming(
     };
     
     // Create camera just for this enrollment
-    let mut camera = match Camera::new(config) {
-        Ok(c) => c,
+    let mut camera = match camera::open(config) {
+        Ok(c) => {
+            stats.record_camera(c.as_ref());
+            c
+        }
         Err(e) => {
             return Ok(Response::Enroll(EnrollResponse {
                 success: false,
@@ -708,10 +2109,26 @@ fn handle_enroll_request_streaming(
     // Capture multiple images
     let mut embeddings = Vec::new();
     let mut quality_scores = Vec::new();
-    let total_captures = config.enrollment.num_captures.unwrap_or(5);
-    let min_quality = config.enrollment.min_enrollment_quality;
+    let mut capture_metrics = Vec::new();
+    let mut last_rejected: Option<QualityMetrics> = None;
+    let mut last_hint: Option<String> = None;
+    
+    // Guided enrollment captures one template per requested pose
+    let guided_steps = request.guided
+        .then(|| enrollment::guided_steps(&config.enrollment, request.with_glasses));
+    let mut poses = Vec::new();
+    let total_captures = match guided_steps {
+        Some(ref steps) => steps.len(),
+        None => config.enrollment.num_captures.unwrap_or(5),
+    };
     
-    tracing::info!("Capturing {} images for enrollment with ASCII preview", total_captures);
+    tracing::info!("Capturing {} images for enrollment with ASCII preview{}", 
+        total_captures, if request.guided { " (guided)" } else { "" });
+    if let Some(step) = guided_steps.as_ref().and_then(|steps| steps.first()) {
+        if let Err(e) = send_stream_message(stream, &StreamMessage::StatusUpdate { message: step.prompt() }) {
+            tracing::debug!("Failed to send status update: {}", e);
+        }
+    }
     
     // Start camera session
     let mut session = match camera.start_session() {
@@ -729,8 +2146,10 @@ fn handle_enroll_request_streaming(
     let capture_interval = Duration::from_millis(capture_interval_ms);
     
     // Calculate dynamic timeout: num_captures * interval * 5 for overhead
+    // Guided poses take longer to get into than small head movements
+    let timeout_factor = if request.guided { 15 } else { 5 };
     let enrollment_timeout = Duration::from_millis(
-        total_captures as u64 * capture_interval_ms * 5
+        total_captures as u64 * capture_interval_ms * timeout_factor
     );
     let enrollment_start = Instant::now();
     let mut last_capture_time = Instant::now();
@@ -739,6 +2158,15 @@ fn handle_enroll_request_streaming(
                  enrollment_timeout.as_secs_f32(), total_captures, capture_interval.as_secs_f32());
     
     while captured < total_captures && enrollment_start.elapsed() < enrollment_timeout {
+        // Stop as soon as the client hangs up or cancels
+        if let Some(reason) = watch.check() {
+            tracing::info!("Enrollment for {} cancelled: {}", request.username, reason);
+            return Ok(Response::Enroll(EnrollResponse {
+                success: false,
+                message: format!("Enrollment cancelled: {}", reason),
+            }));
+        }
+        
         // Capture frame
         let frame = match session.capture_frame() {
             Ok(f) => f,
@@ -758,11 +2186,14 @@ fn handle_enroll_request_streaming(
         };
         
         // Send ASCII preview frame
+        let step = guided_steps.as_ref().and_then(|steps| steps.get(captured)).copied();
+        let prompt = step.map(|step| step.prompt());
         let ascii = renderer.render_frame_with_progress(
             &frame,
             &faces,
             captured,
-            total_captures
+            total_captures,
+            prompt.as_deref(),
         );
         
         if let Err(e) = send_stream_message(stream, &StreamMessage::PreviewFrame { 
@@ -778,11 +2209,25 @@ fn handle_enroll_request_streaming(
         if !faces.is_empty() && last_capture_time.elapsed() >= capture_interval {
             let face = &faces[0];
             
+            // Pose can only be checked with landmarks
+            if step.is_some() && face.landmarks.is_none() {
+                return Ok(Response::Enroll(EnrollResponse {
+                    success: false,
+                    message: "Guided enrollment needs a face detector that outputs landmarks".to_string(),
+                }));
+            }
+            
             // Calculate quality metrics
-            let quality = QualityMetrics::calculate(&frame, face);
+            let quality = QualityMetrics::calculate(&frame, face, &config.enrollment);
+            
+            // In guided mode, wait until the user holds the requested pose
+            if step.is_some_and(|step| !step.matches(&quality.pose, &config.enrollment)) {
+                std::thread::sleep(Duration::from_millis(50));
+                continue;
+            }
             
             // Check if quality meets requirements
-            if quality.meets_minimum_requirements(min_quality) {
+            if quality.meets_minimum_requirements(&config.enrollment) {
                 // Get embedding
                 let embedding = match recognizer.get_embedding(&frame, face) {
                     Ok(e) => e,
@@ -800,20 +2245,37 @@ fn handle_enroll_request_streaming(
                 
                 embeddings.push(embedding);
                 quality_scores.push(quality.overall_score);
+                capture_metrics.push(quality.clone());
+                poses.push(step);
                 captured += 1;
                 last_capture_time = Instant::now();
                 
                 // Use debug level to avoid interfering with ASCII preview
                 tracing::debug!("Captured image {}/{} with quality {:.2}", captured, total_captures, quality.overall_score);
                 
-                // Send status update through the stream (not to stderr)
-                if let Err(e) = send_stream_message(stream, &StreamMessage::StatusUpdate { 
-                    message: format!("Captured image {}/{} with quality {:.2}", captured, total_captures, quality.overall_score),
-                }) {
+                // Send status update through the stream (not to stderr), naming the next pose when guided
+                let message = match guided_steps.as_ref().and_then(|steps| steps.get(captured)) {
+                    Some(next) => format!("Captured {}/{} - {}", captured, total_captures, next.prompt()),
+                    None => format!("Captured image {}/{} with quality {:.2}", captured, total_captures, quality.overall_score),
+                };
+                last_hint = None;
+                if let Err(e) = send_stream_message(stream, &StreamMessage::StatusUpdate { message }) {
                     tracing::debug!("Failed to send status update: {}", e);
                 }
             } else {
-                tracing::debug!("Image quality too low: {:.2}", quality.overall_score);
+                tracing::debug!("Image quality too low: {:.2} {:?}", quality.overall_score, quality.failed_minimums(&config.enrollment));
+                
+                // Tell the user what to fix, once per change of advice
+                let hint = quality.get_improvement_suggestions().into_iter().next();
+                if hint.is_some() && hint != last_hint {
+                    if let Err(e) = send_stream_message(stream, &StreamMessage::StatusUpdate {
+                        message: hint.clone().unwrap_or_default(),
+                    }) {
+                        tracing::debug!("Failed to send status update: {}", e);
+                    }
+                    last_hint = hint;
+                }
+                last_rejected = Some(quality);
             }
         }
         
@@ -836,7 +2298,8 @@ fn handle_enroll_request_streaming(
         &request.username,
         captured,
         total_captures,
-        &quality_scores,
+        &capture_metrics,
+        last_rejected.as_ref(),
         consistency,
         success,
         config.enrollment.ascii_width.unwrap_or(60),
@@ -859,35 +2322,23 @@ fn handle_enroll_request_streaming(
         }));
     }
     
-    // Calculate averaged embedding for successful enrollment
-    let averaged_embedding = if !embeddings.is_empty() {
-        let embedding_size = embeddings[0].len();
-        let mut averaged = vec![0.0f32; embedding_size];
-        
-        for embedding in &embeddings {
-            for (i, &value) in embedding.iter().enumerate() {
-                averaged[i] += value;
-            }
-        }
-        
-        let count = embeddings.len() as f32;
-        for value in &mut averaged {
-            *value /= count;
+    // Create the profile with the averaged embedding, keeping the user's other profiles
+    let mut profile = enrollment::build_profile(&request.profile, &camera_label(config), embeddings, quality_scores);
+    if request.guided {
+        profile.embedding_poses = Some(poses);
+    }
+    let captures = profile.embeddings.len();
+    let mut user_data = match store.get_user(&request.username) {
+        Ok(data) => data,
+        Err(FaceAuthError::UserNotFound(_)) => UserData::new(&request.username),
+        Err(e) => {
+            return Ok(Response::Enroll(EnrollResponse {
+                success: false,
+                message: format!("Failed to load existing profiles: {}", e),
+            }));
         }
-        
-        Some(averaged)
-    } else {
-        None
-    };
-    
-    // Create user data
-    let user_data = sup_linux::storage::UserData {
-        version: 1,
-        username: request.username.clone(),
-        embeddings,
-        averaged_embedding,
-        embedding_qualities: Some(quality_scores.clone()),
     };
+    user_data.set_profile(profile);
     
     // Save user data
     if let Err(e) = store.save_user_data(&user_data) {
@@ -897,10 +2348,15 @@ fn handle_enroll_request_streaming(
         }));
     }
     
+    // Templates learned against the replaced profile don't carry over; other profiles keep theirs
+    if let Err(e) = store.clear_adaptive_profile(&request.username, &request.profile) {
+        tracing::warn!("Failed to clear adaptive templates for {}: {}", request.username, e);
+    }
+    
     Ok(Response::Enroll(EnrollResponse {
         success: true,
-        message: format!("User '{}' enrolled successfully with {} face captures", 
-                        request.username, user_data.embeddings.len()),
+        message: format!("User '{}' enrolled successfully with {} face captures in profile '{}'", 
+                        request.username, captures, request.profile),
     }))
 }
 
@@ -911,8 +2367,9 @@ fn handle_enroll_request(
     peer_cred: &PeerCredentials,
     config: &Config,
     data_dir: &Path,
+    stats: &ServiceStats,
+    watch: &mut ClientWatch,
 ) -> Response {
-    use sup_linux::quality::QualityMetrics;
     
     // Authorization check: Users can only enroll themselves unless they're root
     if peer_cred.uid != 0 {
@@ -939,11 +2396,16 @@ fn handle_enroll_request(
     tracing::info!("Starting enrollment for user: {} (requested by UID: {})", 
         request.username, peer_cred.uid);
     
+    // Guided enrollment prompts through the preview stream
+    if request.guided {
+        return Response::Enroll(EnrollResponse {
+            success: false,
+            message: "Guided enrollment requires the live preview".to_string(),
+        });
+    }
+    
     // Create user store with appropriate paths
-    let store = match UserStore::new_with_paths(
-        data_dir.join("users"),
-        data_dir.join("enrollment"),
-    ) {
+    let store = match open_user_store(config, data_dir) {
         Ok(s) => s,
         Err(e) => {
             return Response::Enroll(EnrollResponse {
@@ -953,8 +2415,8 @@ fn handle_enroll_request(
         }
     };
     
-    // Create enrollment images directory for this user
-    let enrollment_dir = match store.get_enrollment_images_dir(&request.username) {
+    // Create enrollment images directory for this profile
+    let enrollment_dir = match store.get_profile_images_dir(&request.username, &request.profile) {
         Ok(dir) => {
             if let Err(e) = std::fs::create_dir_all(&dir) {
                 return Response::Enroll(EnrollResponse {
@@ -973,8 +2435,11 @@ fn handle_enroll_request(
     };
     
     // Create camera just for this enrollment
-    let mut camera = match Camera::new(config) {
-        Ok(c) => c,
+    let mut camera = match camera::open(config) {
+        Ok(c) => {
+            stats.record_camera(c.as_ref());
+            c
+        }
         Err(e) => {
             return Response::Enroll(EnrollResponse {
                 success: false,
@@ -987,7 +2452,6 @@ fn handle_enroll_request(
     let mut embeddings = Vec::new();
     let mut quality_scores = Vec::new();
     let total_captures = config.enrollment.num_captures.unwrap_or(5);
-    let min_quality = config.enrollment.min_enrollment_quality;
     
     tracing::info!("Capturing {} images for enrollment", total_captures);
     
@@ -1017,6 +2481,14 @@ fn handle_enroll_request(
                  enrollment_timeout.as_secs_f32(), total_captures, capture_interval.as_secs_f32());
     
     while captured < total_captures && enrollment_start.elapsed() < enrollment_timeout {
+        // Stop as soon as the client hangs up or cancels
+        if let Some(reason) = watch.check() {
+            tracing::info!("Enrollment for {} cancelled: {}", request.username, reason);
+            return Response::Enroll(EnrollResponse {
+                success: false,
+                message: format!("Enrollment cancelled: {}", reason),
+            });
+        }
         
         // Capture frame
         let frame = match session.capture_frame() {
@@ -1040,18 +2512,13 @@ fn handle_enroll_request(
         
         let face = &faces[0];
         
-        // Calculate quality metrics
-        let quality = QualityMetrics::calculate(&frame, face);
-        
-        // Check if quality meets requirements
-        if !quality.meets_minimum_requirements(min_quality) {
-            tracing::debug!("Image quality too low: {:.2}", quality.overall_score);
-            continue;
-        }
-        
-        // Get embedding
-        let embedding = match recognizer.get_embedding(&frame, face) {
-            Ok(e) => e,
+        // Quality check and embedding, shared with `suplinux evaluate`
+        let (embedding, quality) = match enrollment::enrollment_sample(recognizer, &frame, face, &config.enrollment) {
+            Ok(EnrollmentSample::Accepted { embedding, quality }) => (embedding, quality),
+            Ok(EnrollmentSample::LowQuality(quality)) => {
+                tracing::debug!("Image quality too low: {:.2} {:?}", quality.overall_score, quality.failed_minimums(&config.enrollment));
+                continue;
+            }
             Err(e) => {
                 tracing::warn!("Failed to get embedding: {}", e);
                 continue;
@@ -1079,43 +2546,34 @@ fn handle_enroll_request(
         });
     }
     
-    // Calculate averaged embedding
-    let averaged_embedding = if !embeddings.is_empty() {
-        let embedding_size = embeddings[0].len();
-        let mut averaged = vec![0.0f32; embedding_size];
-        
-        for embedding in &embeddings {
-            for (i, &value) in embedding.iter().enumerate() {
-                averaged[i] += value;
-            }
-        }
-        
-        let count = embeddings.len() as f32;
-        for value in &mut averaged {
-            *value /= count;
+    // Create the profile with the averaged embedding, keeping the user's other profiles
+    let profile = enrollment::build_profile(&request.profile, &camera_label(config), embeddings, quality_scores);
+    let captures = profile.embeddings.len();
+    let mut user_data = match store.get_user(&request.username) {
+        Ok(data) => data,
+        Err(FaceAuthError::UserNotFound(_)) => UserData::new(&request.username),
+        Err(e) => {
+            return Response::Enroll(EnrollResponse {
+                success: false,
+                message: format!("Failed to load existing profiles: {}", e),
+            });
         }
-        
-        Some(averaged)
-    } else {
-        None
-    };
-    
-    // Create user data
-    let user_data = sup_linux::storage::UserData {
-        version: 1,
-        username: request.username.clone(),
-        embeddings,
-        averaged_embedding,
-        embedding_qualities: Some(quality_scores),
     };
+    user_data.set_profile(profile);
     
     // Save user data
     match store.save_user_data(&user_data) {
         Ok(_) => {
             tracing::info!("Successfully enrolled user: {}", request.username);
+            
+            // Templates learned against the replaced profile don't carry over; other profiles keep theirs
+            if let Err(e) = store.clear_adaptive_profile(&request.username, &request.profile) {
+                tracing::warn!("Failed to clear adaptive templates for {}: {}", request.username, e);
+            }
             Response::Enroll(EnrollResponse {
                 success: true,
-                message: format!("Successfully enrolled user '{}' with {} face captures", request.username, user_data.embeddings.len()),
+                message: format!("Successfully enrolled user '{}' with {} face captures in profile '{}'", 
+                                request.username, captures, request.profile),
             })
         }
         Err(e) => {
@@ -1129,58 +2587,119 @@ fn handle_enroll_request(
 }
 
 fn perform_authentication(
-    camera: &mut Camera,
+    camera: &mut dyn FrameSource,
     detector: &FaceDetector,
     recognizer: &FaceRecognizer,
+    liveness: &LivenessDetector,
     username: &str,
     challenge: &[u8],
     config: &Config,
+    learning_threshold: f32,
     data_dir: &Path,
-) -> Result<AuthResponse> {
+    record: bool,
+    stats: &ServiceStats,
+    progress: &mut dyn FnMut(AuthProgress),
+    watch: &mut ClientWatch,
+) -> Result<AuthAttempt> {
     // Load user's stored embeddings
-    let store = UserStore::new_with_paths(
-        data_dir.join("users"),
-        data_dir.join("enrollment"),
-    )?;
+    let store = open_user_store(config, data_dir)?;
     
     let user_data = match store.get_user(username) {
         Ok(data) => data,
-        Err(_) => {
-            return Ok(AuthResponse {
-                success: false,
-                message: format!("User {} not enrolled", username),
-                attempts: 0,
-                signature: vec![],
-                timestamp: SystemTime::now(),
+        Err(FaceAuthError::UserNotFound(_)) => {
+            return Ok(AuthAttempt {
+                response: AuthResponse {
+                    success: false,
+                    message: format!("User {} not enrolled", username),
+                    attempts: 0,
+                    challenge: challenge.to_vec(),
+                    signature: vec![],
+                    timestamp: SystemTime::now(),
+                    locked_until: None,
+                    liveness_failures: 0,
+                    not_applicable: false,
+                },
+                face_seen: false,
+                best_similarity: None,
+                recorder: None,
             });
         }
+        Err(e) => {
+            // Tampered, undecryptable or unmigrated templates - an error for
+            // the audit log, not a user who simply isn't enrolled
+            tracing::error!("Cannot load templates of {}: {}", username, e);
+            return Err(e);
+        }
+    };
+    
+    // Learned templates match alongside the enrollment
+    let adaptive_templates = if config.adaptive.enabled {
+        store.get_adaptive(username).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring adaptive templates for {}: {}", username, e);
+            Vec::new()
+        })
+    } else {
+        Vec::new()
     };
+    let templates = user_data.with_adaptive(&adaptive_templates);
     
     // Initialize authentication state
     let mut state = AuthenticationState::new(config.auth.embedding_buffer_size as usize);
+    let mut liveness_tracker = liveness.tracker();
+    let mut recorder = record.then(|| AuthRecorder::new(username, &templates, config));
+    let mut face_lost = false;
     
     // Start camera session
     let mut session = camera.start_session()?;
     tracing::info!("Starting authentication for user: {}", username);
+    progress(AuthProgress::Started);
     
     let start_time = Instant::now();
     let timeout = Duration::from_secs(config.auth.timeout_seconds as u64);
     let lost_face_timeout = Duration::from_secs(config.auth.lost_face_timeout as u64);
     let mut total_attempts = 0;
+    let mut face_seen = false;
+    let mut best_similarity: Option<f32> = None;
+    let mut liveness_failures = 0;
+    let mut last_liveness_reason: Option<String> = None;
     
     // Authentication loop
     while start_time.elapsed() < timeout {
+        // Stop as soon as the caller gives up, e.g. the user typed their password instead
+        if let Some(reason) = watch.check() {
+            tracing::info!("Authentication for {} cancelled after {} attempts: {}", username, total_attempts, reason);
+            return Ok(AuthAttempt {
+                response: AuthResponse {
+                    success: false,
+                    message: format!("Authentication cancelled: {}", reason),
+                    attempts: total_attempts,
+                    challenge: challenge.to_vec(),
+                    signature: vec![],
+                    timestamp: SystemTime::now(),
+                    locked_until: None,
+                    liveness_failures,
+                    not_applicable: false,
+                },
+                face_seen,
+                best_similarity,
+                recorder,
+            });
+        }
+        
         total_attempts += 1;
         
         // Check if we've lost the face for too long
         if state.face_detected_once && state.last_face_time.elapsed() > lost_face_timeout {
             tracing::info!("Face lost - resetting authentication progress");
+            progress(AuthProgress::FaceLost);
             // Reset K-of-N tracking
             state = AuthenticationState::new(config.auth.embedding_buffer_size as usize);
+            liveness_tracker.reset();
+            face_lost = true;
         }
         
         // Capture frame
-        let frame = match session.capture_frame() {
+        let frame = match stats.time(Stage::Capture, || session.capture_frame()) {
             Ok(f) => f,
             Err(e) => {
                 tracing::warn!("Failed to capture frame: {}", e);
@@ -1188,19 +2707,34 @@ fn perform_authentication(
             }
         };
         
+        if let Some(ref mut r) = recorder {
+            if let Err(e) = r.frame(&frame) {
+                tracing::warn!("Stopped recording session: {}", e);
+                recorder = None;
+            }
+        }
+        if let Some(ref mut r) = recorder {
+            r.annotate(|t| t.reset = std::mem::take(&mut face_lost));
+        }
+        
         // Detect faces
-        match detector.detect(&frame) {
+        match stats.time(Stage::Detection, || detector.detect(&frame)) {
             Ok(faces) if !faces.is_empty() => {
+                if let Some(ref mut r) = recorder {
+                    r.annotate(|t| t.faces = faces.clone());
+                }
                 if !state.face_detected_once {
                     tracing::info!("Face detected, beginning verification");
+                    progress(AuthProgress::FaceDetected);
                 }
                 state.face_detected_once = true;
                 state.last_face_time = Instant::now();
+                face_seen = true;
                 
                 let face = &faces[0];
                 
                 // Get embedding
-                let embedding = match recognizer.get_embedding(&frame, face) {
+                let embedding = match stats.time(Stage::Embedding, || recognizer.get_embedding(&frame, face)) {
                     Ok(e) => e,
                     Err(e) => {
                         tracing::warn!("Failed to get embedding: {}", e);
@@ -1218,12 +2752,40 @@ fn perform_authentication(
                 let similarity = calculate_best_similarity(
                     &embedding,
                     &state.embedding_buffer,
-                    &user_data,
+                    &templates,
                     config.auth.use_embedding_fusion
                 );
                 
+                best_similarity = Some(best_similarity.map_or(similarity, |best| best.max(similarity)));
+                
+                // Anti-spoofing runs on every frame so motion is measured between consecutive ones
+                let live = stats.time(Stage::Liveness, || liveness_tracker.check(&frame, face));
+                if let Some(ref mut r) = recorder {
+                    r.annotate(|t| {
+                        t.embedding = Some(embedding.clone());
+                        t.similarity = Some(similarity);
+                        t.live = Some(live.live);
+                        t.liveness_reason = live.reason.clone();
+                    });
+                }
+                if live.pending {
+                    std::thread::sleep(Duration::from_millis(50));
+                    continue;
+                }
+                let matched = similarity > config.auth.similarity_threshold;
+                if matched && !live.live {
+                    // A matching face that fails liveness is a failed attempt, not a skipped one
+                    liveness_failures += 1;
+                    tracing::info!("Liveness check failed for {}: {}", username,
+                        live.reason.as_deref().unwrap_or("unknown"));
+                    last_liveness_reason = live.reason;
+                }
+                
                 // Update K-of-N tracking
-                let success = similarity > config.auth.similarity_threshold;
+                let success = matched && live.live;
+                if let Some(ref mut r) = recorder {
+                    r.annotate(|t| t.counted = Some(success));
+                }
                 state.auth_attempts.push_back(success);
                 if success {
                     state.successful_matches += 1;
@@ -1236,22 +2798,33 @@ fn perform_authentication(
                     }
                 }
                 
-                tracing::debug!("Auth attempt: similarity={:.3}, success={}, matches={}/{}", 
-                    similarity, success, state.successful_matches, config.auth.k_required_matches);
+                tracing::debug!("Auth attempt: similarity={:.3}, ir_brightness={:.0}, ir_contrast={:.1}, motion={:?}, success={}, matches={}/{}", 
+                    similarity, live.ir_brightness, live.ir_contrast, live.motion, success,
+                    state.successful_matches, config.auth.k_required_matches);
                 
                 // Check for K successes
                 if state.successful_matches >= config.auth.k_required_matches {
                     tracing::info!("Authentication successful after {} attempts", total_attempts);
                     
-                    // Generate signature using the current embedding
-                    let signature = generate_signature(&embedding, challenge);
+                    if config.adaptive.enabled {
+                        adapt_templates(&store, username, adaptive_templates, &embedding, &frame, face, &user_data, learning_threshold, config);
+                    }
                     
-                    return Ok(AuthResponse {
-                        success: true,
-                        message: format!("Authenticated after {} attempts", total_attempts),
-                        attempts: total_attempts,
-                        signature,
-                        timestamp: SystemTime::now(),
+                    return Ok(AuthAttempt {
+                        response: AuthResponse {
+                            success: true,
+                            message: format!("Authenticated after {} attempts", total_attempts),
+                            attempts: total_attempts,
+                            challenge: challenge.to_vec(),
+                            signature: vec![],
+                            timestamp: SystemTime::now(),
+                            locked_until: None,
+                            liveness_failures,
+                            not_applicable: false,
+                        },
+                        face_seen,
+                        best_similarity,
+                        recorder,
                     });
                 }
             }
@@ -1267,87 +2840,52 @@ fn perform_authentication(
     
     // Timeout
     tracing::info!("Authentication timeout for user {} after {} attempts", username, total_attempts);
-    Ok(AuthResponse {
-        success: false,
-        message: "Authentication timeout".to_string(),
-        attempts: total_attempts,
-        signature: vec![],
-        timestamp: SystemTime::now(),
+    let message = match last_liveness_reason {
+        Some(reason) => format!("Authentication timeout - {} matching frames failed liveness ({})", liveness_failures, reason),
+        None => "Authentication timeout".to_string(),
+    };
+    Ok(AuthAttempt {
+        response: AuthResponse {
+            success: false,
+            message,
+            attempts: total_attempts,
+            challenge: challenge.to_vec(),
+            signature: vec![],
+            timestamp: SystemTime::now(),
+            locked_until: None,
+            liveness_failures,
+            not_applicable: false,
+        },
+        face_seen,
+        best_similarity,
+        recorder,
     })
 }
 
-fn calculate_best_similarity(
-    embedding: &[f32],
-    embedding_buffer: &VecDeque<Vec<f32>>,
-    user_data: &sup_linux::storage::UserData,
-    use_fusion: bool,
-) -> f32 {
-    let mut best_similarity = 0.0f32;
-    
-    // Check individual embedding against stored embeddings
-    for stored_embedding in user_data.embeddings.iter() {
-        let similarity = cosine_similarity(embedding, stored_embedding);
-        best_similarity = best_similarity.max(similarity);
-    }
-    
-    // Check against averaged stored embedding if available
-    if let Some(ref avg_stored) = user_data.averaged_embedding {
-        let similarity = cosine_similarity(embedding, avg_stored);
-        best_similarity = best_similarity.max(similarity);
-    }
-    
-    // Check fused embedding if enabled and we have enough samples
-    if use_fusion && embedding_buffer.len() >= 2 {
-        let fused_embedding = average_embeddings_buffer(embedding_buffer);
-        
-        for stored_embedding in user_data.embeddings.iter() {
-            let similarity = cosine_similarity(&fused_embedding, stored_embedding);
-            best_similarity = best_similarity.max(similarity);
-        }
-        
-        if let Some(ref avg_stored) = user_data.averaged_embedding {
-            let similarity = cosine_similarity(&fused_embedding, avg_stored);
-            best_similarity = best_similarity.max(similarity);
-        }
-    }
-    
-    best_similarity
-}
-
-fn average_embeddings_buffer(buffer: &VecDeque<Vec<f32>>) -> Vec<f32> {
-    if buffer.is_empty() {
-        return vec![];
+// Fold a confident success into the user's adaptive templates; failures only cost the update
+fn adapt_templates(
+    store: &UserStore,
+    username: &str,
+    mut templates: Vec<AdaptiveTemplate>,
+    embedding: &Embedding,
+    frame: &DynamicImage,
+    face: &FaceBox,
+    enrolled: &UserData,
+    learning_threshold: f32,
+    config: &Config,
+) {
+    let quality = QualityMetrics::calculate(frame, face, &config.enrollment);
+    if !quality.meets_minimum_requirements(&config.enrollment) {
+        tracing::debug!("Not adapting templates for {}: {:?}", username, quality.failed_minimums(&config.enrollment));
+        return;
     }
     
-    let embedding_size = buffer[0].len();
-    let mut averaged = vec![0.0f32; embedding_size];
-    
-    for embedding in buffer.iter() {
-        for (i, &value) in embedding.iter().enumerate() {
-            averaged[i] += value;
+    if adaptive::consider(&mut templates, embedding, quality.overall_score, enrolled, learning_threshold, config, SystemTime::now()) {
+        match store.save_adaptive(username, &templates) {
+            Ok(()) => tracing::info!("Adaptive templates for {} updated ({} kept)", username, templates.len()),
+            Err(e) => tracing::warn!("Failed to save adaptive templates for {}: {}", username, e),
         }
     }
-    
-    let count = buffer.len() as f32;
-    for value in &mut averaged {
-        *value /= count;
-    }
-    
-    averaged
-}
-
-fn generate_signature(embedding: &[f32], challenge: &[u8]) -> Vec<u8> {
-    let mut hasher = Sha256::new();
-    
-    // Hash embedding data
-    for &value in embedding {
-        hasher.update(value.to_le_bytes());
-    }
-    
-    // Hash challenge
-    hasher.update(challenge);
-    
-    hasher.finalize().to_vec()
 }
 
 // Streaming version of enhancement that sends ASCII preview frames
@@ -1359,8 +2897,9 @@ fn handle_enhance_request_streaming(
     peer_cred: &PeerCredentials,
     config: &Config,
     data_dir: &Path,
+    stats: &ServiceStats,
+    watch: &mut ClientWatch,
 ) -> Result<Response> {
-    use sup_linux::quality::QualityMetrics;
     
     // Authorization check: Users can only enhance themselves unless they're root
     if peer_cred.uid != 0 {
@@ -1393,10 +2932,7 @@ fn handle_enhance_request_streaming(
         request.username, peer_cred.uid);
     
     // Create user store
-    let store = match UserStore::new_with_paths(
-        data_dir.join("users"),
-        data_dir.join("enrollment"),
-    ) {
+    let store = match open_user_store(config, data_dir) {
         Ok(s) => s,
         Err(e) => {
             return Ok(Response::Enhance(EnhanceResponse {
@@ -1423,10 +2959,21 @@ fn handle_enhance_request_streaming(
         }
     };
     
-    let embeddings_before = user_data.embeddings.len();
+    let embeddings_before = match user_data.profile(&request.profile) {
+        Some(profile) => profile.embeddings.len(),
+        None => {
+            return Ok(Response::Enhance(EnhanceResponse {
+                success: false,
+                message: format!("User {} has no profile '{}'. Please enroll it first.", request.username, request.profile),
+                embeddings_before: 0,
+                embeddings_after: 0,
+                replaced_count: 0,
+            }));
+        }
+    };
     
     // Get enrollment images directory
-    let enrollment_dir = match store.get_enrollment_images_dir(&request.username) {
+    let enrollment_dir = match store.get_profile_images_dir(&request.username, &request.profile) {
         Ok(dir) => {
             if let Err(e) = std::fs::create_dir_all(&dir) {
                 return Ok(Response::Enhance(EnhanceResponse {
@@ -1451,8 +2998,11 @@ fn handle_enhance_request_streaming(
     };
     
     // Create camera just for this enhancement
-    let mut camera = match Camera::new(config) {
-        Ok(c) => c,
+    let mut camera = match camera::open(config) {
+        Ok(c) => {
+            stats.record_camera(c.as_ref());
+            c
+        }
         Err(e) => {
             return Ok(Response::Enhance(EnhanceResponse {
                 success: false,
@@ -1474,7 +3024,6 @@ fn handle_enhance_request_streaming(
     let mut new_embeddings = Vec::new();
     let mut new_quality_scores = Vec::new();
     let additional_captures = request.additional_captures.unwrap_or(3) as usize;
-    let min_quality = config.enrollment.min_enrollment_quality;
     
     tracing::info!("Capturing {} additional images for enhancement with ASCII preview", additional_captures);
     
@@ -1513,6 +3062,18 @@ fn handle_enhance_request_streaming(
     }
     
     while captured < additional_captures && enhancement_start.elapsed() < enhancement_timeout {
+        // Stop as soon as the client hangs up or cancels
+        if let Some(reason) = watch.check() {
+            tracing::info!("Enhancement for {} cancelled: {}", request.username, reason);
+            return Ok(Response::Enhance(EnhanceResponse {
+                success: false,
+                message: format!("Enhancement cancelled: {}", reason),
+                embeddings_before,
+                embeddings_after: embeddings_before,
+                replaced_count: 0,
+            }));
+        }
+        
         // Capture frame
         let frame = match session.capture_frame() {
             Ok(f) => f,
@@ -1536,7 +3097,8 @@ fn handle_enhance_request_streaming(
             &frame,
             &faces,
             captured,
-            additional_captures
+            additional_captures,
+            None,
         );
         
         if let Err(e) = send_stream_message(stream, &StreamMessage::PreviewFrame { 
@@ -1553,10 +3115,10 @@ fn handle_enhance_request_streaming(
             let face = &faces[0];
             
             // Calculate quality metrics
-            let quality = QualityMetrics::calculate(&frame, face);
+            let quality = QualityMetrics::calculate(&frame, face, &config.enrollment);
             
             // Check if quality meets requirements
-            if quality.meets_minimum_requirements(min_quality) {
+            if quality.meets_minimum_requirements(&config.enrollment) {
                 // Get embedding
                 let embedding = match recognizer.get_embedding(&frame, face) {
                     Ok(e) => e,
@@ -1601,15 +3163,14 @@ fn handle_enhance_request_streaming(
     let success = captured > 0;  // Enhancement can succeed with partial captures
     
     // Merge new embeddings with existing data
-    let (added_count, replaced_count) = if !new_embeddings.is_empty() {
-        store.merge_user_data(
-            &mut user_data,
+    let (added_count, replaced_count) = match user_data.profile_mut(&request.profile) {
+        Some(profile) if !new_embeddings.is_empty() => store.merge_profile(
+            profile,
             new_embeddings,
             new_quality_scores.clone(),
             request.replace_weak
-        )
-    } else {
-        (0, 0)
+        ),
+        _ => (0, 0),
     };
     
     // Save updated user data if we have new embeddings
@@ -1625,7 +3186,7 @@ fn handle_enhance_request_streaming(
         }
     }
     
-    let embeddings_after = user_data.embeddings.len();
+    let embeddings_after = user_data.profile(&request.profile).map_or(0, |p| p.embeddings.len());
     
     // Send the enhancement report as final frame
     let report = format_enhancement_report(
@@ -1687,8 +3248,9 @@ fn handle_enhance_request(
     peer_cred: &PeerCredentials,
     config: &Config,
     data_dir: &Path,
+    stats: &ServiceStats,
+    watch: &mut ClientWatch,
 ) -> Response {
-    use sup_linux::quality::QualityMetrics;
     
     // Authorization check: Users can only enhance themselves unless they're root
     if peer_cred.uid != 0 {
@@ -1721,10 +3283,7 @@ fn handle_enhance_request(
         request.username, peer_cred.uid);
     
     // Create user store
-    let store = match UserStore::new_with_paths(
-        data_dir.join("users"),
-        data_dir.join("enrollment"),
-    ) {
+    let store = match open_user_store(config, data_dir) {
         Ok(s) => s,
         Err(e) => {
             return Response::Enhance(EnhanceResponse {
@@ -1751,10 +3310,21 @@ fn handle_enhance_request(
         }
     };
     
-    let embeddings_before = user_data.embeddings.len();
+    let embeddings_before = match user_data.profile(&request.profile) {
+        Some(profile) => profile.embeddings.len(),
+        None => {
+            return Response::Enhance(EnhanceResponse {
+                success: false,
+                message: format!("User {} has no profile '{}'. Please enroll it first.", request.username, request.profile),
+                embeddings_before: 0,
+                embeddings_after: 0,
+                replaced_count: 0,
+            });
+        }
+    };
     
     // Get enrollment images directory
-    let enrollment_dir = match store.get_enrollment_images_dir(&request.username) {
+    let enrollment_dir = match store.get_profile_images_dir(&request.username, &request.profile) {
         Ok(dir) => {
             if let Err(e) = std::fs::create_dir_all(&dir) {
                 return Response::Enhance(EnhanceResponse {
@@ -1779,8 +3349,11 @@ fn handle_enhance_request(
     };
     
     // Create camera just for this enhancement
-    let mut camera = match Camera::new(config) {
-        Ok(c) => c,
+    let mut camera = match camera::open(config) {
+        Ok(c) => {
+            stats.record_camera(c.as_ref());
+            c
+        }
         Err(e) => {
             return Response::Enhance(EnhanceResponse {
                 success: false,
@@ -1796,7 +3369,6 @@ fn handle_enhance_request(
     let mut new_embeddings = Vec::new();
     let mut new_quality_scores = Vec::new();
     let additional_captures = request.additional_captures.unwrap_or(3);
-    let min_quality = config.enrollment.min_enrollment_quality;
     
     tracing::info!("Capturing {} additional images for enhancement", additional_captures);
     
@@ -1835,6 +3407,17 @@ fn handle_enhance_request(
     }
     
     while captured < additional_captures && enhancement_start.elapsed() < enhancement_timeout {
+        // Stop as soon as the client hangs up or cancels
+        if let Some(reason) = watch.check() {
+            tracing::info!("Enhancement for {} cancelled: {}", request.username, reason);
+            return Response::Enhance(EnhanceResponse {
+                success: false,
+                message: format!("Enhancement cancelled: {}", reason),
+                embeddings_before,
+                embeddings_after: embeddings_before,
+                replaced_count: 0,
+            });
+        }
         
         // Capture frame
         let frame = match session.capture_frame() {
@@ -1858,18 +3441,13 @@ fn handle_enhance_request(
         
         let face = &faces[0];
         
-        // Calculate quality metrics
-        let quality = QualityMetrics::calculate(&frame, face);
-        
-        // Check if quality meets requirements
-        if !quality.meets_minimum_requirements(min_quality) {
-            tracing::debug!("Image quality too low: {:.2}", quality.overall_score);
-            continue;
-        }
-        
-        // Get embedding
-        let embedding = match recognizer.get_embedding(&frame, face) {
-            Ok(e) => e,
+        // Quality check and embedding, shared with `suplinux evaluate`
+        let (embedding, quality) = match enrollment::enrollment_sample(recognizer, &frame, face, &config.enrollment) {
+            Ok(EnrollmentSample::Accepted { embedding, quality }) => (embedding, quality),
+            Ok(EnrollmentSample::LowQuality(quality)) => {
+                tracing::debug!("Image quality too low: {:.2} {:?}", quality.overall_score, quality.failed_minimums(&config.enrollment));
+                continue;
+            }
             Err(e) => {
                 tracing::warn!("Failed to get embedding: {}", e);
                 continue;
@@ -1902,17 +3480,15 @@ fn handle_enhance_request(
     }
     
     // Merge new embeddings with existing data
-    let (added_count, replaced_count) = store.merge_user_data(
-        &mut user_data,
-        new_embeddings,
-        new_quality_scores,
-        request.replace_weak
-    );
+    let (added_count, replaced_count) = match user_data.profile_mut(&request.profile) {
+        Some(profile) => store.merge_profile(profile, new_embeddings, new_quality_scores, request.replace_weak),
+        None => (0, 0),
+    };
     
     // Save updated user data
     match store.save_user_data(&user_data) {
         Ok(_) => {
-            let embeddings_after = user_data.embeddings.len();
+            let embeddings_after = user_data.profile(&request.profile).map_or(0, |p| p.embeddings.len());
             tracing::info!("Successfully enhanced user: {} (before: {}, after: {}, replaced: {})", 
                          request.username, embeddings_before, embeddings_after, replaced_count);
             Response::Enhance(EnhanceResponse {
@@ -1943,4 +3519,83 @@ fn handle_enhance_request(
             })
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    // Nobody has this UID, so it can't resolve to the target user
+    const FOREIGN_UID: u32 = u32::MAX - 1;
+    
+    fn peer(uid: u32) -> PeerCredentials {
+        PeerCredentials { pid: std::process::id(), uid, _gid: uid }
+    }
+    
+    fn auth_config() -> AuthConfig {
+        AuthConfig {
+            similarity_threshold: 0.6,
+            timeout_seconds: 5,
+            detection_confidence: 0.5,
+            k_required_matches: 2,
+            n_total_attempts: 3,
+            embedding_buffer_size: 3,
+            use_embedding_fusion: true,
+            lost_face_timeout: 3,
+            max_failures: 1,
+            lockout_seconds: 60,
+            lockout_backoff: 2.0,
+            max_lockout_seconds: 3600,
+        }
+    }
+    
+    fn lockout_store(name: &str) -> LockoutStore {
+        let dir = std::env::temp_dir().join(format!("suplinux-service-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        LockoutStore::new(dir)
+    }
+    
+    #[test]
+    fn foreign_failure_does_not_touch_lockout() {
+        let lockouts = lockout_store("foreign");
+        
+        let result = update_lockout(&lockouts, &peer(FOREIGN_UID), "root", false, true, &auth_config()).unwrap();
+        assert_eq!(result, None);
+        
+        let record = lockouts.get("root").unwrap();
+        assert_eq!(record.failures, 0);
+        assert_eq!(record.lockouts, 0);
+        assert!(record.locked_until.is_none());
+    }
+    
+    #[test]
+    fn root_failure_counts() {
+        let lockouts = lockout_store("root");
+        
+        let result = update_lockout(&lockouts, &peer(0), "root", false, true, &auth_config()).unwrap();
+        assert_eq!(result, Some(Duration::from_secs(60)));
+        assert!(lockouts.check("root").unwrap().is_some());
+    }
+    
+    #[test]
+    fn foreign_caller_is_turned_away() {
+        let request = AuthRequest {
+            username: "root".to_string(),
+            challenge: vec![7; 32],
+            timestamp: SystemTime::now(),
+            pam_service: Some("sudo".to_string()),
+            pam_tty: None,
+            pam_rhost: None,
+            stream_progress: false,
+        };
+        
+        match check_caller(&request, &peer(FOREIGN_UID), b"secret") {
+            Some(Response::Auth(response)) => {
+                assert!(!response.success);
+                assert_eq!(response.challenge, request.challenge);
+            }
+            other => panic!("expected a signed failure, got {:?}", other),
+        }
+        assert!(check_caller(&request, &peer(0), b"secret").is_none());
+    }
+}
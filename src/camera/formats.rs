@@ -0,0 +1,276 @@
+use crate::common::{FaceAuthError, Result};
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma, Rgb};
+use v4l::FourCC;
+
+/// Capture formats we can decode, in order of preference
+///
+/// Native grayscale comes first because IR sensors deliver it and the
+/// detector and recognizer work on luma anyway. Uncompressed colour beats
+/// MJPG, which costs a JPEG decode per frame and adds block artefacts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Grey,
+    Y16,
+    Yuyv,
+    Nv12,
+    Mjpeg,
+}
+
+impl PixelFormat {
+    pub const PREFERENCE: [PixelFormat; 5] = [
+        PixelFormat::Grey,
+        PixelFormat::Y16,
+        PixelFormat::Yuyv,
+        PixelFormat::Nv12,
+        PixelFormat::Mjpeg,
+    ];
+
+    pub fn from_fourcc(fourcc: &FourCC) -> Option<Self> {
+        match &fourcc.repr {
+            b"GREY" | b"Y8  " => Some(PixelFormat::Grey),
+            b"Y16 " => Some(PixelFormat::Y16),
+            b"YUYV" => Some(PixelFormat::Yuyv),
+            b"NV12" => Some(PixelFormat::Nv12),
+            b"MJPG" | b"JPEG" => Some(PixelFormat::Mjpeg),
+            _ => None,
+        }
+    }
+
+    pub fn fourcc(&self) -> FourCC {
+        match self {
+            PixelFormat::Grey => FourCC::new(b"GREY"),
+            PixelFormat::Y16 => FourCC::new(b"Y16 "),
+            PixelFormat::Yuyv => FourCC::new(b"YUYV"),
+            PixelFormat::Nv12 => FourCC::new(b"NV12"),
+            PixelFormat::Mjpeg => FourCC::new(b"MJPG"),
+        }
+    }
+
+    /// Pick the most preferred format out of what the device offers
+    pub fn best_of(available: &[FourCC]) -> Option<Self> {
+        Self::PREFERENCE.into_iter()
+            .find(|format| available.iter().any(|fourcc| Self::from_fourcc(fourcc) == Some(*format)))
+    }
+
+    /// Decode one captured buffer. `stride` is the length of a row in bytes
+    /// as reported by the driver (0 means tightly packed).
+    pub fn decode(&self, data: &[u8], width: u32, height: u32, stride: u32) -> Result<DynamicImage> {
+        let (w, h) = (width as usize, height as usize);
+        match self {
+            PixelFormat::Grey => {
+                let stride = row_stride(stride, w);
+                check_len(data, stride * h, "GREY")?;
+                let mut pixels = Vec::with_capacity(w * h);
+                for row in data.chunks(stride).take(h) {
+                    pixels.extend_from_slice(&row[..w]);
+                }
+                luma8(pixels, width, height)
+            }
+            PixelFormat::Y16 => {
+                // Little-endian 16-bit samples, full range
+                let stride = row_stride(stride, w * 2);
+                check_len(data, stride * h, "Y16")?;
+                let mut pixels = Vec::with_capacity(w * h);
+                for row in data.chunks(stride).take(h) {
+                    pixels.extend(row[..w * 2].chunks_exact(2).map(|p| u16::from_le_bytes([p[0], p[1]])));
+                }
+                let buffer = ImageBuffer::<Luma<u16>, _>::from_raw(width, height, pixels)
+                    .ok_or_else(|| FaceAuthError::Camera("Failed to create Y16 image buffer".into()))?;
+                Ok(DynamicImage::ImageLuma16(buffer))
+            }
+            PixelFormat::Yuyv => {
+                // Y0 U Y1 V: two pixels share one chroma pair
+                check_even_width(w, "YUYV")?;
+                let stride = row_stride(stride, w * 2);
+                check_len(data, stride * h, "YUYV")?;
+                let mut pixels = Vec::with_capacity(w * h * 3);
+                for row in data.chunks(stride).take(h) {
+                    for pair in row[..w * 2].chunks_exact(4) {
+                        let (u, v) = (pair[1], pair[3]);
+                        pixels.extend_from_slice(&yuv_to_rgb(pair[0], u, v));
+                        pixels.extend_from_slice(&yuv_to_rgb(pair[2], u, v));
+                    }
+                }
+                rgb8(pixels, width, height)
+            }
+            PixelFormat::Nv12 => {
+                // Full-size Y plane followed by a half-size interleaved UV plane.
+                // Each UV pair covers two columns, so every chroma row holds
+                // exactly `w` bytes only for even widths.
+                check_even_width(w, "NV12")?;
+                let stride = row_stride(stride, w);
+                let chroma_rows = h.div_ceil(2);
+                check_len(data, stride * (h + chroma_rows), "NV12")?;
+                let (y_plane, uv_plane) = data.split_at(stride * h);
+                let mut pixels = Vec::with_capacity(w * h * 3);
+                for y in 0..h {
+                    let luma = &y_plane[y * stride..y * stride + w];
+                    let chroma = &uv_plane[(y / 2) * stride..];
+                    for (x, &l) in luma.iter().enumerate() {
+                        let uv = (x / 2) * 2;
+                        pixels.extend_from_slice(&yuv_to_rgb(l, chroma[uv], chroma[uv + 1]));
+                    }
+                }
+                rgb8(pixels, width, height)
+            }
+            PixelFormat::Mjpeg => {
+                image::load_from_memory_with_format(data, ImageFormat::Jpeg)
+                    .map_err(|e| FaceAuthError::Camera(format!("Failed to decode MJPG frame: {}", e)))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.fourcc().str().unwrap_or("?").trim_end())
+    }
+}
+
+fn row_stride(stride: u32, packed: usize) -> usize {
+    (stride as usize).max(packed)
+}
+
+fn check_len(data: &[u8], needed: usize, format: &str) -> Result<()> {
+    if data.len() < needed {
+        return Err(FaceAuthError::Camera(format!(
+            "Short {} frame: got {} bytes, expected {}", format, data.len(), needed
+        )));
+    }
+    Ok(())
+}
+
+// 4:2:2 and 4:2:0 formats pair columns up for chroma
+fn check_even_width(width: usize, format: &str) -> Result<()> {
+    if width % 2 != 0 {
+        return Err(FaceAuthError::Camera(format!(
+            "{} frames need an even width, got {}", format, width
+        )));
+    }
+    Ok(())
+}
+
+fn luma8(pixels: Vec<u8>, width: u32, height: u32) -> Result<DynamicImage> {
+    let buffer = ImageBuffer::<Luma<u8>, _>::from_raw(width, height, pixels)
+        .ok_or_else(|| FaceAuthError::Camera("Failed to create grayscale image buffer".into()))?;
+    Ok(DynamicImage::ImageLuma8(buffer))
+}
+
+fn rgb8(pixels: Vec<u8>, width: u32, height: u32) -> Result<DynamicImage> {
+    let buffer = ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, pixels)
+        .ok_or_else(|| FaceAuthError::Camera("Failed to create RGB image buffer".into()))?;
+    Ok(DynamicImage::ImageRgb8(buffer))
+}
+
+// BT.601 limited range, as produced by UVC webcams
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = (y as i32 - 16).max(0) * 298;
+    let d = u as i32 - 128;
+    let e = v as i32 - 128;
+
+    let clamp = |x: i32| ((x + 128) >> 8).clamp(0, 255) as u8;
+    [
+        clamp(c + 409 * e),
+        clamp(c - 100 * d - 208 * e),
+        clamp(c + 516 * d),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BT.601 limited range: black, white and pure red
+    const BLACK: [u8; 3] = [16, 128, 128];
+    const WHITE: [u8; 3] = [235, 128, 128];
+    const RED: [u8; 3] = [81, 90, 240];
+
+    fn rgb_pixels(image: &DynamicImage) -> Vec<[u8; 3]> {
+        image.as_rgb8().unwrap().pixels().map(|p| p.0).collect()
+    }
+
+    fn is_short_frame(result: Result<DynamicImage>) -> bool {
+        matches!(result, Err(FaceAuthError::Camera(msg)) if msg.starts_with("Short"))
+    }
+
+    #[test]
+    fn yuv_reference_colours() {
+        assert_eq!(yuv_to_rgb(BLACK[0], BLACK[1], BLACK[2]), [0, 0, 0]);
+        assert_eq!(yuv_to_rgb(WHITE[0], WHITE[1], WHITE[2]), [255, 255, 255]);
+        assert_eq!(yuv_to_rgb(RED[0], RED[1], RED[2]), [255, 0, 0]);
+    }
+
+    #[test]
+    fn grey_skips_row_padding() {
+        let data = [1, 2, 3, 0xee, 0xee, 4, 5, 6, 0xee, 0xee];
+        let image = PixelFormat::Grey.decode(&data, 3, 2, 5).unwrap();
+        assert_eq!(image.as_luma8().unwrap().as_raw(), &[1, 2, 3, 4, 5, 6]);
+
+        let packed = PixelFormat::Grey.decode(&[1, 2, 3, 4, 5, 6], 3, 2, 0).unwrap();
+        assert_eq!(packed.as_luma8().unwrap().as_raw(), &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn y16_is_little_endian_with_padding() {
+        let data = [0x34, 0x12, 0xff, 0xff, 0xee, 0xee, 0x01, 0x00, 0x00, 0x80, 0xee, 0xee];
+        let image = PixelFormat::Y16.decode(&data, 2, 2, 6).unwrap();
+        assert_eq!(image.as_luma16().unwrap().as_raw(), &[0x1234, 0xffff, 0x0001, 0x8000]);
+    }
+
+    #[test]
+    fn yuyv_shares_chroma_between_pixel_pairs() {
+        let row = |y0: u8, y1: u8, [_, u, v]: [u8; 3]| [y0, u, y1, v, 0xee, 0xee];
+        let data = [row(WHITE[0], BLACK[0], WHITE), row(RED[0], RED[0], RED)].concat();
+        let image = PixelFormat::Yuyv.decode(&data, 2, 2, 6).unwrap();
+        assert_eq!(rgb_pixels(&image), [[255, 255, 255], [0, 0, 0], [255, 0, 0], [255, 0, 0]]);
+    }
+
+    #[test]
+    fn nv12_subsamples_chroma_in_both_directions() {
+        // 4x2 luma, one chroma row: white/black on the left, red on the right
+        let data = [
+            WHITE[0], BLACK[0], RED[0], RED[0], 0xee, 0xee,
+            BLACK[0], WHITE[0], RED[0], RED[0], 0xee, 0xee,
+            WHITE[1], WHITE[2], RED[1], RED[2], 0xee, 0xee,
+        ];
+        let image = PixelFormat::Nv12.decode(&data, 4, 2, 6).unwrap();
+        let red = [255, 0, 0];
+        assert_eq!(rgb_pixels(&image), [
+            [255, 255, 255], [0, 0, 0], red, red,
+            [0, 0, 0], [255, 255, 255], red, red,
+        ]);
+    }
+
+    #[test]
+    fn nv12_with_odd_height_has_a_last_chroma_row() {
+        let data = [WHITE[0], WHITE[0], WHITE[0], WHITE[0], WHITE[0], WHITE[0], 128, 128, 128, 128];
+        let image = PixelFormat::Nv12.decode(&data, 2, 3, 0).unwrap();
+        assert_eq!(rgb_pixels(&image), [[255, 255, 255]; 6]);
+    }
+
+    #[test]
+    fn odd_widths_are_rejected_for_subsampled_chroma() {
+        // 3x1 NV12 with stride == width would read past the last chroma row
+        let nv12 = PixelFormat::Nv12.decode(&[16, 16, 16, 128, 128, 128], 3, 1, 0);
+        assert!(matches!(nv12, Err(FaceAuthError::Camera(msg)) if msg.contains("even width")));
+        let yuyv = PixelFormat::Yuyv.decode(&[16; 8], 3, 1, 0);
+        assert!(matches!(yuyv, Err(FaceAuthError::Camera(msg)) if msg.contains("even width")));
+    }
+
+    #[test]
+    fn short_frames_are_errors() {
+        assert!(is_short_frame(PixelFormat::Grey.decode(&[0; 5], 3, 2, 0)));
+        // The padding of the last row counts too
+        assert!(is_short_frame(PixelFormat::Grey.decode(&[0; 7], 3, 2, 4)));
+        assert!(is_short_frame(PixelFormat::Y16.decode(&[0; 7], 2, 2, 0)));
+        assert!(is_short_frame(PixelFormat::Yuyv.decode(&[0; 7], 2, 2, 0)));
+        assert!(is_short_frame(PixelFormat::Nv12.decode(&[0; 5], 2, 2, 0)));
+    }
+
+    #[test]
+    fn preference_order() {
+        let offered = [FourCC::new(b"MJPG"), FourCC::new(b"YUYV"), FourCC::new(b"Y16 ")];
+        assert_eq!(PixelFormat::best_of(&offered), Some(PixelFormat::Y16));
+        assert_eq!(PixelFormat::best_of(&[FourCC::new(b"H264")]), None);
+    }
+}
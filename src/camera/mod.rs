@@ -1,3 +1,9 @@
 pub mod v4l2;
+pub mod formats;
+pub mod source;
+pub mod replay;
 
-pub use v4l2::{Camera, CameraSession};
\ No newline at end of file
+pub use v4l2::{Camera, CameraSession};
+pub use formats::PixelFormat;
+pub use source::{FrameSource, FrameStream, open};
+pub use replay::{ReplaySource, SessionRecording, RecordedFrame};
\ No newline at end of file
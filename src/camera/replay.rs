@@ -0,0 +1,230 @@
+use crate::common::{FaceAuthError, Result, Config};
+use crate::camera::source::{FrameSource, FrameStream};
+use crate::core::recording::AuthTrace;
+use crate::storage::encryption;
+use image::{DynamicImage, ImageFormat};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Session files start with this, followed by a u16 format version and a bincode body
+const RECORDING_MAGIC: &[u8; 6] = b"SUPREC";
+const RECORDING_VERSION: u16 = 5;
+
+/// Frames captured during one session, stored as PNG so IR and colour
+/// frames round-trip exactly
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SessionRecording {
+    pub frames: Vec<RecordedFrame>,
+    /// What the service decided, for recordings of authentication attempts
+    pub auth: Option<AuthTrace>,
+}
+
+// Format v1 held frames only; v2 to v4 traces predate face landmarks,
+// pose-tagged templates and enrollment profiles, so only their frames (which
+// come first in the body) are kept
+#[derive(Deserialize)]
+struct SessionRecordingV1 {
+    frames: Vec<RecordedFrame>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    /// Time since the session started
+    pub offset_ms: u64,
+    pub png: Vec<u8>,
+}
+
+impl RecordedFrame {
+    pub fn encode(offset_ms: u64, image: &DynamicImage) -> Result<Self> {
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        Ok(Self { offset_ms, png })
+    }
+
+    pub fn decode(&self) -> Result<DynamicImage> {
+        Ok(image::load_from_memory_with_format(&self.png, ImageFormat::Png)?)
+    }
+}
+
+impl SessionRecording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path)?;
+        let body = data.strip_prefix(RECORDING_MAGIC.as_slice())
+            .ok_or_else(|| FaceAuthError::Other(anyhow::anyhow!("{} is not a session recording", path.display())))?;
+        if body.len() < 2 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!("{} is truncated", path.display())));
+        }
+
+        let version = u16::from_le_bytes([body[0], body[1]]);
+        let read_error = |e| FaceAuthError::Other(anyhow::anyhow!("Failed to read {}: {}", path.display(), e));
+        match version {
+            1..=4 => {
+                let v1: SessionRecordingV1 = bincode::deserialize(&body[2..]).map_err(read_error)?;
+                Ok(Self { frames: v1.frames, auth: None })
+            }
+            RECORDING_VERSION => bincode::deserialize(&body[2..]).map_err(read_error),
+            _ => Err(FaceAuthError::Other(anyhow::anyhow!(
+                "{} has recording format v{}, this build reads up to v{}", path.display(), version, RECORDING_VERSION
+            ))),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let body = bincode::serialize(self)
+            .map_err(|e| FaceAuthError::Other(anyhow::anyhow!("Failed to serialize recording: {}", e)))?;
+
+        let mut data = Vec::with_capacity(RECORDING_MAGIC.len() + 2 + body.len());
+        data.extend_from_slice(RECORDING_MAGIC);
+        data.extend_from_slice(&RECORDING_VERSION.to_le_bytes());
+        data.extend_from_slice(&body);
+
+        // Frames and templates are biometric data
+        encryption::write_private_file(path, &data)
+    }
+
+    /// Whether `path` looks like a session file rather than a frame directory
+    pub fn is_recording(path: &Path) -> bool {
+        let mut magic = [0u8; 6];
+        path.is_file()
+            && fs::File::open(path)
+                .and_then(|mut f| std::io::Read::read_exact(&mut f, &mut magic))
+                .is_ok()
+            && magic == *RECORDING_MAGIC
+    }
+}
+
+enum ReplayFrames {
+    Directory(Vec<PathBuf>),
+    Recording(SessionRecording),
+}
+
+impl ReplayFrames {
+    fn len(&self) -> usize {
+        match self {
+            ReplayFrames::Directory(paths) => paths.len(),
+            ReplayFrames::Recording(recording) => recording.frames.len(),
+        }
+    }
+
+    fn load(&self, index: usize) -> Result<DynamicImage> {
+        match self {
+            ReplayFrames::Directory(paths) => image::open(&paths[index])
+                .map_err(|e| FaceAuthError::Camera(format!("Failed to load {}: {}", paths[index].display(), e))),
+            ReplayFrames::Recording(recording) => recording.frames[index].decode(),
+        }
+    }
+}
+
+/// Replays a directory of PNG/JPEG frames (in file name order) or a session
+/// recording in place of a camera
+pub struct ReplaySource {
+    path: PathBuf,
+    frames: ReplayFrames,
+    frame_interval: Option<Duration>,
+    looping: bool,
+    position: usize,
+    last_frame: Option<Instant>,
+}
+
+impl ReplaySource {
+    pub fn new(config: &Config) -> Result<Self> {
+        let path = config.camera.replay_path.clone().ok_or_else(|| {
+            FaceAuthError::Camera("camera.source = \"replay\" needs camera.replay_path".into())
+        })?;
+        Self::open(&path, config.camera.replay_fps, config.camera.replay_loop)
+    }
+
+    /// Open a replay at `fps` frames per second (0 = as fast as frames are read)
+    pub fn open(path: &Path, fps: f32, looping: bool) -> Result<Self> {
+        let frames = if path.is_dir() {
+            let mut paths: Vec<PathBuf> = fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| is_image_file(p))
+                .collect();
+            paths.sort();
+            ReplayFrames::Directory(paths)
+        } else if SessionRecording::is_recording(path) {
+            ReplayFrames::Recording(SessionRecording::load(path)?)
+        } else {
+            return Err(FaceAuthError::Camera(format!(
+                "Replay path {} is neither a frame directory nor a session recording", path.display()
+            )));
+        };
+
+        if frames.len() == 0 {
+            return Err(FaceAuthError::Camera(format!("No frames to replay in {}", path.display())));
+        }
+        tracing::info!("Replaying {} frames from {} at {} fps", frames.len(), path.display(), fps);
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            frames,
+            frame_interval: (fps > 0.0).then(|| Duration::from_secs_f32(1.0 / fps)),
+            looping,
+            position: 0,
+            last_frame: None,
+        })
+    }
+
+    fn next_frame(&mut self) -> Result<DynamicImage> {
+        if self.position >= self.frames.len() {
+            if !self.looping {
+                return Err(FaceAuthError::Camera(format!("Replay of {} finished", self.path.display())));
+            }
+            self.position = 0;
+        }
+
+        // Pace frames like a real camera would
+        if let (Some(interval), Some(last)) = (self.frame_interval, self.last_frame) {
+            if let Some(wait) = interval.checked_sub(last.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        self.last_frame = Some(Instant::now());
+
+        let frame = self.frames.load(self.position);
+        self.position += 1;
+        frame
+    }
+}
+
+impl FrameSource for ReplaySource {
+    fn start_session(&mut self) -> Result<Box<dyn FrameStream + '_>> {
+        Ok(Box::new(ReplayStream { source: self }))
+    }
+
+    fn capture_frame(&mut self) -> Result<DynamicImage> {
+        self.next_frame()
+    }
+
+    fn label(&self) -> String {
+        format!("replay:{}", self.path.display())
+    }
+
+    fn format(&self) -> String {
+        match self.frames {
+            ReplayFrames::Directory(_) => format!("{} image files", self.frames.len()),
+            ReplayFrames::Recording(_) => format!("{} recorded frames", self.frames.len()),
+        }
+    }
+}
+
+pub struct ReplayStream<'a> {
+    source: &'a mut ReplaySource,
+}
+
+impl FrameStream for ReplayStream<'_> {
+    fn capture_frame(&mut self) -> Result<DynamicImage> {
+        self.source.next_frame()
+    }
+}
+
+fn is_image_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref(),
+        Some("png" | "jpg" | "jpeg")
+    )
+}
@@ -0,0 +1,58 @@
+use crate::common::{Config, Result};
+use crate::common::config::FrameSourceKind;
+use crate::camera::{Camera, CameraSession, ReplaySource};
+use image::DynamicImage;
+
+/// Anything that can supply camera frames: a V4L2 device or a replay of
+/// recorded frames, so the auth and enrollment pipelines can run without hardware
+pub trait FrameSource {
+    /// Start streaming for a run of captures (includes any warmup)
+    fn start_session(&mut self) -> Result<Box<dyn FrameStream + '_>>;
+
+    /// Capture a single frame outside a session
+    fn capture_frame(&mut self) -> Result<DynamicImage>;
+
+    /// Where frames come from, for logs and the audit trail
+    fn label(&self) -> String;
+
+    /// Resolution and pixel format frames arrive in, for diagnostics
+    fn format(&self) -> String;
+}
+
+/// Frames from a started `FrameSource`
+pub trait FrameStream {
+    fn capture_frame(&mut self) -> Result<DynamicImage>;
+}
+
+/// Open the frame source selected by `[camera] source` in the config
+pub fn open(config: &Config) -> Result<Box<dyn FrameSource>> {
+    match config.camera.source {
+        FrameSourceKind::V4l2 => Ok(Box::new(Camera::new(config)?)),
+        FrameSourceKind::Replay => Ok(Box::new(ReplaySource::new(config)?)),
+    }
+}
+
+impl FrameSource for Camera {
+    fn start_session(&mut self) -> Result<Box<dyn FrameStream + '_>> {
+        Ok(Box::new(Camera::start_session(self)?))
+    }
+
+    fn capture_frame(&mut self) -> Result<DynamicImage> {
+        Camera::capture_frame(self)
+    }
+
+    fn label(&self) -> String {
+        format!("/dev/video{}", self.device_index())
+    }
+
+    fn format(&self) -> String {
+        let (width, height) = self.resolution();
+        format!("{}x{} {:?}", width, height, self.pixel_format())
+    }
+}
+
+impl FrameStream for CameraSession<'_> {
+    fn capture_frame(&mut self) -> Result<DynamicImage> {
+        CameraSession::capture_frame(self)
+    }
+}
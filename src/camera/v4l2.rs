@@ -1,14 +1,18 @@
 use crate::common::{FaceAuthError, Result, Config};
+use crate::camera::formats::PixelFormat;
 use v4l::buffer::Type;
 use v4l::io::traits::CaptureStream;
 use v4l::video::Capture;
-use v4l::{Device, FourCC};
-use image::{DynamicImage, ImageBuffer, Luma};
+use v4l::Device;
+use image::DynamicImage;
 use std::fs;
 
 pub struct Camera {
     device: Device,
     config: Config,
+    index: u32,
+    pixel_format: PixelFormat,
+    resolution: (u32, u32),
 }
 
 // Helper to work around lifetime issues
@@ -16,6 +20,7 @@ pub struct CameraSession<'a> {
     camera: &'a mut Camera,
     stream: v4l::io::mmap::Stream<'a>,
     format: v4l::Format,
+    pixel_format: PixelFormat,
 }
 
 impl Camera {
@@ -62,11 +67,13 @@ impl Camera {
                                 
                                 for fmt in &formats {
                                     let fourcc_str = fmt.fourcc.str().unwrap_or("UNKNOWN");
-                                    if fourcc_str == "GREY" || fourcc_str == "Y8" || fourcc_str == "Y16" {
-                                        features.push(format!("Grayscale ({})", fourcc_str));
-                                        likely_ir = true;
-                                    } else if fourcc_str == "MJPG" || fourcc_str == "YUYV" {
-                                        features.push(format!("Color ({})", fourcc_str));
+                                    match PixelFormat::from_fourcc(&fmt.fourcc) {
+                                        Some(PixelFormat::Grey | PixelFormat::Y16) => {
+                                            features.push(format!("Grayscale ({})", fourcc_str.trim_end()));
+                                            likely_ir = true;
+                                        }
+                                        Some(_) => features.push(format!("Color ({})", fourcc_str.trim_end())),
+                                        None => features.push(format!("Unsupported ({})", fourcc_str.trim_end())),
                                     }
                                 }
                                 
@@ -133,6 +140,10 @@ impl Camera {
                                         println!("Found potential IR camera at /dev/video{}: {}", 
                                                 index, caps.card);
                                         candidates.push((index, caps.card.clone(), 50)); // Medium priority
+                                    } else if formats.iter().any(|fmt| PixelFormat::from_fourcc(&fmt.fourcc).is_some()) {
+                                        println!("Found color camera at /dev/video{}: {}", 
+                                                index, caps.card);
+                                        candidates.push((index, caps.card.clone(), 10)); // Fallback
                                     }
                                 }
                             }
@@ -145,11 +156,14 @@ impl Camera {
         // Sort by priority (highest first)
         candidates.sort_by(|a, b| b.2.cmp(&a.2));
         
-        if let Some((index, name, _)) = candidates.first() {
+        if let Some((index, name, priority)) = candidates.first() {
             println!("Selected camera: /dev/video{} ({})", index, name);
+            if *priority < 50 {
+                println!("No IR camera found - using a color camera, which is easier to spoof");
+            }
             Ok(*index)
         } else {
-            // No IR camera found, fall back to default camera
+            // No usable camera found, fall back to default camera
             println!("No IR camera detected, falling back to default camera (device 0)");
             println!("For better accuracy, consider using an IR camera or specifying device_index in config");
             Ok(0)
@@ -188,10 +202,18 @@ impl Camera {
         fmt.width = config.camera.width;
         fmt.height = config.camera.height;
 
-        // Keep GREY format for IR camera, otherwise use MJPG
-        if fmt.fourcc.str().unwrap() != "GREY" {
-            fmt.fourcc = FourCC::new(b"MJPG");
-        }
+        // Pick the best format we can decode out of what the device offers
+        let available: Vec<_> = device.enum_formats()
+            .map_err(|e| FaceAuthError::Camera(format!("Failed to enumerate formats: {}", e)))?
+            .into_iter()
+            .map(|desc| desc.fourcc)
+            .collect();
+        let preferred = PixelFormat::best_of(&available).ok_or_else(|| FaceAuthError::Camera(format!(
+            "Camera {} offers no supported format (have {}; need one of GREY, Y16, YUYV, NV12, MJPG)",
+            index,
+            available.iter().map(|f| f.str().unwrap_or("?").trim_end().to_string()).collect::<Vec<_>>().join(", ")
+        )))?;
+        fmt.fourcc = preferred.fourcc();
 
         println!("Attempting to set format: {}x{} {}", fmt.width, fmt.height, fmt.fourcc.str().unwrap());
         
@@ -205,7 +227,12 @@ impl Camera {
         let final_fmt = device.format()
             .map_err(|e| FaceAuthError::Camera(format!("Failed to get final format: {}", e)))?;
         
-        println!("Actual format: {}x{} {}", final_fmt.width, final_fmt.height, final_fmt.fourcc.str().unwrap());
+        println!("Actual format: {}x{} {}", final_fmt.width, final_fmt.height, final_fmt.fourcc.str().unwrap_or("?"));
+        
+        // The driver may have kept a different format than the one we asked for
+        let pixel_format = PixelFormat::from_fourcc(&final_fmt.fourcc).ok_or_else(|| FaceAuthError::Camera(format!(
+            "Camera {} is using unsupported format {}", index, final_fmt.fourcc.str().unwrap_or("?")
+        )))?;
         
         // Warn if resolution differs significantly from requested
         if final_fmt.width != config.camera.width || final_fmt.height != config.camera.height {
@@ -214,7 +241,13 @@ impl Camera {
                      config.camera.width, config.camera.height);
         }
 
-        Ok(Self { device, config })
+        let resolution = (final_fmt.width, final_fmt.height);
+        Ok(Self { device, config, index, pixel_format, resolution })
+    }
+    
+    /// Index of the opened /dev/video device (after auto-detection)
+    pub fn device_index(&self) -> u32 {
+        self.index
     }
 
     pub fn capture_frame(&mut self) -> Result<DynamicImage> {
@@ -238,10 +271,7 @@ impl Camera {
         let (buf, _meta) = stream.next()
             .map_err(|e| FaceAuthError::Camera(format!("Failed to capture: {}", e)))?;
 
-        match fmt.fourcc.str().unwrap() {
-            "GREY" => self.grey_to_image(&buf, fmt.width, fmt.height),
-            _ => Err(FaceAuthError::Camera("Unsupported format".into())),
-        }
+        self.pixel_format.decode(&buf, fmt.width, fmt.height, fmt.stride)
     }
     
     // Start a streaming session for multiple captures
@@ -261,18 +291,23 @@ impl Camera {
         }
         println!("Camera ready");
             
+        let pixel_format = self.pixel_format;
         Ok(CameraSession {
             camera: self,
             stream,
             format: fmt,
+            pixel_format,
         })
     }
-
-    fn grey_to_image(&self, data: &[u8], width: u32, height: u32) -> Result<DynamicImage> {
-        let img_buffer = ImageBuffer::<Luma<u8>, _>::from_raw(width, height, data.to_vec())
-            .ok_or_else(|| FaceAuthError::Camera("Failed to create grayscale image buffer".into()))?;
-
-        Ok(DynamicImage::ImageLuma8(img_buffer))
+    
+    /// Capture format chosen when the device was opened
+    pub fn pixel_format(&self) -> PixelFormat {
+        self.pixel_format
+    }
+    
+    /// Width and height the driver settled on, which may differ from the config
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
     }
 }
 
@@ -282,10 +317,7 @@ impl<'a> CameraSession<'a> {
         let (buf, _meta) = self.stream.next()
             .map_err(|e| FaceAuthError::Camera(format!("Failed to capture: {}", e)))?;
 
-        match self.format.fourcc.str().unwrap() {
-            "GREY" => self.camera.grey_to_image(&buf, self.format.width, self.format.height),
-            _ => Err(FaceAuthError::Camera("Unsupported format".into())),
-        }
+        self.pixel_format.decode(&buf, self.format.width, self.format.height, self.format.stride)
     }
     
     pub fn capture_frame_with_warmup(&mut self, warmup_frames: u32) -> Result<DynamicImage> {
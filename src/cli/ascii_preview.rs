@@ -40,6 +40,7 @@ impl AsciiRenderer {
         faces: &[FaceBox],
         captured: usize,
         total: usize,
+        prompt: Option<&str>,
     ) -> String {
         let mut grid = self.image_to_ascii(image);
         
@@ -56,7 +57,11 @@ impl AsciiRenderer {
             let face_center_x = (face_x1 + face_x2) / 2;
             
             // Draw message first, 2 lines above face
-            let msg = if captured < total { "Move head slightly" } else { "Complete!" };
+            let msg = if captured >= total {
+                "Complete!"
+            } else {
+                prompt.unwrap_or("Move head slightly")
+            };
             let msg_x = face_center_x.saturating_sub(msg.len() / 2) + 10;  // Add 4 spaces offset to the right
             let msg_y = face_y1.saturating_sub(2).max(0);
             self.overlay_text(&mut grid, msg, msg_x, msg_y);
@@ -1,6 +1,7 @@
 use crate::common::{Result, DevMode};
 use crate::storage::UserStore;
 use crate::core::recognizer::Embedding;
+use crate::core::evaluation::RocPoint;
 use std::fs;
 use std::path::PathBuf;
 
@@ -28,27 +29,30 @@ impl Visualizer {
         
         let mut content = String::new();
         content.push_str(&format!("Similarity Matrix for user: {}\n", username));
-        content.push_str(&format!("Number of embeddings: {}\n", user_data.embeddings.len()));
-        if user_data.averaged_embedding.is_some() {
-            content.push_str("Has averaged embedding: Yes\n");
-        }
-        content.push_str("\n");
+        content.push_str(&format!("Number of embeddings: {}\n", user_data.embedding_count()));
         
-        // Calculate similarity between all pairs of embeddings
-        content.push_str("Pairwise similarities:\n");
-        for i in 0..user_data.embeddings.len() {
-            for j in i+1..user_data.embeddings.len() {
-                let similarity = cosine_similarity(&user_data.embeddings[i], &user_data.embeddings[j]);
-                content.push_str(&format!("Embedding {} vs {}: {:.3}\n", i, j, similarity));
+        for profile in &user_data.profiles {
+            content.push_str(&format!("\nProfile: {} ({} embeddings)\n", profile.name, profile.embeddings.len()));
+            if profile.averaged_embedding.is_some() {
+                content.push_str("Has averaged embedding: Yes\n");
             }
-        }
-        
-        // If averaged embedding exists, compare it with individual embeddings
-        if let Some(ref avg_embedding) = user_data.averaged_embedding {
-            content.push_str("\nSimilarities with averaged embedding:\n");
-            for (i, embedding) in user_data.embeddings.iter().enumerate() {
-                let similarity = cosine_similarity(embedding, avg_embedding);
-                content.push_str(&format!("Embedding {} vs Averaged: {:.3}\n", i, similarity));
+            
+            // Calculate similarity between all pairs of embeddings
+            content.push_str("Pairwise similarities:\n");
+            for i in 0..profile.embeddings.len() {
+                for j in i+1..profile.embeddings.len() {
+                    let similarity = cosine_similarity(&profile.embeddings[i], &profile.embeddings[j]);
+                    content.push_str(&format!("Embedding {} vs {}: {:.3}\n", i, j, similarity));
+                }
+            }
+            
+            // If averaged embedding exists, compare it with individual embeddings
+            if let Some(ref avg_embedding) = profile.averaged_embedding {
+                content.push_str("\nSimilarities with averaged embedding:\n");
+                for (i, embedding) in profile.embeddings.iter().enumerate() {
+                    let similarity = cosine_similarity(embedding, avg_embedding);
+                    content.push_str(&format!("Embedding {} vs Averaged: {:.3}\n", i, similarity));
+                }
             }
         }
         
@@ -65,15 +69,18 @@ impl Visualizer {
         
         let mut content = String::new();
         content.push_str(&format!("Embedding Statistics for user: {}\n", username));
-        content.push_str(&format!("Number of embeddings: {}\n", user_data.embeddings.len()));
+        content.push_str(&format!("Number of embeddings: {}\n", user_data.embedding_count()));
         content.push_str(&format!("Embedding dimension: {}\n", 
-                                user_data.embeddings.first()
+                                user_data.profiles.iter()
+                                    .find_map(|p| p.embeddings.first())
                                     .map(|e| e.len())
                                     .unwrap_or(0)));
         content.push_str("\n");
         
         // Calculate statistics for each embedding
-        for (i, embedding) in user_data.embeddings.iter().enumerate() {
+        let embeddings = user_data.profiles.iter()
+            .flat_map(|p| p.embeddings.iter().enumerate().map(move |(i, e)| (&p.name, i, e)));
+        for (profile, i, embedding) in embeddings {
             let mean = embedding.iter().sum::<f32>() / embedding.len() as f32;
             let variance = embedding.iter()
                 .map(|x| (x - mean).powi(2))
@@ -82,7 +89,7 @@ impl Visualizer {
             let min = embedding.iter().cloned().fold(f32::INFINITY, f32::min);
             let max = embedding.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
             
-            content.push_str(&format!("Embedding {}:\n", i));
+            content.push_str(&format!("Embedding {} ({}):\n", i, profile));
             content.push_str(&format!("  Mean: {:.6}\n", mean));
             content.push_str(&format!("  Std Dev: {:.6}\n", std_dev));
             content.push_str(&format!("  Min: {:.6}\n", min));
@@ -105,30 +112,32 @@ impl Visualizer {
         let mut content = String::new();
         
         // Header
-        if let Some(first_embedding) = user_data.embeddings.first() {
+        if let Some(first_embedding) = user_data.profiles.iter().find_map(|p| p.embeddings.first()) {
             let headers: Vec<String> = (0..first_embedding.len())
                 .map(|i| format!("dim_{}", i))
                 .collect();
-            content.push_str("embedding_id,");
+            content.push_str("profile,embedding_id,");
             content.push_str(&headers.join(","));
             content.push_str("\n");
             
-            // Data
-            for (i, embedding) in user_data.embeddings.iter().enumerate() {
-                content.push_str(&format!("{}", i));
-                for value in embedding {
-                    content.push_str(&format!(",{}", value));
+            for profile in &user_data.profiles {
+                // Data
+                for (i, embedding) in profile.embeddings.iter().enumerate() {
+                    content.push_str(&format!("{},{}", profile.name, i));
+                    for value in embedding {
+                        content.push_str(&format!(",{}", value));
+                    }
+                    content.push_str("\n");
                 }
-                content.push_str("\n");
-            }
-            
-            // Add averaged embedding if it exists
-            if let Some(ref avg_embedding) = user_data.averaged_embedding {
-                content.push_str("averaged");
-                for value in avg_embedding {
-                    content.push_str(&format!(",{}", value));
+                
+                // Add averaged embedding if it exists
+                if let Some(ref avg_embedding) = profile.averaged_embedding {
+                    content.push_str(&format!("{},averaged", profile.name));
+                    for value in avg_embedding {
+                        content.push_str(&format!(",{}", value));
+                    }
+                    content.push_str("\n");
                 }
-                content.push_str("\n");
             }
         }
         
@@ -138,6 +147,71 @@ impl Visualizer {
         
         Ok(())
     }
+    
+    /// Write FAR/FRR per threshold from `suplinux evaluate` as CSV
+    pub fn export_roc_csv(&self, roc: &[RocPoint]) -> Result<()> {
+        let output_file = self.output_dir.join("roc.csv");
+        
+        let mut content = String::from("threshold,far,frr\n");
+        for point in roc {
+            content.push_str(&format!("{:.4},{:.6},{:.6}\n", point.threshold, point.far, point.frr));
+        }
+        
+        fs::write(output_file, content)?;
+        println!("Saved ROC data to visualizations/roc.csv");
+        
+        Ok(())
+    }
+    
+    /// Plot the ROC curve (FAR against FRR, log-scaled FAR) with the EER and
+    /// chosen operating points marked
+    #[cfg(feature = "visualization")]
+    pub fn plot_roc(&self, roc: &[RocPoint], marked: &[(&str, RocPoint)]) -> Result<()> {
+        use plotters::prelude::*;
+        
+        let output_file = self.output_dir.join("roc.png");
+        let plot_error = |e: &dyn std::fmt::Display| crate::common::FaceAuthError::Other(anyhow::anyhow!("Failed to plot ROC: {}", e));
+        
+        // FAR of 0 can't go on a log axis; clamp to the smallest measurable rate
+        let min_far = 1e-4f32;
+        let root = BitMapBackend::new(&output_file, (800, 600)).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| plot_error(&e))?;
+        
+        let mut chart = ChartBuilder::on(&root)
+            .caption("ROC", ("sans-serif", 24))
+            .margin(20)
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .build_cartesian_2d((min_far..1.0f32).log_scale(), 0.0f32..1.0f32)
+            .map_err(|e| plot_error(&e))?;
+        
+        chart.configure_mesh()
+            .x_desc("False accept rate")
+            .y_desc("False reject rate")
+            .draw()
+            .map_err(|e| plot_error(&e))?;
+        
+        chart.draw_series(LineSeries::new(
+            roc.iter().map(|p| (p.far.max(min_far), p.frr)),
+            &BLUE,
+        )).map_err(|e| plot_error(&e))?;
+        
+        for (label, point) in marked {
+            let position = (point.far.max(min_far), point.frr);
+            chart.draw_series(std::iter::once(Circle::new(position, 5, RED.filled())))
+                .map_err(|e| plot_error(&e))?;
+            chart.draw_series(std::iter::once(Text::new(
+                format!("{} ({:.3})", label, point.threshold),
+                position,
+                ("sans-serif", 14),
+            ))).map_err(|e| plot_error(&e))?;
+        }
+        
+        root.present().map_err(|e| plot_error(&e))?;
+        println!("Saved ROC plot to visualizations/roc.png");
+        
+        Ok(())
+    }
 }
 
 // Helper functions
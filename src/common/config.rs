@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use crate::common::error::{FaceAuthError, Result};
 
@@ -14,6 +15,17 @@ pub struct Config {
     pub performance: PerformanceConfig,
     #[serde(default)]
     pub enrollment: EnrollmentConfig,
+    #[serde(default)]
+    pub service: ServiceConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub liveness: LivenessConfig,
+    #[serde(default)]
+    pub adaptive: AdaptiveConfig,
+    /// Per-PAM-service overrides, keyed by PAM_SERVICE (`[policy.sudo]`)
+    #[serde(default)]
+    pub policy: BTreeMap<String, PolicyConfig>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -24,16 +36,41 @@ pub struct CameraConfig {
     pub warmup_frames: u32,
     #[serde(default = "default_warmup_delay")]
     pub warmup_delay_ms: u64,
+    /// Where frames come from: a V4L2 device or a replay of recorded frames
+    #[serde(default)]
+    pub source: FrameSourceKind,
+    /// Frame directory or session recording for `source = "replay"`
+    #[serde(default)]
+    pub replay_path: Option<PathBuf>,
+    /// Replay speed (0 = as fast as frames are read)
+    #[serde(default = "default_replay_fps")]
+    pub replay_fps: f32,
+    /// Start over at the first frame instead of failing when the replay ends
+    #[serde(default = "default_true")]
+    pub replay_loop: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameSourceKind {
+    #[default]
+    V4l2,
+    Replay,
 }
 
 fn default_warmup_delay() -> u64 {
     50
 }
 
+fn default_replay_fps() -> f32 { 15.0 }
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ModelConfig {
     pub detector_path: PathBuf,
     pub recognizer_path: PathBuf,
+    /// Optional anti-spoofing model, see `LivenessConfig`
+    #[serde(default)]
+    pub liveness_path: Option<PathBuf>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -51,6 +88,29 @@ pub struct AuthConfig {
     pub use_embedding_fusion: bool,
     #[serde(default = "default_lost_face_timeout")]
     pub lost_face_timeout: u32,
+    /// Failed attempts before a user is locked out (0 disables lockout)
+    #[serde(default = "default_max_failures")]
+    pub max_failures: u32,
+    /// Length of the first lockout
+    #[serde(default = "default_lockout_seconds")]
+    pub lockout_seconds: u64,
+    /// Each consecutive lockout lasts this many times longer than the last
+    #[serde(default = "default_lockout_backoff")]
+    pub lockout_backoff: f32,
+    #[serde(default = "default_max_lockout_seconds")]
+    pub max_lockout_seconds: u64,
+}
+
+/// Authentication settings for one PAM service; unset fields fall back to `[auth]`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PolicyConfig {
+    /// false turns face authentication off for this service
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub similarity_threshold: Option<f32>,
+    pub k_required_matches: Option<u32>,
+    pub n_total_attempts: Option<u32>,
+    pub timeout_seconds: Option<u32>,
 }
 
 fn default_k_required() -> u32 { 2 }
@@ -58,6 +118,10 @@ fn default_n_attempts() -> u32 { 3 }
 fn default_buffer_size() -> u32 { 3 }
 fn default_true() -> bool { true }
 fn default_lost_face_timeout() -> u32 { 3 }
+fn default_max_failures() -> u32 { 5 }
+fn default_lockout_seconds() -> u64 { 60 }
+fn default_lockout_backoff() -> f32 { 2.0 }
+fn default_max_lockout_seconds() -> u64 { 3600 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DetectorConfig {
@@ -71,13 +135,35 @@ pub struct DetectorConfig {
 pub struct RecognizerConfig {
     pub input_size: u32,
     pub normalization_value: f32,
+    /// Warp faces onto the ArcFace 5-point template when the detector
+    /// provides landmarks, instead of stretching the bounding box
+    #[serde(default = "default_true")]
+    pub align_faces: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct StorageConfig {
     pub enrollment_images_dir: PathBuf,
+    #[serde(default)]
+    pub key_provider: KeyProviderKind,
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+    #[serde(default = "default_keyring_description")]
+    pub keyring_description: String,
+    #[serde(default = "default_true")]
+    pub keyring_file_fallback: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyProviderKind {
+    #[default]
+    File,
+    Keyring,
+}
+
+fn default_keyring_description() -> String { "suplinux:template".to_string() }
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct PerformanceConfig {
     #[serde(default = "default_true")]
@@ -88,7 +174,7 @@ pub struct PerformanceConfig {
 
 fn default_optimization_level() -> u32 { 3 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EnrollmentConfig {
     #[serde(default = "default_true")]
     pub store_averaged_embedding: bool,
@@ -106,8 +192,260 @@ pub struct EnrollmentConfig {
     pub ascii_width: Option<usize>,
     #[serde(default)]
     pub ascii_height: Option<usize>,
+    /// How much each metric contributes to the overall quality score
+    #[serde(default)]
+    pub quality_weights: QualityWeights,
+    /// Captures failing any of these are rejected whatever their overall score
+    #[serde(default)]
+    pub quality_minimums: QualityMinimums,
+    /// Head angles guided enrollment (`suplinux enroll --guided`) prompts through, in order
+    #[serde(default = "default_guided_poses")]
+    pub guided_poses: Vec<PoseBucket>,
+    /// How far to turn or tilt for the left/right/up/down poses, in degrees
+    #[serde(default = "default_guided_pose_angle")]
+    pub guided_pose_angle: f32,
+    /// How close the estimated pose must be to the requested one, in degrees
+    #[serde(default = "default_guided_pose_tolerance")]
+    pub guided_pose_tolerance: f32,
+}
+
+impl Default for EnrollmentConfig {
+    fn default() -> Self {
+        Self {
+            store_averaged_embedding: true,
+            capture_quality_metrics: true,
+            min_enrollment_quality: default_enrollment_quality(),
+            num_captures: default_num_captures(),
+            capture_interval_ms: default_capture_interval(),
+            enable_ascii_preview: default_true_option(),
+            ascii_width: None,
+            ascii_height: None,
+            quality_weights: QualityWeights::default(),
+            quality_minimums: QualityMinimums::default(),
+            guided_poses: default_guided_poses(),
+            guided_pose_angle: default_guided_pose_angle(),
+            guided_pose_tolerance: default_guided_pose_tolerance(),
+        }
+    }
+}
+
+/// Head angle requested during guided enrollment, from the user's point of view
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PoseBucket {
+    Frontal,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+fn default_guided_poses() -> Vec<PoseBucket> {
+    vec![PoseBucket::Frontal, PoseBucket::Left, PoseBucket::Right, PoseBucket::Up, PoseBucket::Down]
+}
+fn default_guided_pose_angle() -> f32 { 15.0 }
+fn default_guided_pose_tolerance() -> f32 { 8.0 }
+
+/// Relative weights of the quality metrics; they are normalized by their sum
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct QualityWeights {
+    pub detection_confidence: f32,
+    pub face_size: f32,
+    pub centering: f32,
+    pub brightness: f32,
+    pub contrast: f32,
+    pub sharpness: f32,
+    pub pose: f32,
+    pub occlusion: f32,
+}
+
+impl Default for QualityWeights {
+    fn default() -> Self {
+        Self {
+            detection_confidence: 0.2,
+            face_size: 0.1,
+            centering: 0.1,
+            brightness: 0.1,
+            contrast: 0.1,
+            sharpness: 0.15,
+            pose: 0.15,
+            occlusion: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct QualityMinimums {
+    pub min_detection_confidence: f32,
+    pub min_brightness: f32,
+    pub min_contrast: f32,
+    /// Sharpness score (0-1) from the variance of the Laplacian over the face
+    pub min_sharpness: f32,
+    pub max_yaw_degrees: f32,
+    pub max_pitch_degrees: f32,
+    pub max_roll_degrees: f32,
+    /// Fraction of eye and mouth regions that must look unobstructed
+    pub min_occlusion_score: f32,
 }
 
+impl Default for QualityMinimums {
+    fn default() -> Self {
+        Self {
+            min_detection_confidence: 0.0,
+            min_brightness: 0.0,
+            min_contrast: 0.0,
+            min_sharpness: 0.3,
+            max_yaw_degrees: 25.0,
+            max_pitch_degrees: 20.0,
+            max_roll_degrees: 20.0,
+            min_occlusion_score: 0.6,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServiceConfig {
+    /// Connections handled concurrently by suplinux-service
+    #[serde(default = "default_worker_threads")]
+    pub worker_threads: usize,
+    /// How long a camera request waits for the camera before "camera busy"
+    #[serde(default = "default_camera_wait_ms")]
+    pub camera_wait_ms: u64,
+    /// When socket activated, exit after this many idle seconds so the models
+    /// aren't kept in memory; systemd restarts the service on the next connection.
+    /// 0 keeps it running.
+    #[serde(default = "default_idle_exit_secs")]
+    pub idle_exit_secs: u64,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            worker_threads: default_worker_threads(),
+            camera_wait_ms: default_camera_wait_ms(),
+            idle_exit_secs: default_idle_exit_secs(),
+        }
+    }
+}
+
+fn default_worker_threads() -> usize { 4 }
+fn default_camera_wait_ms() -> u64 { 2000 }
+fn default_idle_exit_secs() -> u64 { 300 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Rotate the live log once it would exceed this size
+    #[serde(default = "default_audit_max_file_kb")]
+    pub max_file_kb: u64,
+    /// Rotated files to keep before the oldest is deleted
+    #[serde(default = "default_audit_max_files")]
+    pub max_files: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_file_kb: default_audit_max_file_kb(),
+            max_files: default_audit_max_files(),
+        }
+    }
+}
+
+fn default_audit_max_file_kb() -> u64 { 10 * 1024 }
+fn default_audit_max_files() -> usize { 5 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LivenessConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Check IR reflectance against the two minimums below. Off by default:
+    /// a color camera fails them on every frame.
+    #[serde(default)]
+    pub ir_checks: bool,
+    /// Minimum mean face brightness under IR (0-255); screens emit no NIR and look dark
+    #[serde(default = "default_min_ir_brightness")]
+    pub min_ir_brightness: f32,
+    /// Minimum standard deviation over the face box; prints and screens are flat
+    #[serde(default = "default_min_ir_contrast")]
+    pub min_ir_contrast: f32,
+    /// Minimum median change between consecutive face crops (mean absolute
+    /// pixel difference, 0-255) over the motion window
+    #[serde(default = "default_min_motion")]
+    pub min_motion: f32,
+    /// Consecutive frame pairs over which motion is measured
+    #[serde(default = "default_motion_window")]
+    pub motion_window: u32,
+    /// Minimum live probability from `models.liveness_path`, if set
+    #[serde(default = "default_liveness_model_threshold")]
+    pub model_threshold: f32,
+    #[serde(default = "default_liveness_input_size")]
+    pub model_input_size: u32,
+}
+
+impl Default for LivenessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ir_checks: false,
+            min_ir_brightness: default_min_ir_brightness(),
+            min_ir_contrast: default_min_ir_contrast(),
+            min_motion: default_min_motion(),
+            motion_window: default_motion_window(),
+            model_threshold: default_liveness_model_threshold(),
+            model_input_size: default_liveness_input_size(),
+        }
+    }
+}
+
+fn default_min_ir_brightness() -> f32 { 40.0 }
+fn default_min_ir_contrast() -> f32 { 12.0 }
+fn default_min_motion() -> f32 { 1.0 }
+fn default_motion_window() -> u32 { 5 }
+fn default_liveness_model_threshold() -> f32 { 0.5 }
+fn default_liveness_input_size() -> u32 { 112 }
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdaptiveConfig {
+    /// Learn extra templates from confident successful authentications
+    #[serde(default)]
+    pub enabled: bool,
+    /// How far above `auth.similarity_threshold` a frame must score against
+    /// the enrolled templates alone to be learned
+    #[serde(default = "default_adaptive_min_margin")]
+    pub min_margin: f32,
+    /// Adaptive templates kept per user; the oldest is dropped beyond this
+    #[serde(default = "default_adaptive_max_templates")]
+    pub max_templates: usize,
+    /// Adaptive templates older than this are discarded
+    #[serde(default = "default_adaptive_max_age_days")]
+    pub max_age_days: u32,
+    /// Minimum time between two learned templates for the same user
+    #[serde(default = "default_adaptive_min_interval_minutes")]
+    pub min_interval_minutes: u64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_margin: default_adaptive_min_margin(),
+            max_templates: default_adaptive_max_templates(),
+            max_age_days: default_adaptive_max_age_days(),
+            min_interval_minutes: default_adaptive_min_interval_minutes(),
+        }
+    }
+}
+
+fn default_adaptive_min_margin() -> f32 { 0.1 }
+fn default_adaptive_max_templates() -> usize { 10 }
+fn default_adaptive_max_age_days() -> u32 { 90 }
+fn default_adaptive_min_interval_minutes() -> u64 { 60 }
+
 fn default_enrollment_quality() -> f32 { 0.7 }
 fn default_num_captures() -> Option<usize> { Some(5) }
 fn default_capture_interval() -> Option<u64> { Some(2000) }
@@ -156,6 +494,152 @@ impl Config {
             )));
         }
         
+        // Validate frame source
+        if self.camera.source == FrameSourceKind::Replay && self.camera.replay_path.is_none() {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "camera.source = \"replay\" requires camera.replay_path"
+            )));
+        }
+        if self.camera.replay_fps < 0.0 || self.camera.replay_fps > 240.0 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Replay FPS must be between 0 and 240, got {}", 
+                self.camera.replay_fps
+            )));
+        }
+        
+        self.validate_auth()?;
+        for (service, policy) in &self.policy {
+            self.with_policy(policy).validate_auth()
+                .map_err(|e| FaceAuthError::Other(anyhow::anyhow!("[policy.{}]: {}", service, e)))?;
+        }
+        
+        // Validate detector dimensions
+        if self.detector.input_width == 0 || self.detector.input_width > 4096 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Detector input width must be between 1 and 4096, got {}", 
+                self.detector.input_width
+            )));
+        }
+        if self.detector.input_height == 0 || self.detector.input_height > 4096 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Detector input height must be between 1 and 4096, got {}", 
+                self.detector.input_height
+            )));
+        }
+        
+        // Validate service settings
+        if self.service.worker_threads == 0 || self.service.worker_threads > 64 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Service worker threads must be between 1 and 64, got {}", 
+                self.service.worker_threads
+            )));
+        }
+        if self.service.idle_exit_secs != 0 && self.service.idle_exit_secs < 10 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Service idle exit must be 0 (never) or at least 10 seconds, got {}", 
+                self.service.idle_exit_secs
+            )));
+        }
+        
+        // Validate liveness settings
+        if self.liveness.model_threshold < 0.0 || self.liveness.model_threshold > 1.0 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Liveness model threshold must be between 0.0 and 1.0, got {}", 
+                self.liveness.model_threshold
+            )));
+        }
+        if self.liveness.model_input_size == 0 || self.liveness.model_input_size > 1024 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Liveness model input size must be between 1 and 1024, got {}", 
+                self.liveness.model_input_size
+            )));
+        }
+        
+        // Validate enrollment quality settings
+        let weights = &self.enrollment.quality_weights;
+        let weight_values = [
+            weights.detection_confidence, weights.face_size, weights.centering, weights.brightness,
+            weights.contrast, weights.sharpness, weights.pose, weights.occlusion,
+        ];
+        if weight_values.iter().any(|&w| w < 0.0) || weight_values.iter().sum::<f32>() <= 0.0 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Enrollment quality weights must be non-negative and not all zero"
+            )));
+        }
+        let minimums = &self.enrollment.quality_minimums;
+        for (name, value) in [
+            ("min_detection_confidence", minimums.min_detection_confidence),
+            ("min_brightness", minimums.min_brightness),
+            ("min_contrast", minimums.min_contrast),
+            ("min_sharpness", minimums.min_sharpness),
+            ("min_occlusion_score", minimums.min_occlusion_score),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(FaceAuthError::Other(anyhow::anyhow!(
+                    "Enrollment quality minimum {} must be between 0.0 and 1.0, got {}", name, value
+                )));
+            }
+        }
+        for (name, value) in [
+            ("max_yaw_degrees", minimums.max_yaw_degrees),
+            ("max_pitch_degrees", minimums.max_pitch_degrees),
+            ("max_roll_degrees", minimums.max_roll_degrees),
+        ] {
+            if !(0.0..=90.0).contains(&value) {
+                return Err(FaceAuthError::Other(anyhow::anyhow!(
+                    "Enrollment quality limit {} must be between 0 and 90, got {}", name, value
+                )));
+            }
+        }
+        
+        if self.enrollment.guided_poses.is_empty() {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "enrollment.guided_poses must list at least one pose"
+            )));
+        }
+        if self.enrollment.guided_pose_angle <= 0.0 || self.enrollment.guided_pose_angle > 45.0 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Guided pose angle must be between 0 and 45 degrees, got {}", 
+                self.enrollment.guided_pose_angle
+            )));
+        }
+        if self.enrollment.guided_pose_tolerance <= 0.0
+            || self.enrollment.guided_pose_tolerance >= self.enrollment.guided_pose_angle
+        {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Guided pose tolerance must be positive and below guided_pose_angle, got {}", 
+                self.enrollment.guided_pose_tolerance
+            )));
+        }
+        
+        // Validate adaptive template settings, unused while learning is off
+        if self.adaptive.enabled {
+            if self.adaptive.max_templates == 0 || self.adaptive.max_templates > 100 {
+                return Err(FaceAuthError::Other(anyhow::anyhow!(
+                    "Adaptive max_templates must be between 1 and 100, got {}", 
+                    self.adaptive.max_templates
+                )));
+            }
+            if self.adaptive.max_age_days == 0 {
+                return Err(FaceAuthError::Other(anyhow::anyhow!(
+                    "Adaptive max_age_days must be at least 1"
+                )));
+            }
+        }
+        
+        // Validate recognizer input size
+        if self.recognizer.input_size == 0 || self.recognizer.input_size > 1024 {
+            return Err(FaceAuthError::Other(anyhow::anyhow!(
+                "Recognizer input size must be between 1 and 1024, got {}", 
+                self.recognizer.input_size
+            )));
+        }
+        
+        Ok(())
+    }
+    
+    // Auth settings alone, also checked for each policy applied on top of them
+    fn validate_auth(&self) -> Result<()> {
         // Validate thresholds
         if self.auth.similarity_threshold < 0.0 || self.auth.similarity_threshold > 1.0 {
             return Err(FaceAuthError::Other(anyhow::anyhow!(
@@ -178,28 +662,95 @@ impl Config {
             )));
         }
         
-        // Validate detector dimensions
-        if self.detector.input_width == 0 || self.detector.input_width > 4096 {
+        // Validate lockout settings
+        if self.auth.lockout_backoff < 1.0 {
             return Err(FaceAuthError::Other(anyhow::anyhow!(
-                "Detector input width must be between 1 and 4096, got {}", 
-                self.detector.input_width
+                "Lockout backoff must be at least 1.0, got {}", 
+                self.auth.lockout_backoff
             )));
         }
-        if self.detector.input_height == 0 || self.detector.input_height > 4096 {
+        
+        // Validate K-of-N matching
+        if self.auth.k_required_matches == 0 || self.auth.k_required_matches > self.auth.n_total_attempts {
             return Err(FaceAuthError::Other(anyhow::anyhow!(
-                "Detector input height must be between 1 and 4096, got {}", 
-                self.detector.input_height
+                "K-of-N matching needs 1 <= k_required_matches <= n_total_attempts, got {} of {}", 
+                self.auth.k_required_matches, self.auth.n_total_attempts
             )));
         }
         
-        // Validate recognizer input size
-        if self.recognizer.input_size == 0 || self.recognizer.input_size > 1024 {
+        // The learning margin sits on top of the effective threshold
+        if self.adaptive.enabled
+            && (self.adaptive.min_margin <= 0.0 || self.auth.similarity_threshold + self.adaptive.min_margin >= 1.0)
+        {
             return Err(FaceAuthError::Other(anyhow::anyhow!(
-                "Recognizer input size must be between 1 and 1024, got {}", 
-                self.recognizer.input_size
+                "Adaptive min_margin must be positive and keep similarity_threshold + min_margin below 1.0, got {} over {}", 
+                self.adaptive.min_margin, self.auth.similarity_threshold
             )));
         }
         
         Ok(())
     }
-}
\ No newline at end of file
+    
+    /// This config with `policy`'s overrides applied to `[auth]`
+    pub fn with_policy(&self, policy: &PolicyConfig) -> Config {
+        let mut config = self.clone();
+        if let Some(threshold) = policy.similarity_threshold {
+            config.auth.similarity_threshold = threshold;
+        }
+        if let Some(k) = policy.k_required_matches {
+            config.auth.k_required_matches = k;
+        }
+        if let Some(n) = policy.n_total_attempts {
+            config.auth.n_total_attempts = n;
+        }
+        if let Some(timeout) = policy.timeout_seconds {
+            config.auth.timeout_seconds = timeout;
+        }
+        config
+    }
+    
+    /// Similarity adaptive learning is measured against: the strictest of
+    /// `[auth]` and every policy, so a lax policy can't lower the bar
+    pub fn adaptive_threshold(&self) -> f32 {
+        self.policy.values()
+            .filter_map(|policy| policy.similarity_threshold)
+            .fold(self.auth.similarity_threshold, f32::max)
+    }
+    
+    /// The `[policy.<service>]` table for a PAM service, if there is one
+    pub fn policy_for(&self, pam_service: Option<&str>) -> Option<&PolicyConfig> {
+        pam_service.and_then(|service| self.policy.get(service))
+    }
+    
+    /// Settings that differ from `other`, one "section.key: old -> new" line each
+    pub fn diff(&self, other: &Config) -> Vec<String> {
+        let mut changes = Vec::new();
+        match (serde_json::to_value(self), serde_json::to_value(other)) {
+            (Ok(old), Ok(new)) => diff_values("", &old, &new, &mut changes),
+            _ => changes.push("(config could not be compared)".to_string()),
+        }
+        changes
+    }
+}
+
+fn diff_values(path: &str, old: &serde_json::Value, new: &serde_json::Value, changes: &mut Vec<String>) {
+    use serde_json::Value;
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match (old.get(key), new.get(key)) {
+                    (Some(o), Some(n)) => diff_values(&path, o, n, changes),
+                    (Some(o), None) => changes.push(format!("{}: {} -> unset", path, o)),
+                    (None, Some(n)) => changes.push(format!("{}: unset -> {}", path, n)),
+                    (None, None) => {}
+                }
+            }
+        }
+        (old, new) if old != new => changes.push(format!("{}: {} -> {}", path, old, new)),
+        _ => {}
+    }
+}
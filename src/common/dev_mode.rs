@@ -23,6 +23,8 @@ impl DevMode {
             fs::create_dir_all(base_dir.join("enrollment"))?;
             fs::create_dir_all(base_dir.join("captures"))?;
             fs::create_dir_all(base_dir.join("logs"))?;
+            fs::create_dir_all(base_dir.join("audit"))?;
+            fs::create_dir_all(base_dir.join("recordings"))?;
             fs::create_dir_all(base_dir.join("config"))?;
             fs::create_dir_all(base_dir.join("debug"))?;
             
@@ -62,6 +64,22 @@ impl DevMode {
         }
     }
     
+    pub fn audit_dir(&self) -> PathBuf {
+        if self.enabled {
+            self.base_dir.join("audit")
+        } else {
+            panic!("audit_dir() called when dev mode is disabled")
+        }
+    }
+    
+    pub fn recordings_dir(&self) -> PathBuf {
+        if self.enabled {
+            self.base_dir.join("recordings")
+        } else {
+            panic!("recordings_dir() called when dev mode is disabled")
+        }
+    }
+    
     #[allow(dead_code)]
     pub fn logs_dir(&self) -> PathBuf {
         if self.enabled {
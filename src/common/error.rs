@@ -11,6 +11,12 @@ pub enum FaceAuthError {
     #[error("Storage error: {0}")]
     Storage(String),
 
+    #[error("Template integrity check failed: {0}")]
+    TemplateTampered(String),
+
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+
     #[error("User not found: {0}")]
     UserNotFound(String),
 
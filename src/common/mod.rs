@@ -6,4 +6,4 @@ pub mod paths;
 pub use config::Config;
 pub use dev_mode::DevMode;
 pub use error::{FaceAuthError, Result};
-pub use paths::{system_user_data_dir, system_enrollment_dir, system_config_file, system_models_dir};
\ No newline at end of file
+pub use paths::{system_user_data_dir, system_enrollment_dir, system_audit_dir, system_config_file, system_models_dir};
\ No newline at end of file
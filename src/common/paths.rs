@@ -11,6 +11,10 @@ pub fn system_enrollment_dir() -> PathBuf {
     PathBuf::from("/var/lib/suplinux/enrollment")
 }
 
+pub fn system_audit_dir() -> PathBuf {
+    PathBuf::from("/var/lib/suplinux/audit")
+}
+
 pub fn system_config_file() -> PathBuf {
     PathBuf::from("/etc/suplinux/face-auth.toml")
 }
@@ -0,0 +1,81 @@
+use crate::common::Config;
+use crate::core::recognizer::{cosine_similarity, Embedding};
+use crate::storage::{AdaptiveTemplate, UserData};
+use std::time::{Duration, SystemTime};
+
+// A candidate this close to an existing adaptive template adds nothing new
+const DUPLICATE_SIMILARITY: f32 = 0.98;
+
+/// The enrolled profile `embedding` matches best and its similarity, ignoring
+/// anything learned since. Adaptation is always anchored to the enrollment.
+pub fn best_profile<'a>(embedding: &[f32], enrolled: &'a UserData) -> Option<(&'a str, f32)> {
+    enrolled.profiles.iter()
+        .map(|profile| {
+            let similarity = profile.embeddings.iter()
+                .chain(profile.averaged_embedding.iter())
+                .map(|template| cosine_similarity(embedding, template))
+                .fold(0.0f32, f32::max);
+            (profile.name.as_str(), similarity)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Drop templates older than `adaptive.max_age_days`. Returns how many were dropped.
+pub fn prune(templates: &mut Vec<AdaptiveTemplate>, config: &Config, now: SystemTime) -> usize {
+    let max_age = Duration::from_secs(config.adaptive.max_age_days as u64 * 24 * 60 * 60);
+    let before = templates.len();
+    templates.retain(|t| now.duration_since(t.added).map_or(true, |age| age <= max_age));
+    before - templates.len()
+}
+
+/// Learn `embedding` from a successful authentication if it clears the margin
+/// over `threshold` (`Config::adaptive_threshold`, not the request's policy)
+/// against the enrollment, the rate limit and isn't a near-duplicate. The
+/// oldest template goes once the set is full. Returns whether `templates` changed.
+pub fn consider(
+    templates: &mut Vec<AdaptiveTemplate>,
+    embedding: &Embedding,
+    quality: f32,
+    enrolled: &UserData,
+    threshold: f32,
+    config: &Config,
+    now: SystemTime,
+) -> bool {
+    let changed = prune(templates, config, now) > 0;
+
+    let Some((profile, similarity)) = best_profile(embedding, enrolled) else {
+        return changed;
+    };
+    let required = threshold + config.adaptive.min_margin;
+    if similarity < required {
+        tracing::debug!("Not adapting: similarity {:.3} to enrollment below {:.3}", similarity, required);
+        return changed;
+    }
+
+    let min_interval = Duration::from_secs(config.adaptive.min_interval_minutes * 60);
+    if let Some(latest) = templates.iter().map(|t| t.added).max() {
+        if now.duration_since(latest).map_or(true, |since| since < min_interval) {
+            tracing::debug!("Not adapting: last template learned less than {} minutes ago",
+                config.adaptive.min_interval_minutes);
+            return changed;
+        }
+    }
+
+    if templates.iter().any(|t| cosine_similarity(embedding, &t.embedding) > DUPLICATE_SIMILARITY) {
+        tracing::debug!("Not adapting: near-duplicate of a learned template");
+        return changed;
+    }
+
+    templates.push(AdaptiveTemplate {
+        embedding: embedding.clone(),
+        similarity,
+        quality,
+        added: now,
+        profile: profile.to_string(),
+    });
+    templates.sort_by_key(|t| t.added);
+    while templates.len() > config.adaptive.max_templates {
+        templates.remove(0);
+    }
+    true
+}
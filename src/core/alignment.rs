@@ -0,0 +1,94 @@
+use image::{DynamicImage, GrayImage, Luma};
+
+/// Eye, nose and mouth-corner positions of the ArcFace template for a 112x112
+/// crop: left eye, right eye, nose tip, left mouth corner, right mouth corner
+const ARCFACE_TEMPLATE: [(f32, f32); 5] = [
+    (38.2946, 51.6963),
+    (73.5318, 51.5014),
+    (56.0252, 71.7366),
+    (41.5493, 92.3655),
+    (70.7299, 92.2041),
+];
+const TEMPLATE_SIZE: f32 = 112.0;
+
+/// Rotation, uniform scale and translation mapping image points onto the template
+#[derive(Debug, Clone, Copy)]
+struct SimilarityTransform {
+    // [x'; y'] = [a -b; b a] [x; y] + [tx; ty]
+    a: f32,
+    b: f32,
+    tx: f32,
+    ty: f32,
+}
+
+impl SimilarityTransform {
+    /// Least-squares fit of `src` onto `dst` (Umeyama without reflection)
+    fn estimate(src: &[(f32, f32); 5], dst: &[(f32, f32); 5]) -> Option<Self> {
+        let n = src.len() as f32;
+        let (src_mx, src_my) = src.iter().fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+        let (dst_mx, dst_my) = dst.iter().fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+
+        let (mut dot, mut cross, mut norm) = (0.0f32, 0.0f32, 0.0f32);
+        for (s, d) in src.iter().zip(dst) {
+            let (sx, sy) = (s.0 - src_mx, s.1 - src_my);
+            let (dx, dy) = (d.0 - dst_mx, d.1 - dst_my);
+            dot += sx * dx + sy * dy;
+            cross += sx * dy - sy * dx;
+            norm += sx * sx + sy * sy;
+        }
+        if norm < f32::EPSILON {
+            return None;
+        }
+
+        let a = dot / norm;
+        let b = cross / norm;
+        Some(Self {
+            a,
+            b,
+            tx: dst_mx - (a * src_mx - b * src_my),
+            ty: dst_my - (b * src_mx + a * src_my),
+        })
+    }
+
+    /// Where the output pixel `(x, y)` comes from in the source image
+    fn inverse_map(&self, x: f32, y: f32) -> (f32, f32) {
+        let det = self.a * self.a + self.b * self.b;
+        let (u, v) = (x - self.tx, y - self.ty);
+        ((self.a * u + self.b * v) / det, (-self.b * u + self.a * v) / det)
+    }
+}
+
+/// Warp the face onto the canonical ArcFace template at `size`x`size`,
+/// removing head roll and keeping the face's proportions. Returns None if
+/// the landmarks are degenerate.
+pub fn align_face(image: &DynamicImage, landmarks: &[(f32, f32); 5], size: u32) -> Option<DynamicImage> {
+    let scale = size as f32 / TEMPLATE_SIZE;
+    let template = ARCFACE_TEMPLATE.map(|(x, y)| (x * scale, y * scale));
+    let transform = SimilarityTransform::estimate(landmarks, &template)?;
+
+    let gray = image.to_luma8();
+    let mut aligned = GrayImage::new(size, size);
+    for (x, y, pixel) in aligned.enumerate_pixels_mut() {
+        let (sx, sy) = transform.inverse_map(x as f32 + 0.5, y as f32 + 0.5);
+        *pixel = Luma([sample_bilinear(&gray, sx - 0.5, sy - 0.5)]);
+    }
+
+    Some(DynamicImage::ImageLuma8(aligned))
+}
+
+// Bilinear sample with edge clamping, so faces near the frame border still align
+fn sample_bilinear(image: &GrayImage, x: f32, y: f32) -> u8 {
+    let max_x = image.width() as f32 - 1.0;
+    let max_y = image.height() as f32 - 1.0;
+    let x = x.clamp(0.0, max_x);
+    let y = y.clamp(0.0, max_y);
+
+    let (x0, y0) = (x.floor(), y.floor());
+    let (x1, y1) = ((x0 + 1.0).min(max_x), (y0 + 1.0).min(max_y));
+    let (fx, fy) = (x - x0, y - y0);
+    let at = |px: f32, py: f32| image.get_pixel(px as u32, py as u32)[0] as f32;
+
+    let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+    let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+    (top * (1.0 - fy) + bottom * fy).round() as u8
+}
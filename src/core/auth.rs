@@ -1,11 +1,12 @@
 use crate::{
-    camera::Camera,
+    camera::{self, FrameSource},
     common::{Config, DevMode, Result},
     core::{
         detector::{FaceDetector, FaceBox},
+        enrollment,
         recognizer::{FaceRecognizer, cosine_similarity, Embedding},
     },
-    storage::UserStore,
+    storage::{UserStore, UserData},
 };
 use std::time::{Duration, Instant};
 use std::collections::VecDeque;
@@ -14,7 +15,7 @@ use imageproc::drawing::draw_hollow_rect_mut;
 use imageproc::rect::Rect;
 
 pub struct FaceAuth {
-    camera: Camera,
+    camera: Box<dyn FrameSource>,
     detector: FaceDetector,
     recognizer: FaceRecognizer,
     store: UserStore,
@@ -27,10 +28,10 @@ impl FaceAuth {
         let config = Config::load()?;
 
         Ok(Self {
-            camera: Camera::new(&config)?,
+            camera: camera::open(&config)?,
             detector: FaceDetector::new(&config)?,
             recognizer: FaceRecognizer::new(&config)?,
-            store: UserStore::new_with_dev_mode(&dev_mode)?,
+            store: UserStore::new_with_dev_mode(&dev_mode, &config.storage)?,
             config,
             _dev_mode: dev_mode,
         })
@@ -95,34 +96,12 @@ impl FaceAuth {
                     }
                     
                     // Calculate best similarity
-                    let mut best_similarity = 0.0f32;
-                    
-                    // Check individual embedding
-                    for stored_embedding in user_data.embeddings.iter() {
-                        let similarity = cosine_similarity(&embedding, stored_embedding);
-                        best_similarity = best_similarity.max(similarity);
-                    }
-                    
-                    // Check against averaged stored embedding if available
-                    if let Some(ref avg_stored) = user_data.averaged_embedding {
-                        let similarity = cosine_similarity(&embedding, avg_stored);
-                        best_similarity = best_similarity.max(similarity);
-                    }
-                    
-                    // Check fused embedding if enabled and we have enough samples
-                    if self.config.auth.use_embedding_fusion && embedding_buffer.len() >= 2 {
-                        let fused_embedding = average_embeddings_buffer(&embedding_buffer);
-                        
-                        for stored_embedding in user_data.embeddings.iter() {
-                            let similarity = cosine_similarity(&fused_embedding, stored_embedding);
-                            best_similarity = best_similarity.max(similarity);
-                        }
-                        
-                        if let Some(ref avg_stored) = user_data.averaged_embedding {
-                            let similarity = cosine_similarity(&fused_embedding, avg_stored);
-                            best_similarity = best_similarity.max(similarity);
-                        }
-                    }
+                    let best_similarity = calculate_best_similarity(
+                        &embedding,
+                        &embedding_buffer,
+                        &user_data,
+                        self.config.auth.use_embedding_fusion,
+                    );
                     
                     // Update K-of-N tracking
                     let auth_success = best_similarity > self.config.auth.similarity_threshold;
@@ -180,7 +159,7 @@ impl FaceAuth {
 // Dev mode versions of public functions
 pub fn test_camera_dev(dev_mode: &DevMode) -> Result<()> {
     let config = Config::load()?;
-    let mut camera = Camera::new(&config)?;
+    let mut camera = camera::open(&config)?;
     let img = camera.capture_frame()?;
     
     let save_path = if dev_mode.is_enabled() {
@@ -268,10 +247,10 @@ fn visualize_detections(image: &DynamicImage, all_faces: &[FaceBox], filtered_fa
 
 pub fn test_detection_dev(dev_mode: &DevMode) -> Result<()> {
     let config = Config::load()?;
-    let mut camera = Camera::new(&config)?;
+    let mut camera = camera::open(&config)?;
     let detector = FaceDetector::new(&config)?;
 
-    println!("Capturing frame from camera {}...", config.camera.device_index);
+    println!("Capturing frame from {}...", camera.label());
     let frame = camera.capture_frame()?;
     
     let save_path = if dev_mode.is_enabled() {
@@ -313,12 +292,12 @@ pub fn test_detection_dev(dev_mode: &DevMode) -> Result<()> {
     Ok(())
 }
 
-pub fn enroll_user_dev(username: &str, dev_mode: &DevMode) -> Result<()> {
+pub fn enroll_user_dev(username: &str, profile: &str, guided: bool, with_glasses: bool, dev_mode: &DevMode) -> Result<()> {
     use crate::service::ServiceClient;
     
     // Always use the service now (unified path)
     let mut client = ServiceClient::new(dev_mode.is_enabled());
-    client.enroll(username)
+    client.enroll_with_options(username, profile, guided, with_glasses)
 }
 
 // Removed enroll_via_service - now using ServiceClient for both dev and production
@@ -331,57 +310,55 @@ pub fn authenticate_user_dev(username: &str, dev_mode: &DevMode) -> Result<bool>
     client.test_auth(username)
 }
 
-pub fn enhance_user_dev(username: &str, additional_captures: u32, replace_weak: bool, dev_mode: &DevMode) -> Result<()> {
+pub fn enhance_user_dev(username: &str, profile: &str, additional_captures: u32, replace_weak: bool, dev_mode: &DevMode) -> Result<()> {
     use crate::service::ServiceClient;
     
     // Always use the service now (unified path)
     let mut client = ServiceClient::new(dev_mode.is_enabled());
-    client.enhance(username, Some(additional_captures), replace_weak)
+    client.enhance(username, profile, Some(additional_captures), replace_weak)
 }
 
-// Helper function to average embeddings - used by authentication
-#[allow(dead_code)]
-fn average_embeddings(embeddings: &[Embedding]) -> Embedding {
-    if embeddings.is_empty() {
-        return vec![];
-    }
+/// Best cosine similarity of a probe against a user's templates: the probe
+/// itself, and with fusion the average of the recent embeddings in `embedding_buffer`
+pub fn calculate_best_similarity(
+    embedding: &[f32],
+    embedding_buffer: &VecDeque<Embedding>,
+    user_data: &UserData,
+    use_fusion: bool,
+) -> f32 {
+    let mut best_similarity = 0.0f32;
     
-    let embedding_size = embeddings[0].len();
-    let mut averaged = vec![0.0f32; embedding_size];
+    // Fused embedding if enabled and we have enough samples
+    let fused_embedding = (use_fusion && embedding_buffer.len() >= 2)
+        .then(|| enrollment::average_embeddings(embedding_buffer))
+        .flatten();
     
-    for embedding in embeddings {
-        for (i, &value) in embedding.iter().enumerate() {
-            averaged[i] += value;
+    // Every profile can match, e.g. with and without glasses
+    for profile in &user_data.profiles {
+        // Check individual embedding against stored embeddings
+        for stored_embedding in profile.embeddings.iter() {
+            let similarity = cosine_similarity(embedding, stored_embedding);
+            best_similarity = best_similarity.max(similarity);
         }
-    }
-    
-    let count = embeddings.len() as f32;
-    for value in &mut averaged {
-        *value /= count;
-    }
-    
-    averaged
-}
-
-// Helper function to average embeddings from a buffer
-fn average_embeddings_buffer(buffer: &VecDeque<Embedding>) -> Embedding {
-    if buffer.is_empty() {
-        return vec![];
-    }
-    
-    let embedding_size = buffer[0].len();
-    let mut averaged = vec![0.0f32; embedding_size];
-    
-    for embedding in buffer.iter() {
-        for (i, &value) in embedding.iter().enumerate() {
-            averaged[i] += value;
+        
+        // Check against averaged stored embedding if available
+        if let Some(ref avg_stored) = profile.averaged_embedding {
+            let similarity = cosine_similarity(embedding, avg_stored);
+            best_similarity = best_similarity.max(similarity);
+        }
+        
+        if let Some(ref fused_embedding) = fused_embedding {
+            for stored_embedding in profile.embeddings.iter() {
+                let similarity = cosine_similarity(fused_embedding, stored_embedding);
+                best_similarity = best_similarity.max(similarity);
+            }
+            
+            if let Some(ref avg_stored) = profile.averaged_embedding {
+                let similarity = cosine_similarity(fused_embedding, avg_stored);
+                best_similarity = best_similarity.max(similarity);
+            }
         }
     }
     
-    let count = buffer.len() as f32;
-    for value in &mut averaged {
-        *value /= count;
-    }
-    
-    averaged
-}
\ No newline at end of file
+    best_similarity
+}
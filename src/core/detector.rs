@@ -3,18 +3,41 @@ use ort::{Environment, Session, SessionBuilder, Value, GraphOptimizationLevel};
 use std::sync::Arc;
 use image::{DynamicImage, imageops::FilterType};
 use ndarray::{Array4, CowArray};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FaceBox {
     pub x1: f32,
     pub y1: f32,
     pub x2: f32,
     pub y2: f32,
     pub confidence: f32,
+    /// Left eye, right eye, nose tip, left and right mouth corners, from
+    /// detectors that output keypoints (YOLOv8-face)
+    pub landmarks: Option<[(f32, f32); 5]>,
 }
 
+impl FaceBox {
+    fn scale(&mut self, scale_x: f32, scale_y: f32) {
+        self.x1 *= scale_x;
+        self.x2 *= scale_x;
+        self.y1 *= scale_y;
+        self.y2 *= scale_y;
+        if let Some(landmarks) = &mut self.landmarks {
+            for point in landmarks.iter_mut() {
+                point.0 *= scale_x;
+                point.1 *= scale_y;
+            }
+        }
+    }
+}
+
+// YOLOv8-face predictions: box (4), confidence (1), then 5 keypoints as
+// (x, y, visibility) triples, or as bare (x, y) pairs in some exports
+const KEYPOINTS_OFFSET: usize = 5;
+
 pub struct FaceDetector {
-    session: Session,
+    session: Arc<Session>,  // Shared with reconfigured copies
     _environment: Arc<Environment>,
     config: Config,
 }
@@ -54,7 +77,7 @@ impl FaceDetector {
         let session = session_builder.with_model_from_file(model_path)?;
         
         Ok(Self {
-            session,
+            session: Arc::new(session),
             _environment: environment,
             config: config.clone(),
         })
@@ -92,12 +115,23 @@ impl FaceDetector {
         let session = session_builder.with_model_from_file(model_path)?;
 
         Ok(Self {
-            session,
+            session: Arc::new(session),
             _environment: environment,
             config: config.clone(),
         })
     }
 
+    /// The same loaded model with new settings, e.g. after a config reload.
+    /// Only valid while `models.detector_path` and the optimization level
+    /// the session was built with are unchanged.
+    pub fn reconfigure(&self, config: &Config) -> Self {
+        Self {
+            session: Arc::clone(&self.session),
+            _environment: Arc::clone(&self._environment),
+            config: config.clone(),
+        }
+    }
+
     pub fn detect(&self, image: &DynamicImage) -> Result<Vec<FaceBox>> {
         // Store original image dimensions for coordinate scaling
         let orig_width = image.width() as f32;
@@ -129,10 +163,7 @@ impl FaceDetector {
         let scale_y = orig_height / self.config.detector.input_height as f32;
         
         for face in &mut faces {
-            face.x1 *= scale_x;
-            face.x2 *= scale_x;
-            face.y1 *= scale_y;
-            face.y2 *= scale_y;
+            face.scale(scale_x, scale_y);
         }
         
         Ok(faces)
@@ -169,18 +200,8 @@ impl FaceDetector {
         let scale_x = orig_width / self.config.detector.input_width as f32;
         let scale_y = orig_height / self.config.detector.input_height as f32;
         
-        for face in &mut all_faces {
-            face.x1 *= scale_x;
-            face.x2 *= scale_x;
-            face.y1 *= scale_y;
-            face.y2 *= scale_y;
-        }
-        
-        for face in &mut filtered_faces {
-            face.x1 *= scale_x;
-            face.x2 *= scale_x;
-            face.y1 *= scale_y;
-            face.y2 *= scale_y;
+        for face in all_faces.iter_mut().chain(filtered_faces.iter_mut()) {
+            face.scale(scale_x, scale_y);
         }
         
         Ok((all_faces, filtered_faces))
@@ -225,7 +246,8 @@ impl FaceDetector {
         let mut faces = Vec::new();
 
         // YOLOv8 output format: [1, 8400, num_classes + 4] OR [1, num_classes + 4, 8400] (transposed)
-        // Where each detection is [x_center, y_center, width, height, class_scores...]
+        // Where each detection is [x_center, y_center, width, height, class_scores...],
+        // followed by keypoints for face models
         if outputs.len() >= 1 {
             let output = outputs[0].try_extract::<f32>()?.view().to_owned();
             let output_array = output.as_slice().unwrap();
@@ -236,8 +258,8 @@ impl FaceDetector {
             
             // Check if output is transposed
             let (num_predictions, prediction_length, is_transposed) = if shape.len() >= 3 {
-                if shape[2] > shape[1] && shape[1] <= 10 {
-                    // Likely transposed format [1, 5, 8400]
+                if shape[2] > shape[1] && shape[1] <= 20 {
+                    // Likely transposed format [1, 5, 8400] (or [1, 20, 8400] with keypoints)
                     // Detected transposed output format
                     (shape[2], shape[1], true)
                 } else {
@@ -262,13 +284,14 @@ impl FaceDetector {
                 let (x_center_raw, y_center_raw, width_raw, height_raw, confidence) = if is_transposed {
                     // Transposed format: [1, 5, 8400]
                     let base_idx = i;
+                    let stride = num_predictions;
                     (
                         output_array[base_idx],                    // x_center at [0, i]
-                        output_array[8400 + base_idx],            // y_center at [1, i]
-                        output_array[2 * 8400 + base_idx],       // width at [2, i]
-                        output_array[3 * 8400 + base_idx],       // height at [3, i]
+                        output_array[stride + base_idx],          // y_center at [1, i]
+                        output_array[2 * stride + base_idx],     // width at [2, i]
+                        output_array[3 * stride + base_idx],     // height at [3, i]
                         if prediction_length > 4 { 
-                            output_array[4 * 8400 + base_idx]    // confidence at [4, i]
+                            output_array[4 * stride + base_idx]  // confidence at [4, i]
                         } else { 0.0 }
                     )
                 } else {
@@ -309,12 +332,18 @@ impl FaceDetector {
                     
                     // Skip invalid boxes (too small or inverted)
                     if x2 > x1 && y2 > y1 && (x2 - x1) > 10.0 && (y2 - y1) > 10.0 {
+                        let value = |row: usize| if is_transposed {
+                            output_array[row * num_predictions + i]
+                        } else {
+                            output_array[i * prediction_length + row]
+                        };
                         faces.push(FaceBox {
                             x1,
                             y1,
                             x2,
                             y2,
                             confidence,
+                            landmarks: parse_landmarks(value, prediction_length, scale_factor),
                         });
                     }
                 }
@@ -408,7 +437,7 @@ impl FaceDetector {
             
             // Use same parsing logic as main function
             let (num_predictions, prediction_length, is_transposed) = if shape.len() >= 3 {
-                if shape[2] > shape[1] && shape[1] <= 10 {
+                if shape[2] > shape[1] && shape[1] <= 20 {
                     (shape[2], shape[1], true)
                 } else {
                     (shape[1], shape[2], false)
@@ -462,12 +491,18 @@ impl FaceDetector {
                 let x2 = (x_center + width / 2.0).min(self.config.detector.input_width as f32);
                 let y2 = (y_center + height / 2.0).min(self.config.detector.input_height as f32);
                 
+                let value = |row: usize| if is_transposed {
+                    output_array[row * num_predictions + i]
+                } else {
+                    output_array[i * prediction_length + row]
+                };
                 let face_box = FaceBox {
                     x1,
                     y1,
                     x2,
                     y2,
                     confidence,
+                    landmarks: parse_landmarks(value, prediction_length, scale_factor),
                 };
                 
                 // Add all boxes for debugging
@@ -494,4 +529,20 @@ impl FaceDetector {
 
         Ok((all_faces, filtered_faces))
     }
-}
\ No newline at end of file
+}
+
+// Read the 5 keypoints of one prediction, if the model outputs them
+fn parse_landmarks(value: impl Fn(usize) -> f32, prediction_length: usize, scale_factor: f32) -> Option<[(f32, f32); 5]> {
+    let point_stride = match prediction_length.saturating_sub(KEYPOINTS_OFFSET) {
+        15.. => 3,
+        10..=14 => 2,
+        _ => return None,
+    };
+
+    let mut landmarks = [(0.0f32, 0.0f32); 5];
+    for (k, point) in landmarks.iter_mut().enumerate() {
+        let row = KEYPOINTS_OFFSET + k * point_stride;
+        *point = (value(row) * scale_factor, value(row + 1) * scale_factor);
+    }
+    Some(landmarks)
+}
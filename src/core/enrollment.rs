@@ -0,0 +1,115 @@
+use crate::common::Result;
+use crate::common::config::{EnrollmentConfig, PoseBucket};
+use crate::core::detector::FaceBox;
+use crate::core::quality::{HeadPose, QualityMetrics};
+use crate::core::recognizer::{Embedding, FaceRecognizer};
+use crate::storage::user_store::EnrollmentProfile;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// Outcome of offering one detected face for enrollment
+pub enum EnrollmentSample {
+    Accepted { embedding: Embedding, quality: QualityMetrics },
+    LowQuality(QualityMetrics),
+}
+
+/// Quality-check a detected face and embed it if it is good enough to enroll
+pub fn enrollment_sample(
+    recognizer: &FaceRecognizer,
+    frame: &DynamicImage,
+    face: &FaceBox,
+    config: &EnrollmentConfig,
+) -> Result<EnrollmentSample> {
+    let quality = QualityMetrics::calculate(frame, face, config);
+    if !quality.meets_minimum_requirements(config) {
+        return Ok(EnrollmentSample::LowQuality(quality));
+    }
+
+    let embedding = recognizer.get_embedding(frame, face)?;
+    Ok(EnrollmentSample::Accepted { embedding, quality })
+}
+
+/// Element-wise mean of the embeddings, None if there are none
+pub fn average_embeddings<'a>(embeddings: impl IntoIterator<Item = &'a Embedding>) -> Option<Embedding> {
+    let mut embeddings = embeddings.into_iter();
+    let mut averaged = embeddings.next()?.clone();
+    let mut count = 1;
+
+    for embedding in embeddings {
+        for (sum, &value) in averaged.iter_mut().zip(embedding.iter()) {
+            *sum += value;
+        }
+        count += 1;
+    }
+
+    let count = count as f32;
+    for value in &mut averaged {
+        *value /= count;
+    }
+
+    Some(averaged)
+}
+
+/// Templates for a freshly enrolled profile, with the averaged embedding
+pub fn build_profile(name: &str, camera: &str, embeddings: Vec<Embedding>, quality_scores: Vec<f32>) -> EnrollmentProfile {
+    let averaged_embedding = average_embeddings(&embeddings);
+
+    EnrollmentProfile {
+        name: name.to_string(),
+        camera: camera.to_string(),
+        created: SystemTime::now(),
+        embeddings,
+        averaged_embedding,
+        embedding_qualities: Some(quality_scores),
+        embedding_poses: None,
+    }
+}
+
+/// Pose a template was captured in during guided enrollment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoseTag {
+    pub bucket: PoseBucket,
+    pub glasses: bool,
+}
+
+impl PoseTag {
+    /// What to tell the user to get into this pose
+    pub fn prompt(&self) -> String {
+        let pose = match self.bucket {
+            PoseBucket::Frontal => "Look straight at the camera",
+            PoseBucket::Left => "Turn your head slightly to your left",
+            PoseBucket::Right => "Turn your head slightly to your right",
+            PoseBucket::Up => "Tilt your head slightly up",
+            PoseBucket::Down => "Tilt your head slightly down",
+        };
+        if self.glasses {
+            format!("{} (with glasses on)", pose)
+        } else {
+            pose.to_string()
+        }
+    }
+
+    /// Whether an estimated head pose falls in this bucket. The camera faces the
+    /// user, so turning to their left turns the face towards image right.
+    pub fn matches(&self, pose: &HeadPose, config: &EnrollmentConfig) -> bool {
+        let angle = config.guided_pose_angle;
+        let near = |value: f32, target: f32| (value - target).abs() <= config.guided_pose_tolerance;
+        match self.bucket {
+            PoseBucket::Frontal => near(pose.yaw, 0.0) && near(pose.pitch, 0.0),
+            PoseBucket::Left => near(pose.yaw, angle) && near(pose.pitch, 0.0),
+            PoseBucket::Right => near(pose.yaw, -angle) && near(pose.pitch, 0.0),
+            PoseBucket::Up => near(pose.pitch, -angle) && near(pose.yaw, 0.0),
+            PoseBucket::Down => near(pose.pitch, angle) && near(pose.yaw, 0.0),
+        }
+    }
+}
+
+/// Poses guided enrollment prompts through: `guided_poses` without glasses, then
+/// again with glasses for users who wear them
+pub fn guided_steps(config: &EnrollmentConfig, with_glasses: bool) -> Vec<PoseTag> {
+    let passes: &[bool] = if with_glasses { &[false, true] } else { &[false] };
+    passes.iter()
+        .flat_map(|&glasses| config.guided_poses.iter().map(move |&bucket| PoseTag { bucket, glasses }))
+        .collect()
+}
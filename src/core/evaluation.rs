@@ -0,0 +1,295 @@
+use crate::common::{Config, FaceAuthError, Result};
+use crate::core::auth::calculate_best_similarity;
+use crate::core::detector::FaceDetector;
+use crate::core::enrollment::{self, EnrollmentSample};
+use crate::core::recognizer::{Embedding, FaceRecognizer};
+use crate::storage::{UserData, DEFAULT_PROFILE};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Thresholds swept for the ROC curve
+const ROC_STEPS: usize = 200;
+
+/// One identity in a `<identity>/<images>` dataset, images in file name order
+pub struct Identity {
+    pub name: String,
+    pub images: Vec<PathBuf>,
+}
+
+/// Read a dataset directory with one subdirectory of PNG/JPEG images per identity
+pub fn load_dataset(dir: &Path) -> Result<Vec<Identity>> {
+    let mut identities = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+
+        let mut images: Vec<PathBuf> = fs::read_dir(&path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| is_image_file(p))
+            .collect();
+        images.sort();
+        if images.is_empty() {
+            continue;
+        }
+
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        identities.push(Identity { name, images });
+    }
+    identities.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if identities.len() < 2 {
+        return Err(FaceAuthError::Other(anyhow::anyhow!(
+            "{} needs at least two identity directories with images to measure impostor scores", dir.display()
+        )));
+    }
+    Ok(identities)
+}
+
+/// Similarity scores of genuine (same identity) and impostor probe frames
+#[derive(Debug, Clone, Default)]
+pub struct ScoreSet {
+    pub genuine: Vec<f32>,
+    pub impostor: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RocPoint {
+    pub threshold: f32,
+    pub far: f32,
+    pub frr: f32,
+}
+
+impl ScoreSet {
+    /// Fraction of impostor scores the service would accept at `threshold`
+    pub fn far(&self, threshold: f32) -> f32 {
+        rate(&self.impostor, |s| s > threshold)
+    }
+
+    /// Fraction of genuine scores the service would reject at `threshold`
+    pub fn frr(&self, threshold: f32) -> f32 {
+        rate(&self.genuine, |s| s <= threshold)
+    }
+
+    /// FAR and FRR over thresholds spanning all observed scores
+    pub fn roc(&self) -> Vec<RocPoint> {
+        let (low, high) = self.genuine.iter().chain(&self.impostor)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &s| (lo.min(s), hi.max(s)));
+        if !low.is_finite() {
+            return Vec::new();
+        }
+
+        (0..=ROC_STEPS)
+            .map(|i| {
+                let threshold = low + (high - low) * i as f32 / ROC_STEPS as f32;
+                RocPoint { threshold, far: self.far(threshold), frr: self.frr(threshold) }
+            })
+            .collect()
+    }
+
+    /// Equal error rate and the threshold where FAR and FRR cross
+    pub fn eer(&self) -> Option<RocPoint> {
+        self.roc().into_iter()
+            .min_by(|a, b| (a.far - a.frr).abs().total_cmp(&(b.far - b.frr).abs()))
+    }
+
+    /// Lowest threshold that keeps FAR at or below `target_far`, which gives the
+    /// lowest FRR for that security level
+    pub fn threshold_for_far(&self, target_far: f32) -> Option<RocPoint> {
+        self.roc().into_iter().find(|p| p.far <= target_far)
+    }
+}
+
+/// Attempt-level results: each probe sequence run through the K-of-N window
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AttemptRates {
+    pub genuine_attempts: usize,
+    pub genuine_rejected: usize,
+    pub impostor_attempts: usize,
+    pub impostor_accepted: usize,
+}
+
+impl AttemptRates {
+    pub fn far(&self) -> f32 {
+        ratio(self.impostor_accepted, self.impostor_attempts)
+    }
+
+    pub fn frr(&self) -> f32 {
+        ratio(self.genuine_rejected, self.genuine_attempts)
+    }
+}
+
+// Per-frame similarities of one probe sequence against one enrolled identity
+struct Attempt {
+    genuine: bool,
+    similarities: Vec<f32>,
+}
+
+pub struct Evaluation {
+    /// Identities enrolled, with the number of images used for enrollment
+    pub enrolled: Vec<(String, usize)>,
+    /// Identities with too few usable images to enroll
+    pub skipped: Vec<String>,
+    /// Images with no detectable face
+    pub no_face: usize,
+    pub scores: ScoreSet,
+    attempts: Vec<Attempt>,
+}
+
+impl Evaluation {
+    /// Replay every probe sequence through the K-of-N window at `threshold`
+    pub fn attempt_rates(&self, threshold: f32, k_required: u32, n_total: u32) -> AttemptRates {
+        let mut rates = AttemptRates::default();
+        for attempt in &self.attempts {
+            let accepted = k_of_n_accepts(&attempt.similarities, threshold, k_required, n_total);
+            if attempt.genuine {
+                rates.genuine_attempts += 1;
+                rates.genuine_rejected += usize::from(!accepted);
+            } else {
+                rates.impostor_attempts += 1;
+                rates.impostor_accepted += usize::from(accepted);
+            }
+        }
+        rates
+    }
+}
+
+/// Enroll each identity from its first `enrollment.num_captures` usable images,
+/// the way the service does, and score every identity's remaining images
+/// against every enrolled identity
+pub fn evaluate(
+    dataset: &[Identity],
+    detector: &FaceDetector,
+    recognizer: &FaceRecognizer,
+    config: &Config,
+) -> Result<Evaluation> {
+    let num_captures = config.enrollment.num_captures.unwrap_or(5);
+
+    let mut enrolled_users: Vec<(usize, UserData)> = Vec::new();
+    let mut probes: Vec<Vec<Embedding>> = Vec::with_capacity(dataset.len());
+    let mut evaluation = Evaluation {
+        enrolled: Vec::new(),
+        skipped: Vec::new(),
+        no_face: 0,
+        scores: ScoreSet::default(),
+        attempts: Vec::new(),
+    };
+
+    for (index, identity) in dataset.iter().enumerate() {
+        let mut embeddings = Vec::new();
+        let mut quality_scores = Vec::new();
+        let mut probe_embeddings = Vec::new();
+
+        for path in &identity.images {
+            let frame = image::open(path)
+                .map_err(|e| FaceAuthError::Other(anyhow::anyhow!("Failed to load {}: {}", path.display(), e)))?;
+            let faces = detector.detect(&frame).unwrap_or_default();
+            let Some(face) = faces.first() else {
+                tracing::debug!("No face in {}", path.display());
+                evaluation.no_face += 1;
+                continue;
+            };
+
+            if embeddings.len() < num_captures {
+                match enrollment::enrollment_sample(recognizer, &frame, face, &config.enrollment)? {
+                    EnrollmentSample::Accepted { embedding, quality } => {
+                        embeddings.push(embedding);
+                        quality_scores.push(quality.overall_score);
+                    }
+                    EnrollmentSample::LowQuality(quality) => {
+                        tracing::debug!("{} too low quality to enroll: {:.2}", path.display(), quality.overall_score);
+                    }
+                }
+            } else {
+                probe_embeddings.push(recognizer.get_embedding(&frame, face)?);
+            }
+        }
+
+        if embeddings.len() < num_captures || probe_embeddings.is_empty() {
+            tracing::warn!("Skipping {}: {} enrollment images and {} probes usable",
+                identity.name, embeddings.len(), probe_embeddings.len());
+            evaluation.skipped.push(identity.name.clone());
+            probes.push(Vec::new());
+            continue;
+        }
+
+        evaluation.enrolled.push((identity.name.clone(), embeddings.len()));
+        let mut user_data = UserData::new(&identity.name);
+        user_data.set_profile(enrollment::build_profile(DEFAULT_PROFILE, "dataset", embeddings, quality_scores));
+        enrolled_users.push((index, user_data));
+        probes.push(probe_embeddings);
+    }
+
+    if enrolled_users.len() < 2 {
+        return Err(FaceAuthError::Other(anyhow::anyhow!(
+            "Only {} identities had enough usable images; need {} enrollment images plus at least one probe for two or more",
+            enrolled_users.len(), num_captures
+        )));
+    }
+
+    let buffer_size = config.auth.embedding_buffer_size as usize;
+    for &(probe_index, _) in &enrolled_users {
+        for (user_index, user_data) in &enrolled_users {
+            let similarities = score_sequence(&probes[probe_index], user_data, buffer_size, config.auth.use_embedding_fusion);
+            let genuine = probe_index == *user_index;
+            if genuine {
+                evaluation.scores.genuine.extend_from_slice(&similarities);
+            } else {
+                evaluation.scores.impostor.extend_from_slice(&similarities);
+            }
+            evaluation.attempts.push(Attempt { genuine, similarities });
+        }
+    }
+
+    Ok(evaluation)
+}
+
+// Score probes in order with a rolling embedding buffer, as the service does
+// for consecutive frames of one attempt
+fn score_sequence(probes: &[Embedding], user_data: &UserData, buffer_size: usize, use_fusion: bool) -> Vec<f32> {
+    let mut buffer: VecDeque<Embedding> = VecDeque::with_capacity(buffer_size);
+    probes.iter()
+        .map(|embedding| {
+            buffer.push_back(embedding.clone());
+            if buffer.len() > buffer_size {
+                buffer.pop_front();
+            }
+            calculate_best_similarity(embedding, &buffer, user_data, use_fusion)
+        })
+        .collect()
+}
+
+fn k_of_n_accepts(similarities: &[f32], threshold: f32, k_required: u32, n_total: u32) -> bool {
+    let mut window: VecDeque<bool> = VecDeque::new();
+    for &similarity in similarities {
+        window.push_back(similarity > threshold);
+        while window.len() > n_total as usize {
+            window.pop_front();
+        }
+        if window.iter().filter(|&&s| s).count() as u32 >= k_required {
+            return true;
+        }
+    }
+    false
+}
+
+fn rate(scores: &[f32], counts: impl Fn(f32) -> bool) -> f32 {
+    ratio(scores.iter().filter(|&&s| counts(s)).count(), scores.len())
+}
+
+fn ratio(count: usize, total: usize) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f32 / total as f32
+    }
+}
+
+fn is_image_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref(),
+        Some("png" | "jpg" | "jpeg")
+    )
+}
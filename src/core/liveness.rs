@@ -0,0 +1,425 @@
+use crate::common::{FaceAuthError, Result, Config};
+use crate::common::config::LivenessConfig;
+use crate::core::detector::FaceBox;
+use ort::{Environment, Session, SessionBuilder, Value, GraphOptimizationLevel};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use image::{DynamicImage, GrayImage, imageops::FilterType};
+use ndarray::{Array4, CowArray};
+
+// Face crops are compared at this size so motion doesn't depend on distance
+const MOTION_CROP_SIZE: u32 = 64;
+
+/// Result of the anti-spoofing checks for one frame
+#[derive(Debug, Clone)]
+pub struct LivenessResult {
+    pub live: bool,
+    /// Not enough frames yet to judge motion; the frame should not count either way
+    pub pending: bool,
+    /// Mean brightness of the face box under IR (0-255)
+    pub ir_brightness: f32,
+    /// Standard deviation of the face box under IR (0-255)
+    pub ir_contrast: f32,
+    /// Median frame-to-frame change over the motion window, None until it is full
+    pub motion: Option<f32>,
+    /// Live probability from the optional model
+    pub model_score: Option<f32>,
+    /// Why the frame was rejected
+    pub reason: Option<String>,
+}
+
+/// Anti-spoofing checks run on every frame with a face; a matching frame
+/// only counts towards K-of-N once it passes
+///
+/// Combines micro-motion between consecutive frames (a printed photo held
+/// still doesn't blink or breathe), optionally IR reflectance statistics over
+/// the face box (phone and laptop screens emit no near-infrared, so they look
+/// dark and flat to an IR camera) and optionally an ONNX liveness model.
+/// Shared between requests; per-request state lives in `LivenessTracker`.
+pub struct LivenessDetector {
+    config: LivenessConfig,
+    input_size: u32,
+    normalization_value: f32,
+    model: Option<(Arc<Session>, Arc<Environment>)>,  // Shared with reconfigured copies
+}
+
+impl LivenessDetector {
+    pub fn new(config: &Config) -> Result<Self> {
+        let model = match config.models.liveness_path {
+            Some(ref model_path) if config.liveness.enabled => {
+                let environment = Arc::new(
+                    Environment::builder()
+                        .with_name("liveness")
+                        .build()
+                        .map_err(|e| FaceAuthError::Model(format!("Failed to create environment: {}", e)))?
+                );
+
+                if !model_path.exists() {
+                    return Err(FaceAuthError::Model(
+                        format!("Liveness model not found at: {:?}", model_path)
+                    ));
+                }
+
+                let mut session_builder = SessionBuilder::new(&environment)?;
+
+                // Apply optimization level from config
+                let opt_level = match config.performance.optimization_level {
+                    0 => GraphOptimizationLevel::Disable,
+                    1 => GraphOptimizationLevel::Level1,
+                    2 => GraphOptimizationLevel::Level2,
+                    _ => GraphOptimizationLevel::Level3,
+                };
+                session_builder = session_builder.with_optimization_level(opt_level)?;
+
+                let session = session_builder.with_model_from_file(model_path)?;
+                Some((Arc::new(session), environment))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            config: config.liveness.clone(),
+            input_size: config.liveness.model_input_size,
+            normalization_value: config.recognizer.normalization_value,
+            model,
+        })
+    }
+
+    /// The same loaded model with new thresholds, e.g. after a config reload.
+    /// Only valid while whether and which liveness model is used and the
+    /// optimization level it was built with are unchanged.
+    pub fn reconfigure(&self, config: &Config) -> Self {
+        Self {
+            config: config.liveness.clone(),
+            input_size: config.liveness.model_input_size,
+            normalization_value: config.recognizer.normalization_value,
+            model: self.model.clone(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Start tracking a new authentication attempt
+    pub fn tracker(&self) -> LivenessTracker<'_> {
+        LivenessTracker {
+            detector: self,
+            previous: None,
+            motion_history: VecDeque::new(),
+        }
+    }
+
+    fn model_score(&self, image: &DynamicImage, face: &FaceBox) -> Result<Option<f32>> {
+        let Some((ref session, _)) = self.model else {
+            return Ok(None);
+        };
+
+        // Same single-channel preprocessing as the recognizer
+        let face_img = crop_face(image, face).resize_exact(self.input_size, self.input_size, FilterType::Triangle);
+        let gray = face_img.to_luma8();
+        let size = self.input_size as usize;
+        let mut array = Array4::<f32>::zeros((1, 1, size, size));
+        for y in 0..size {
+            for x in 0..size {
+                let pixel = gray.get_pixel(x as u32, y as u32);
+                array[[0, 0, y, x]] = (pixel[0] as f32 - self.normalization_value) / self.normalization_value;
+            }
+        }
+
+        let cow_array = CowArray::from(array.into_dyn());
+        let input_tensor = Value::from_array(session.allocator(), &cow_array)?;
+        let outputs = session.run(vec![input_tensor])?;
+        let scores = outputs[0].try_extract::<f32>()?.view().to_owned().into_raw_vec();
+
+        // Either a single live probability or [spoof, live] logits
+        let score = match scores.as_slice() {
+            [live] => *live,
+            [spoof, live] => {
+                let max = spoof.max(*live);
+                let (e_spoof, e_live) = ((spoof - max).exp(), (live - max).exp());
+                e_live / (e_spoof + e_live)
+            }
+            other => {
+                return Err(FaceAuthError::Model(format!(
+                    "Liveness model returned {} outputs, expected 1 or 2", other.len()
+                )));
+            }
+        };
+        Ok(Some(score))
+    }
+}
+
+/// Per-attempt liveness state; keeps the previous face crop for motion
+pub struct LivenessTracker<'a> {
+    detector: &'a LivenessDetector,
+    previous: Option<GrayImage>,
+    motion_history: VecDeque<f32>,
+}
+
+impl LivenessTracker<'_> {
+    /// Check one frame. A disabled detector passes everything.
+    pub fn check(&mut self, image: &DynamicImage, face: &FaceBox) -> LivenessResult {
+        let config = &self.detector.config;
+        let face_img = crop_face(image, face);
+        let (ir_brightness, ir_contrast) = brightness_and_contrast(&face_img.to_luma8());
+
+        let mut result = LivenessResult {
+            live: true,
+            pending: false,
+            ir_brightness,
+            ir_contrast,
+            motion: None,
+            model_score: None,
+            reason: None,
+        };
+        if !config.enabled {
+            return result;
+        }
+
+        // Micro-motion between consecutive face crops, normalized for exposure
+        let crop = normalized_crop(&face_img);
+        if let Some(ref previous) = self.previous {
+            self.motion_history.push_back(mean_abs_diff(previous, &crop));
+            while self.motion_history.len() > config.motion_window.max(1) as usize {
+                self.motion_history.pop_front();
+            }
+        }
+        self.previous = Some(crop);
+        // The median, so one jump of the detector box over a still photo
+        // isn't taken for motion
+        if self.motion_history.len() >= config.motion_window.max(1) as usize {
+            result.motion = median(&self.motion_history);
+        }
+
+        let reason = if config.ir_checks && ir_brightness < config.min_ir_brightness {
+            Some(format!("face too dark under IR ({:.0} < {:.0}), looks like a screen", ir_brightness, config.min_ir_brightness))
+        } else if config.ir_checks && ir_contrast < config.min_ir_contrast {
+            Some(format!("face too flat under IR (contrast {:.1} < {:.1})", ir_contrast, config.min_ir_contrast))
+        } else {
+            match result.motion {
+                None => {
+                    result.pending = true;
+                    Some("waiting for enough frames to measure motion".to_string())
+                }
+                Some(motion) if motion < config.min_motion => {
+                    Some(format!("no facial micro-motion ({:.2} < {:.2})", motion, config.min_motion))
+                }
+                Some(_) => None,
+            }
+        };
+
+        let reason = reason.or_else(|| match self.detector.model_score(image, face) {
+            Ok(Some(score)) => {
+                result.model_score = Some(score);
+                (score < config.model_threshold)
+                    .then(|| format!("liveness model score {:.2} < {:.2}", score, config.model_threshold))
+            }
+            Ok(None) => None,
+            Err(e) => Some(format!("liveness model failed: {}", e)),
+        });
+
+        result.live = reason.is_none();
+        result.reason = reason;
+        result
+    }
+
+    /// Forget motion history, e.g. when the face was lost
+    pub fn reset(&mut self) {
+        self.previous = None;
+        self.motion_history.clear();
+    }
+}
+
+fn crop_face(image: &DynamicImage, face: &FaceBox) -> DynamicImage {
+    let x = face.x1.max(0.0) as u32;
+    let y = face.y1.max(0.0) as u32;
+    let width = (face.x2 - face.x1).max(1.0) as u32;
+    let height = (face.y2 - face.y1).max(1.0) as u32;
+
+    image.crop_imm(x, y, width, height)
+}
+
+fn brightness_and_contrast(gray: &GrayImage) -> (f32, f32) {
+    let count = gray.pixels().len();
+    if count == 0 {
+        return (0.0, 0.0);
+    }
+
+    let (sum, sum_sq) = gray.pixels().fold((0u64, 0u64), |(sum, sum_sq), p| {
+        let v = p[0] as u64;
+        (sum + v, sum_sq + v * v)
+    });
+    let mean = sum as f32 / count as f32;
+    let variance = (sum_sq as f32 / count as f32) - mean * mean;
+
+    (mean, variance.max(0.0).sqrt())
+}
+
+// Fixed-size crop with the mean brightness removed, so auto-exposure
+// changes don't register as motion
+fn normalized_crop(face_img: &DynamicImage) -> GrayImage {
+    let mut crop = face_img
+        .resize_exact(MOTION_CROP_SIZE, MOTION_CROP_SIZE, FilterType::Triangle)
+        .to_luma8();
+    let (mean, _) = brightness_and_contrast(&crop);
+    let shift = 128.0 - mean;
+    for p in crop.pixels_mut() {
+        p[0] = (p[0] as f32 + shift).clamp(0.0, 255.0) as u8;
+    }
+    crop
+}
+
+fn median(values: &VecDeque<f32>) -> Option<f32> {
+    let mut sorted: Vec<f32> = values.iter().copied().collect();
+    sorted.sort_by(f32::total_cmp);
+    sorted.get(sorted.len() / 2).copied()
+}
+
+fn mean_abs_diff(a: &GrayImage, b: &GrayImage) -> f32 {
+    let total: u64 = a.pixels()
+        .zip(b.pixels())
+        .map(|(p, q)| (p[0] as i32 - q[0] as i32).unsigned_abs() as u64)
+        .sum();
+    total as f32 / a.pixels().len().max(1) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn detector(enabled: bool, ir_checks: bool) -> LivenessDetector {
+        let config = LivenessConfig { enabled, ir_checks, ..LivenessConfig::default() };
+        LivenessDetector {
+            input_size: config.model_input_size,
+            normalization_value: 127.5,
+            config,
+            model: None,
+        }
+    }
+
+    fn face() -> FaceBox {
+        FaceBox { x1: 0.0, y1: 0.0, x2: 32.0, y2: 32.0, confidence: 0.9, landmarks: None }
+    }
+
+    // 4x4 checkerboard of `dark` and `light`, shifted right by `offset` pixels
+    fn checkerboard(offset: u32, dark: u8, light: u8) -> GrayImage {
+        GrayImage::from_fn(32, 32, |x, y| {
+            Luma([if ((x + offset) / 4 + y / 4) % 2 == 0 { dark } else { light }])
+        })
+    }
+
+    fn frame(offset: u32) -> DynamicImage {
+        DynamicImage::ImageLuma8(checkerboard(offset, 60, 200))
+    }
+
+    fn run(tracker: &mut LivenessTracker<'_>, offsets: &[u32]) -> Vec<LivenessResult> {
+        offsets.iter().map(|&offset| tracker.check(&frame(offset), &face())).collect()
+    }
+
+    #[test]
+    fn brightness_and_contrast_of_synthetic_faces() {
+        let (mean, contrast) = brightness_and_contrast(&GrayImage::from_pixel(8, 8, Luma([100])));
+        assert_eq!((mean, contrast), (100.0, 0.0));
+
+        let (mean, contrast) = brightness_and_contrast(&checkerboard(0, 60, 200));
+        assert!((mean - 130.0).abs() < 0.01);
+        assert!((contrast - 70.0).abs() < 0.01);
+
+        assert_eq!(brightness_and_contrast(&GrayImage::new(0, 0)), (0.0, 0.0));
+    }
+
+    #[test]
+    fn mean_abs_diff_of_synthetic_crops() {
+        let a = GrayImage::from_pixel(8, 8, Luma([10]));
+        let b = GrayImage::from_pixel(8, 8, Luma([30]));
+        assert_eq!(mean_abs_diff(&a, &b), 20.0);
+        assert_eq!(mean_abs_diff(&b, &a), 20.0);
+        assert_eq!(mean_abs_diff(&a, &a), 0.0);
+    }
+
+    #[test]
+    fn pending_until_motion_window_is_full() {
+        let detector = detector(true, false);
+        let mut tracker = detector.tracker();
+        let window = detector.config.motion_window;
+
+        // The first frame has nothing to compare with, then one pair per frame
+        let offsets: Vec<u32> = (0..=window).collect();
+        let results = run(&mut tracker, &offsets);
+        for result in &results[..window as usize] {
+            assert!(result.pending && !result.live);
+        }
+        let last = results.last().unwrap();
+        assert!(!last.pending && last.live, "{:?}", last.reason);
+        assert!(last.motion.unwrap() >= detector.config.min_motion);
+    }
+
+    #[test]
+    fn still_photo_fails_motion() {
+        let detector = detector(true, false);
+        let mut tracker = detector.tracker();
+
+        let results = run(&mut tracker, &[0; 6]);
+        let last = results.last().unwrap();
+        assert!(!last.pending && !last.live);
+        assert_eq!(last.motion, Some(0.0));
+        assert!(last.reason.as_deref().unwrap().contains("micro-motion"));
+    }
+
+    #[test]
+    fn single_jitter_does_not_pass_a_still_photo() {
+        let detector = detector(true, false);
+        let mut tracker = detector.tracker();
+
+        // One frame where the box jumped: two large changes out of five
+        let results = run(&mut tracker, &[0, 0, 0, 1, 0, 0]);
+        let last = results.last().unwrap();
+        assert!(!last.pending && !last.live);
+        assert_eq!(last.motion, Some(0.0));
+    }
+
+    #[test]
+    fn reset_forgets_motion() {
+        let detector = detector(true, false);
+        let mut tracker = detector.tracker();
+
+        run(&mut tracker, &[0, 1, 2, 3, 4, 5]);
+        tracker.reset();
+        assert!(tracker.check(&frame(6), &face()).pending);
+    }
+
+    #[test]
+    fn ir_checks_reject_dark_and_flat_faces() {
+        let detector = detector(true, true);
+        let mut tracker = detector.tracker();
+        let dark = DynamicImage::ImageLuma8(checkerboard(0, 5, 30));
+        let result = tracker.check(&dark, &face());
+        assert!(!result.pending && !result.live);
+        assert!(result.reason.as_deref().unwrap().contains("too dark"));
+
+        let flat = DynamicImage::ImageLuma8(GrayImage::from_pixel(32, 32, Luma([120])));
+        let result = tracker.check(&flat, &face());
+        assert!(!result.pending && !result.live);
+        assert!(result.reason.as_deref().unwrap().contains("too flat"));
+    }
+
+    #[test]
+    fn ir_checks_off_ignore_dark_faces() {
+        let detector = detector(true, false);
+        let mut tracker = detector.tracker();
+        let dark = DynamicImage::ImageLuma8(checkerboard(0, 5, 30));
+        let result = tracker.check(&dark, &face());
+        assert!(result.pending);
+        assert!(result.ir_brightness < detector.config.min_ir_brightness);
+    }
+
+    #[test]
+    fn disabled_detector_passes_everything() {
+        let detector = detector(false, true);
+        let mut tracker = detector.tracker();
+        let result = tracker.check(&DynamicImage::ImageLuma8(GrayImage::new(32, 32)), &face());
+        assert!(result.live && !result.pending && result.reason.is_none());
+    }
+}
@@ -1,9 +1,17 @@
 pub mod auth;
 pub mod detector;
 pub mod recognizer;
+pub mod alignment;
 pub mod quality;
+pub mod liveness;
+pub mod recording;
+pub mod enrollment;
+pub mod evaluation;
+pub mod adaptive;
 
 pub use auth::*;
 pub use detector::{FaceDetector, FaceBox};
 pub use recognizer::{FaceRecognizer, cosine_similarity, Embedding};
-pub use quality::{QualityMetrics, calculate_embedding_consistency};
\ No newline at end of file
+pub use quality::{QualityMetrics, HeadPose, calculate_embedding_consistency};
+pub use liveness::{LivenessDetector, LivenessTracker, LivenessResult};
+pub use recording::{AuthRecorder, AuthTrace, FrameTrace, AuthDecision};
\ No newline at end of file
@@ -1,6 +1,72 @@
+use crate::common::config::EnrollmentConfig;
 use crate::core::detector::FaceBox;
 use crate::core::recognizer::Embedding;
-use image::DynamicImage;
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+
+// Face crops are resized to this before measuring sharpness, so the score
+// doesn't depend on how close the user sits
+const SHARPNESS_CROP_SIZE: u32 = 112;
+// Laplacian variance of a crisp face crop; lower values scale the score down
+const SHARP_LAPLACIAN_VARIANCE: f32 = 150.0;
+// Pose at which the pose score reaches zero
+const MAX_SCORED_ANGLE: f32 = 45.0;
+// Width/height of a frontal face box from YOLO face detectors
+const FRONTAL_BOX_ASPECT: f32 = 0.8;
+// (eye-to-nose) / (eye-to-mouth) vertical distance in the frontal ArcFace template
+const FRONTAL_NOSE_RATIO: f32 = 0.494;
+
+/// Estimated head orientation in degrees. Yaw is positive when the face turns
+/// towards image right, pitch when it tilts down, roll when it leans clockwise.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeadPose {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    /// False when estimated from box geometry only (yaw magnitude, no pitch or roll)
+    pub from_landmarks: bool,
+}
+
+impl HeadPose {
+    /// Estimate pose from the 5 face landmarks, or roughly from the box shape
+    pub fn estimate(face: &FaceBox) -> Self {
+        let Some(landmarks) = face.landmarks else {
+            let width = face.x2 - face.x1;
+            let height = face.y2 - face.y1;
+            let aspect = if height > 0.0 { width / height } else { FRONTAL_BOX_ASPECT };
+            // Turning the head narrows the box roughly with cos(yaw)
+            let yaw = (aspect / FRONTAL_BOX_ASPECT).min(1.0).acos().to_degrees();
+            return Self { yaw, pitch: 0.0, roll: 0.0, from_landmarks: false };
+        };
+
+        let [left_eye, right_eye, nose, left_mouth, right_mouth] = landmarks;
+        let roll = (right_eye.1 - left_eye.1).atan2(right_eye.0 - left_eye.0);
+        let eye_distance = ((right_eye.0 - left_eye.0).powi(2) + (right_eye.1 - left_eye.1).powi(2)).sqrt();
+        if eye_distance < 1.0 {
+            return Self { roll: roll.to_degrees(), from_landmarks: true, ..Self::default() };
+        }
+
+        // Undo roll around the eye midpoint so yaw and pitch use upright coordinates
+        let eye_mid = ((left_eye.0 + right_eye.0) / 2.0, (left_eye.1 + right_eye.1) / 2.0);
+        let (sin, cos) = (-roll).sin_cos();
+        let upright = |p: (f32, f32)| {
+            let (dx, dy) = (p.0 - eye_mid.0, p.1 - eye_mid.1);
+            (dx * cos - dy * sin, dx * sin + dy * cos)
+        };
+        let nose = upright(nose);
+        let mouth_y = (upright(left_mouth).1 + upright(right_mouth).1) / 2.0;
+
+        // The nose tip shifts sideways by about half the eye distance at 90 degrees
+        let yaw = (nose.0 / (eye_distance / 2.0)).clamp(-1.0, 1.0).asin().to_degrees();
+        let pitch = if mouth_y > 0.0 {
+            let ratio = nose.1 / mouth_y;
+            ((ratio - FRONTAL_NOSE_RATIO) * 2.0).clamp(-1.0, 1.0).asin().to_degrees()
+        } else {
+            0.0
+        };
+
+        Self { yaw, pitch, roll: roll.to_degrees(), from_landmarks: true }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct QualityMetrics {
@@ -9,12 +75,19 @@ pub struct QualityMetrics {
     pub face_centering_score: f32,
     pub brightness_score: f32,
     pub contrast_score: f32,
+    /// 0-1, from the variance of the Laplacian over the face crop
+    pub sharpness_score: f32,
+    pub pose: HeadPose,
+    /// 0-1, falls as the head turns away from frontal
+    pub pose_score: f32,
+    /// Fraction of the eye and mouth regions that look unobstructed
+    pub occlusion_score: f32,
     pub overall_score: f32,
 }
 
 impl QualityMetrics {
-    /// Calculate quality metrics for a face detection
-    pub fn calculate(image: &DynamicImage, face: &FaceBox) -> Self {
+    /// Calculate quality metrics for a face detection, weighted by `config.quality_weights`
+    pub fn calculate(image: &DynamicImage, face: &FaceBox, config: &EnrollmentConfig) -> Self {
         let detection_confidence = face.confidence;
         
         // Calculate face size ratio (how much of the image the face occupies)
@@ -39,12 +112,27 @@ impl QualityMetrics {
         // Calculate brightness and contrast for the face region
         let (brightness_score, contrast_score) = calculate_image_quality(image, face);
         
+        // Blur, pose and occlusion
+        let gray = image.to_luma8();
+        let sharpness_score = calculate_sharpness(&gray, face);
+        let pose = HeadPose::estimate(face);
+        let worst_angle = pose.yaw.abs().max(pose.pitch.abs()).max(pose.roll.abs());
+        let pose_score = (1.0 - worst_angle / MAX_SCORED_ANGLE).clamp(0.0, 1.0);
+        let occlusion_score = calculate_occlusion(&gray, face);
+        
         // Calculate overall score (weighted average)
-        let overall_score = detection_confidence * 0.3
-            + face_size_ratio * 0.2
-            + face_centering_score * 0.2
-            + brightness_score * 0.15
-            + contrast_score * 0.15;
+        let w = &config.quality_weights;
+        let total_weight = w.detection_confidence + w.face_size + w.centering + w.brightness
+            + w.contrast + w.sharpness + w.pose + w.occlusion;
+        let overall_score = (detection_confidence * w.detection_confidence
+            + face_size_ratio * w.face_size
+            + face_centering_score * w.centering
+            + brightness_score * w.brightness
+            + contrast_score * w.contrast
+            + sharpness_score * w.sharpness
+            + pose_score * w.pose
+            + occlusion_score * w.occlusion)
+            / total_weight.max(f32::EPSILON);
         
         QualityMetrics {
             detection_confidence,
@@ -52,13 +140,54 @@ impl QualityMetrics {
             face_centering_score,
             brightness_score,
             contrast_score,
+            sharpness_score,
+            pose,
+            pose_score,
+            occlusion_score,
             overall_score,
         }
     }
     
-    /// Check if the quality meets minimum requirements
-    pub fn meets_minimum_requirements(&self, min_quality: f32) -> bool {
-        self.overall_score >= min_quality
+    /// Check if the quality meets the overall and per-metric minimums
+    pub fn meets_minimum_requirements(&self, config: &EnrollmentConfig) -> bool {
+        self.overall_score >= config.min_enrollment_quality && self.failed_minimums(config).is_empty()
+    }
+    
+    /// Per-metric minimums this capture fails, for logs and the enrollment report
+    pub fn failed_minimums(&self, config: &EnrollmentConfig) -> Vec<String> {
+        let m = &config.quality_minimums;
+        let mut failed = Vec::new();
+        
+        if self.detection_confidence < m.min_detection_confidence {
+            failed.push(format!("detection confidence {:.2} < {:.2}", self.detection_confidence, m.min_detection_confidence));
+        }
+        if self.brightness_score < m.min_brightness {
+            failed.push(format!("brightness {:.2} < {:.2}", self.brightness_score, m.min_brightness));
+        }
+        if self.contrast_score < m.min_contrast {
+            failed.push(format!("contrast {:.2} < {:.2}", self.contrast_score, m.min_contrast));
+        }
+        if self.sharpness_score < m.min_sharpness {
+            failed.push(format!("sharpness {:.2} < {:.2}", self.sharpness_score, m.min_sharpness));
+        }
+        // A yaw guessed from the box shape is too rough to reject a capture
+        // on: plenty of frontal faces simply have narrow boxes
+        if self.pose.from_landmarks {
+            if self.pose.yaw.abs() > m.max_yaw_degrees {
+                failed.push(format!("yaw {:.0}° > {:.0}°", self.pose.yaw.abs(), m.max_yaw_degrees));
+            }
+            if self.pose.pitch.abs() > m.max_pitch_degrees {
+                failed.push(format!("pitch {:.0}° > {:.0}°", self.pose.pitch.abs(), m.max_pitch_degrees));
+            }
+        }
+        if self.pose.roll.abs() > m.max_roll_degrees {
+            failed.push(format!("roll {:.0}° > {:.0}°", self.pose.roll.abs(), m.max_roll_degrees));
+        }
+        if self.occlusion_score < m.min_occlusion_score {
+            failed.push(format!("occlusion score {:.2} < {:.2}", self.occlusion_score, m.min_occlusion_score));
+        }
+        
+        failed
     }
     
     /// Get a human-readable quality assessment
@@ -106,6 +235,27 @@ impl QualityMetrics {
             suggestions.push("Improve lighting conditions for better contrast".to_string());
         }
         
+        if self.sharpness_score < 0.5 {
+            suggestions.push("Image is blurry - hold still and clean the camera lens".to_string());
+        }
+        
+        // Box-only estimates have no sign, so there is no direction to suggest
+        if self.pose.from_landmarks && self.pose.yaw.abs() > 15.0 {
+            let direction = if self.pose.yaw > 0.0 { "left" } else { "right" };
+            suggestions.push(format!("Head is turned {:.0}° - turn slightly {} to face the camera", self.pose.yaw.abs(), direction));
+        }
+        if self.pose.pitch.abs() > 15.0 {
+            let direction = if self.pose.pitch > 0.0 { "up" } else { "down" };
+            suggestions.push(format!("Head is tilted {:.0}° - tilt your chin {}", self.pose.pitch.abs(), direction));
+        }
+        if self.pose.roll.abs() > 15.0 {
+            suggestions.push(format!("Head is leaning {:.0}° - keep it upright", self.pose.roll.abs()));
+        }
+        
+        if self.occlusion_score < 1.0 {
+            suggestions.push("Part of your face looks covered - move hands, hair or masks away and avoid reflections on glasses".to_string());
+        }
+        
         suggestions
     }
 }
@@ -205,6 +355,94 @@ fn calculate_image_quality(image: &DynamicImage, face: &FaceBox) -> (f32, f32) {
     (brightness_score, contrast_score)
 }
 
+// Variance of the 4-neighbour Laplacian over the face, normalized to 0-1
+fn calculate_sharpness(gray: &GrayImage, face: &FaceBox) -> f32 {
+    let Some(crop) = crop_face(gray, face) else {
+        return 0.0;
+    };
+    let crop = image::imageops::resize(&crop, SHARPNESS_CROP_SIZE, SHARPNESS_CROP_SIZE, FilterType::Triangle);
+    
+    let (width, height) = crop.dimensions();
+    let at = |x: u32, y: u32| crop.get_pixel(x, y)[0] as f32;
+    let mut sum = 0.0f32;
+    let mut sum_sq = 0.0f32;
+    let mut count = 0u32;
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian = at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y);
+            sum += laplacian;
+            sum_sq += laplacian * laplacian;
+            count += 1;
+        }
+    }
+    
+    let mean = sum / count as f32;
+    let variance = sum_sq / count as f32 - mean * mean;
+    (variance / SHARP_LAPLACIAN_VARIANCE).min(1.0)
+}
+
+// Check the eyes and mouth for signs of something in front of them: a hand,
+// mask or hair makes a region flat, glare on glasses saturates it under IR.
+// Regions are placed on the landmarks, or at typical positions in the box.
+fn calculate_occlusion(gray: &GrayImage, face: &FaceBox) -> f32 {
+    let width = face.x2 - face.x1;
+    let height = face.y2 - face.y1;
+    let (regions, radius) = match face.landmarks {
+        Some([left_eye, right_eye, _, left_mouth, right_mouth]) => {
+            let eye_distance = ((right_eye.0 - left_eye.0).powi(2) + (right_eye.1 - left_eye.1).powi(2)).sqrt();
+            let mouth = ((left_mouth.0 + right_mouth.0) / 2.0, (left_mouth.1 + right_mouth.1) / 2.0);
+            ([left_eye, right_eye, mouth], eye_distance * 0.25)
+        }
+        None => (
+            [
+                (face.x1 + width * 0.3, face.y1 + height * 0.4),
+                (face.x1 + width * 0.7, face.y1 + height * 0.4),
+                (face.x1 + width * 0.5, face.y1 + height * 0.78),
+            ],
+            width * 0.12,
+        ),
+    };
+    
+    let clear = regions.iter()
+        .filter(|&&(cx, cy)| {
+            let region = FaceBox {
+                x1: cx - radius,
+                y1: cy - radius,
+                x2: cx + radius,
+                y2: cy + radius,
+                confidence: face.confidence,
+                landmarks: None,
+            };
+            match region_stats(gray, &region) {
+                Some((mean, std_dev)) => std_dev >= 6.0 && (10.0..=245.0).contains(&mean),
+                None => false,
+            }
+        })
+        .count();
+    
+    clear as f32 / regions.len() as f32
+}
+
+fn crop_face(gray: &GrayImage, face: &FaceBox) -> Option<GrayImage> {
+    let x1 = face.x1.max(0.0) as u32;
+    let y1 = face.y1.max(0.0) as u32;
+    let x2 = face.x2.min(gray.width() as f32) as u32;
+    let y2 = face.y2.min(gray.height() as f32) as u32;
+    if x2 < x1 + 3 || y2 < y1 + 3 {
+        return None;
+    }
+    Some(image::imageops::crop_imm(gray, x1, y1, x2 - x1, y2 - y1).to_image())
+}
+
+// Mean and standard deviation of a region, None if it lies outside the image
+fn region_stats(gray: &GrayImage, region: &FaceBox) -> Option<(f32, f32)> {
+    let crop = crop_face(gray, region)?;
+    let count = (crop.width() * crop.height()) as f32;
+    let mean = crop.pixels().map(|p| p[0] as f32).sum::<f32>() / count;
+    let variance = crop.pixels().map(|p| (p[0] as f32 - mean).powi(2)).sum::<f32>() / count;
+    Some((mean, variance.sqrt()))
+}
+
 fn cosine_similarity(a: &Embedding, b: &Embedding) -> f32 {
     if a.len() != b.len() {
         return 0.0;
@@ -1,5 +1,6 @@
 use crate::common::{FaceAuthError, Result, Config};
 use crate::core::detector::FaceBox;
+use crate::core::alignment::align_face;
 use ort::{Environment, Session, SessionBuilder, Value, GraphOptimizationLevel};
 use std::sync::Arc;
 use image::{DynamicImage, imageops::FilterType};
@@ -8,7 +9,7 @@ use ndarray::{Array4, CowArray};
 pub type Embedding = Vec<f32>;
 
 pub struct FaceRecognizer {
-    session: Session,
+    session: Arc<Session>,  // Shared with reconfigured copies
     _environment: Arc<Environment>,
     config: Config,
 }
@@ -48,7 +49,7 @@ impl FaceRecognizer {
         let session = session_builder.with_model_from_file(model_path)?;
         
         Ok(Self {
-            session,
+            session: Arc::new(session),
             _environment: environment,
             config: config.clone(),
         })
@@ -83,22 +84,42 @@ impl FaceRecognizer {
         let session = session_builder.with_model_from_file(model_path)?;
 
         Ok(Self { 
-            session, 
+            session: Arc::new(session), 
             _environment: environment,
             config: config.clone(),
         })
     }
 
+    /// The same loaded model with new settings, e.g. after a config reload.
+    /// Only valid while `models.recognizer_path` and the optimization level
+    /// the session was built with are unchanged.
+    pub fn reconfigure(&self, config: &Config) -> Self {
+        Self {
+            session: Arc::clone(&self.session),
+            _environment: Arc::clone(&self._environment),
+            config: config.clone(),
+        }
+    }
+
     pub fn get_embedding(&self, image: &DynamicImage, face: &FaceBox) -> Result<Embedding> {
-        // Crop face from original image (coordinates are already in original image space)
-        let face_img = self.crop_face(image, face)?;
-
-        // Resize to configured size for embedding model
-        let resized = face_img.resize_exact(
-            self.config.recognizer.input_size, 
-            self.config.recognizer.input_size, 
-            FilterType::Triangle
-        );
+        let size = self.config.recognizer.input_size;
+        
+        // Warp onto the template the model was trained on when we have landmarks
+        let aligned = match face.landmarks {
+            Some(ref landmarks) if self.config.recognizer.align_faces => align_face(image, landmarks, size),
+            _ => None,
+        };
+        
+        let resized = match aligned {
+            Some(aligned) => aligned,
+            None => {
+                // Crop face from original image (coordinates are already in original image space)
+                let face_img = self.crop_face(image, face)?;
+                
+                // Resize to configured size for embedding model
+                face_img.resize_exact(size, size, FilterType::Triangle)
+            }
+        };
 
         // Convert to array with proper preprocessing for single-channel model
         let input_array = self.preprocess_face(&resized)?;
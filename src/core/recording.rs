@@ -0,0 +1,295 @@
+use crate::camera::{RecordedFrame, SessionRecording};
+use crate::common::{Config, FaceAuthError, Result};
+use crate::core::auth::calculate_best_similarity;
+use crate::core::detector::{FaceBox, FaceDetector};
+use crate::core::liveness::LivenessDetector;
+use crate::core::recognizer::{Embedding, FaceRecognizer};
+use crate::storage::UserData;
+use chrono::{DateTime, Utc};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+// Similarities closer than this are reported as unchanged by `diff`
+const SIMILARITY_TOLERANCE: f32 = 0.005;
+
+/// What the pipeline made of one recorded frame
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FrameTrace {
+    pub faces: Vec<FaceBox>,
+    pub embedding: Option<Embedding>,
+    pub similarity: Option<f32>,
+    pub live: Option<bool>,
+    pub liveness_reason: Option<String>,
+    /// Result fed into the K-of-N window, None if the frame didn't count
+    pub counted: Option<bool>,
+    /// K-of-N progress was reset before this frame because the face was lost
+    pub reset: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthDecision {
+    pub success: bool,
+    pub message: String,
+    pub attempts: u32,
+    pub liveness_failures: u32,
+}
+
+/// Everything about one authentication attempt except the frames themselves,
+/// which live alongside it in the `SessionRecording`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuthTrace {
+    pub username: String,
+    pub recorded_at: DateTime<Utc>,
+    /// Config the service was running with
+    pub config: Config,
+    /// The user's templates at the time, so the session replays on another machine
+    pub templates: UserData,
+    /// One entry per frame in `SessionRecording::frames`
+    pub frames: Vec<FrameTrace>,
+    pub decision: AuthDecision,
+}
+
+/// Collects frames and per-frame results while the service authenticates
+pub struct AuthRecorder {
+    started: Instant,
+    frames: Vec<RecordedFrame>,
+    trace: AuthTrace,
+}
+
+impl AuthRecorder {
+    pub fn new(username: &str, templates: &UserData, config: &Config) -> Self {
+        Self {
+            started: Instant::now(),
+            frames: Vec::new(),
+            trace: AuthTrace {
+                username: username.to_string(),
+                recorded_at: Utc::now(),
+                config: config.clone(),
+                templates: templates.clone(),
+                frames: Vec::new(),
+                decision: AuthDecision::default(),
+            },
+        }
+    }
+
+    /// Add a captured frame; `annotate` then fills in what happened to it
+    pub fn frame(&mut self, image: &DynamicImage) -> Result<()> {
+        let offset_ms = self.started.elapsed().as_millis() as u64;
+        self.frames.push(RecordedFrame::encode(offset_ms, image)?);
+        self.trace.frames.push(FrameTrace::default());
+        Ok(())
+    }
+
+    pub fn annotate(&mut self, update: impl FnOnce(&mut FrameTrace)) {
+        if let Some(trace) = self.trace.frames.last_mut() {
+            update(trace);
+        }
+    }
+
+    pub fn finish(mut self, decision: AuthDecision) -> SessionRecording {
+        self.trace.decision = decision;
+        SessionRecording {
+            frames: self.frames,
+            auth: Some(self.trace),
+        }
+    }
+}
+
+/// Outcome of running a recording through the current pipeline
+pub struct ReplayedAuth {
+    pub frames: Vec<FrameTrace>,
+    pub decision: AuthDecision,
+}
+
+/// Run the recorded frames through detection, recognition, liveness and the
+/// K-of-N window with `config`, the same way the service does, using the
+/// recorded frame times in place of the clock
+pub fn replay(
+    recording: &SessionRecording,
+    detector: &FaceDetector,
+    recognizer: &FaceRecognizer,
+    liveness: &LivenessDetector,
+    config: &Config,
+) -> Result<ReplayedAuth> {
+    let trace = recording.auth.as_ref().ok_or_else(|| {
+        FaceAuthError::Other(anyhow::anyhow!("Recording holds frames only, no authentication trace to compare"))
+    })?;
+
+    let timeout_ms = config.auth.timeout_seconds as u64 * 1000;
+    let lost_face_ms = config.auth.lost_face_timeout as u64 * 1000;
+    let buffer_size = config.auth.embedding_buffer_size as usize;
+
+    let mut frames = Vec::with_capacity(recording.frames.len());
+    let mut window: VecDeque<bool> = VecDeque::new();
+    let mut embedding_buffer: VecDeque<Embedding> = VecDeque::with_capacity(buffer_size);
+    let mut tracker = liveness.tracker();
+    let mut last_face_ms: Option<u64> = None;
+    let mut liveness_failures = 0;
+    let mut last_liveness_reason: Option<String> = None;
+
+    for (index, recorded) in recording.frames.iter().enumerate() {
+        if recorded.offset_ms > timeout_ms {
+            break;
+        }
+        let attempts = index as u32 + 1;
+        let mut frame_trace = FrameTrace::default();
+
+        if last_face_ms.is_some_and(|t| recorded.offset_ms.saturating_sub(t) > lost_face_ms) {
+            window.clear();
+            embedding_buffer.clear();
+            tracker.reset();
+            last_face_ms = None;
+            frame_trace.reset = true;
+        }
+
+        let image = recorded.decode()?;
+        let faces = detector.detect(&image).unwrap_or_default();
+        frame_trace.faces = faces.clone();
+        let Some(face) = faces.first() else {
+            frames.push(frame_trace);
+            continue;
+        };
+        last_face_ms = Some(recorded.offset_ms);
+
+        let embedding = match recognizer.get_embedding(&image, face) {
+            Ok(e) => e,
+            Err(_) => {
+                frames.push(frame_trace);
+                continue;
+            }
+        };
+        embedding_buffer.push_back(embedding.clone());
+        if embedding_buffer.len() > buffer_size {
+            embedding_buffer.pop_front();
+        }
+
+        let similarity = calculate_best_similarity(
+            &embedding,
+            &embedding_buffer,
+            &trace.templates,
+            config.auth.use_embedding_fusion,
+        );
+        frame_trace.embedding = Some(embedding);
+        frame_trace.similarity = Some(similarity);
+
+        let live = tracker.check(&image, face);
+        frame_trace.live = Some(live.live);
+        frame_trace.liveness_reason = live.reason.clone();
+        if live.pending {
+            frames.push(frame_trace);
+            continue;
+        }
+
+        let matched = similarity > config.auth.similarity_threshold;
+        if matched && !live.live {
+            liveness_failures += 1;
+            last_liveness_reason = live.reason;
+        }
+        let success = matched && live.live;
+        frame_trace.counted = Some(success);
+        frames.push(frame_trace);
+
+        window.push_back(success);
+        while window.len() > config.auth.n_total_attempts as usize {
+            window.pop_front();
+        }
+        if window.iter().filter(|&&s| s).count() as u32 >= config.auth.k_required_matches {
+            return Ok(ReplayedAuth {
+                frames,
+                decision: AuthDecision {
+                    success: true,
+                    message: format!("Authenticated after {} attempts", attempts),
+                    attempts,
+                    liveness_failures,
+                },
+            });
+        }
+    }
+
+    let message = match last_liveness_reason {
+        Some(reason) => format!("Authentication timeout - {} matching frames failed liveness ({})", liveness_failures, reason),
+        None => "Authentication timeout".to_string(),
+    };
+    Ok(ReplayedAuth {
+        decision: AuthDecision {
+            success: false,
+            message,
+            attempts: frames.len() as u32,
+            liveness_failures,
+        },
+        frames,
+    })
+}
+
+/// Human-readable differences between a recorded session and its replay
+pub fn diff(recorded: &AuthTrace, replayed: &ReplayedAuth, config: &Config) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    // Settings that change the decision
+    let (was, now) = (&recorded.config.auth, &config.auth);
+    if was.similarity_threshold != now.similarity_threshold {
+        lines.push(format!("config: similarity_threshold {} -> {}", was.similarity_threshold, now.similarity_threshold));
+    }
+    if (was.k_required_matches, was.n_total_attempts) != (now.k_required_matches, now.n_total_attempts) {
+        lines.push(format!("config: K-of-N {}/{} -> {}/{}",
+            was.k_required_matches, was.n_total_attempts, now.k_required_matches, now.n_total_attempts));
+    }
+    if recorded.config.models.detector_path != config.models.detector_path
+        || recorded.config.models.recognizer_path != config.models.recognizer_path
+        || recorded.config.models.liveness_path != config.models.liveness_path
+    {
+        lines.push("config: model paths changed".to_string());
+    }
+    if recorded.config.liveness.enabled != config.liveness.enabled {
+        lines.push(format!("config: liveness enabled {} -> {}", recorded.config.liveness.enabled, config.liveness.enabled));
+    }
+
+    for (index, (was, now)) in recorded.frames.iter().zip(&replayed.frames).enumerate() {
+        let mut changes = Vec::new();
+        if was.faces.len() != now.faces.len() {
+            changes.push(format!("faces {} -> {}", was.faces.len(), now.faces.len()));
+        }
+        match (was.similarity, now.similarity) {
+            (Some(a), Some(b)) if (a - b).abs() > SIMILARITY_TOLERANCE => {
+                changes.push(format!("similarity {:.3} -> {:.3}", a, b));
+            }
+            (Some(a), None) => changes.push(format!("similarity {:.3} -> none", a)),
+            (None, Some(b)) => changes.push(format!("similarity none -> {:.3}", b)),
+            _ => {}
+        }
+        if was.live != now.live {
+            changes.push(format!("live {} -> {}", format_flag(was.live), format_flag(now.live)));
+        }
+        if was.counted != now.counted {
+            changes.push(format!("counted {} -> {}", format_flag(was.counted), format_flag(now.counted)));
+        }
+        if !changes.is_empty() {
+            lines.push(format!("frame {}: {}", index, changes.join(", ")));
+        }
+    }
+    if recorded.frames.len() != replayed.frames.len() {
+        lines.push(format!("frames processed {} -> {}", recorded.frames.len(), replayed.frames.len()));
+    }
+
+    let (was, now) = (&recorded.decision, &replayed.decision);
+    if was.success != now.success {
+        lines.push(format!("decision: {} -> {}",
+            if was.success { "success" } else { "failure" },
+            if now.success { "success" } else { "failure" }));
+    }
+    if was.liveness_failures != now.liveness_failures {
+        lines.push(format!("liveness failures {} -> {}", was.liveness_failures, now.liveness_failures));
+    }
+
+    lines
+}
+
+fn format_flag(flag: Option<bool>) -> &'static str {
+    match flag {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "-",
+    }
+}
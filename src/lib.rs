@@ -8,10 +8,10 @@ pub mod common;
 
 // Re-export commonly used types
 pub use common::{Config, DevMode, FaceAuthError, Result};
-pub use core::{FaceDetector, FaceBox, FaceRecognizer, Embedding, cosine_similarity, QualityMetrics};
-pub use camera::Camera;
+pub use core::{FaceDetector, FaceBox, FaceRecognizer, Embedding, cosine_similarity, QualityMetrics, LivenessDetector};
+pub use camera::{Camera, FrameSource};
 pub use storage::{UserStore, UserData};
-pub use service::{ServiceClient, protocol};
+pub use service::{ServiceClient, protocol, signing, arbiter, cancel, stats, systemd};
 
 // Legacy compatibility exports (to avoid breaking existing code)
 pub mod auth {
@@ -1,7 +1,9 @@
 use sup_linux::{
     auth,
     camera,
+    core::{evaluation, recording},
     dev_mode,
+    service_client,
     storage,
     visualization,
 };
@@ -33,11 +35,23 @@ enum Commands {
     Enroll {
         #[arg(short, long)]
         username: String,
+        /// Profile to create or replace, e.g. "glasses" or "docked"; other profiles are kept
+        #[arg(short, long, default_value = storage::DEFAULT_PROFILE)]
+        profile: String,
+        /// Prompt through head poses ([enrollment] guided_poses) instead of timed captures
+        #[arg(long)]
+        guided: bool,
+        /// With --guided, repeat the poses with glasses on
+        #[arg(long, requires = "guided")]
+        glasses: bool,
     },
     /// Enhance existing enrollment with additional embeddings
     Enhance {
         #[arg(short, long)]
         username: String,
+        /// Profile to add captures to
+        #[arg(short, long, default_value = storage::DEFAULT_PROFILE)]
+        profile: String,
         /// Number of additional captures (default: 3)
         #[arg(short = 'c', long, default_value = "3")]
         additional_captures: u32,
@@ -45,11 +59,83 @@ enum Commands {
         #[arg(short = 'r', long)]
         replace_weak: bool,
     },
+    /// List, inspect or delete enrolled users (root for any user, others only themselves)
+    Users {
+        #[command(subcommand)]
+        command: UsersCommands,
+    },
+    /// List or delete a user's enrollment profiles
+    Profiles {
+        #[command(subcommand)]
+        command: ProfilesCommands,
+    },
     /// Test authentication
     Test {
         #[arg(short, long)]
         username: String,
     },
+    /// Generate a new template encryption key and re-encrypt all users (root only)
+    RotateKey,
+    /// Encrypt user templates written before template encryption (root only).
+    /// Run once after upgrading; plaintext templates are refused until then
+    MigrateTemplates,
+    /// Clear a user's failed-attempt lockout (root only)
+    Unlock {
+        #[arg(short, long)]
+        username: String,
+    },
+    /// Roll back templates learned by adaptive mode ([adaptive] in the config);
+    /// the enrollment itself is kept
+    ResetAdaptive {
+        #[arg(short, long)]
+        username: String,
+        /// Only discard templates learned at or after this time (RFC 3339 or YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<String>,
+    },
+    /// Show whether the service is up, which models and camera it uses and
+    /// how long recent requests took
+    Status {
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Query the audit log of authentication and enrollment requests
+    Audit {
+        /// Only show records for this user
+        #[arg(short, long)]
+        username: Option<String>,
+        /// Earliest time to show (RFC 3339 or YYYY-MM-DD)
+        #[arg(long)]
+        since: Option<String>,
+        /// Latest time to show (RFC 3339 or YYYY-MM-DD, inclusive)
+        #[arg(long)]
+        until: Option<String>,
+        /// Only show the most recent N records
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+        /// Print records as JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rerun a recorded authentication session (suplinux-service --record-sessions)
+    /// against the current config and models and show what changed
+    Replay {
+        /// Recording file; in dev mode, names are also looked up in ./dev_data/recordings
+        file: std::path::PathBuf,
+        /// Print every frame, not just the differences
+        #[arg(long)]
+        frames: bool,
+    },
+    /// Measure FAR/FRR on a labeled dataset laid out as <identity>/<images> and
+    /// recommend a similarity threshold
+    Evaluate {
+        /// Dataset directory with one subdirectory of images per identity
+        dir: std::path::PathBuf,
+        /// Highest acceptable per-frame false accept rate for the recommended threshold
+        #[arg(long, default_value = "0.001")]
+        target_far: f32,
+    },
     /// Visualize user data
     Visualize {
         #[arg(short, long)]
@@ -59,6 +145,41 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum UsersCommands {
+    /// List enrolled users
+    List,
+    /// Show a user's profiles, adaptive templates and lockout state
+    Info {
+        #[arg(short, long)]
+        username: String,
+    },
+    /// Delete all biometric data of a user: templates, enrollment images and recordings
+    Delete {
+        #[arg(short, long)]
+        username: String,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfilesCommands {
+    /// Show each profile with its camera, creation time and template count
+    List {
+        #[arg(short, long)]
+        username: String,
+    },
+    /// Delete one profile and its enrollment images
+    Delete {
+        #[arg(short, long)]
+        username: String,
+        #[arg(short, long)]
+        profile: String,
+    },
+}
+
 #[derive(Subcommand)]
 enum VisualizeCommands {
     /// Generate similarity matrix
@@ -156,21 +277,280 @@ fn main() -> Result<()> {
             println!("\n🔧 After editing, test with:");
             println!("   suplinux test-camera");
         }
-        Commands::Enroll { username } => {
-            println!("Enrolling user: {}", username);
-            auth::enroll_user_dev(&username, &dev_mode)?;
+        Commands::Enroll { username, profile, guided, glasses } => {
+            println!("Enrolling user: {} (profile: {})", username, profile);
+            auth::enroll_user_dev(&username, &profile, guided, glasses, &dev_mode)?;
+        }
+        Commands::Enhance { username, profile, additional_captures, replace_weak } => {
+            println!("Enhancing enrollment for user: {} (profile: {})", username, profile);
+            auth::enhance_user_dev(&username, &profile, additional_captures, replace_weak, &dev_mode)?;
+        }
+        Commands::Users { command } => {
+            let mut client = service_client::ServiceClient::new(dev_mode.is_enabled());
+            match command {
+                UsersCommands::List => {
+                    let users = client.list_users()?;
+                    if users.is_empty() {
+                        println!("No enrolled users");
+                    }
+                    for user in users {
+                        println!("{}", user);
+                    }
+                }
+                UsersCommands::Info { username } => {
+                    let info = client.user_info(&username)?;
+                    println!("User: {} (storage v{})", info.username, info.storage_version);
+                    println!("Profiles:");
+                    for p in &info.profiles {
+                        let created: chrono::DateTime<chrono::Utc> = p.created.into();
+                        println!("  {:<20} {:>3} templates  camera {}  created {}",
+                            p.name, p.embeddings, p.camera, created.format("%Y-%m-%d %H:%M:%S UTC"));
+                    }
+                    match info.last_adaptive {
+                        Some(last) => {
+                            let last: chrono::DateTime<chrono::Utc> = last.into();
+                            println!("Adaptive templates: {} (newest {})", 
+                                info.adaptive_templates, last.format("%Y-%m-%d %H:%M:%S UTC"));
+                        }
+                        None => println!("Adaptive templates: 0"),
+                    }
+                    println!("Enrollment images: {}", info.enrollment_images);
+                    match info.locked_until {
+                        Some(until) => {
+                            let until: chrono::DateTime<chrono::Utc> = until.into();
+                            println!("Locked out until {}", until.format("%Y-%m-%d %H:%M:%S UTC"));
+                        }
+                        None => println!("Failed attempts: {}", info.failures),
+                    }
+                }
+                UsersCommands::Delete { username, yes } => {
+                    if !yes {
+                        print!("Delete all face data of {}? This cannot be undone [y/N] ", username);
+                        std::io::Write::flush(&mut std::io::stdout())?;
+                        let mut answer = String::new();
+                        std::io::stdin().read_line(&mut answer)?;
+                        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                            println!("Aborted");
+                            return Ok(());
+                        }
+                    }
+                    let result = client.delete_user(&username)?;
+                    println!("✅ {}", result.message);
+                }
+            }
         }
-        Commands::Enhance { username, additional_captures, replace_weak } => {
-            println!("Enhancing enrollment for user: {}", username);
-            auth::enhance_user_dev(&username, additional_captures, replace_weak, &dev_mode)?;
+        Commands::Profiles { command } => {
+            let mut client = service_client::ServiceClient::new(dev_mode.is_enabled());
+            match command {
+                ProfilesCommands::List { username } => {
+                    let profiles = client.list_profiles(&username)?;
+                    println!("{:<20} {:<24} {:<20} {:>9}", "PROFILE", "CAMERA", "CREATED (UTC)", "TEMPLATES");
+                    for p in &profiles {
+                        let created: chrono::DateTime<chrono::Utc> = p.created.into();
+                        println!("{:<20} {:<24} {:<20} {:>9}",
+                            p.name, p.camera, created.format("%Y-%m-%d %H:%M:%S"), p.embeddings);
+                    }
+                }
+                ProfilesCommands::Delete { username, profile } => {
+                    let result = client.delete_profile(&username, &profile)?;
+                    println!("✅ {}", result.message);
+                }
+            }
         }
         Commands::Test { username } => {
             println!("Testing authentication for: {}", username);
             let result = auth::authenticate_user_dev(&username, &dev_mode)?;
             println!("Authentication: {}", if result { "SUCCESS" } else { "FAILED" });
         }
+        Commands::RotateKey => {
+            println!("Rotating template encryption key...");
+            let mut client = service_client::ServiceClient::new(dev_mode.is_enabled());
+            let result = client.rotate_key()?;
+            println!("✅ {}", result.message);
+            println!("   Active key id: {}", result.key_id);
+        }
+        Commands::MigrateTemplates => {
+            println!("Encrypting plaintext templates...");
+            let mut client = service_client::ServiceClient::new(dev_mode.is_enabled());
+            let result = client.migrate_templates()?;
+            println!("✅ {}", result.message);
+        }
+        Commands::Unlock { username } => {
+            let mut client = service_client::ServiceClient::new(dev_mode.is_enabled());
+            let result = client.unlock(&username)?;
+            println!("✅ {}", result.message);
+        }
+        Commands::ResetAdaptive { username, since } => {
+            let since = since.as_deref().map(|s| parse_audit_time(s, false)).transpose()?;
+            let mut client = service_client::ServiceClient::new(dev_mode.is_enabled());
+            let result = client.reset_adaptive(&username, since.map(Into::into))?;
+            println!("✅ {}", result.message);
+        }
+        Commands::Status { json } => {
+            let mut client = service_client::ServiceClient::new(dev_mode.is_enabled());
+            let status = client.status()?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+            } else {
+                print_status(&status);
+            }
+        }
+        Commands::Audit { username, since, until, limit, json } => {
+            let dir = if dev_mode.is_enabled() {
+                dev_mode.audit_dir()
+            } else {
+                sup_linux::common::system_audit_dir()
+            };
+            let query = storage::AuditQuery {
+                username,
+                since: since.as_deref().map(|s| parse_audit_time(s, false)).transpose()?,
+                until: until.as_deref().map(|s| parse_audit_time(s, true)).transpose()?,
+            };
+            
+            let mut records = storage::AuditLog::open(&dir).query(&query)?;
+            if let Some(limit) = limit {
+                records.drain(..records.len().saturating_sub(limit));
+            }
+            
+            if json {
+                for record in &records {
+                    println!("{}", serde_json::to_string(record)?);
+                }
+            } else if records.is_empty() {
+                println!("No matching audit records in {}", dir.display());
+            } else {
+                println!("{:<20} {:<12} {:<16} {:<12} {:>6} {:>6} {:>8} {:>7}  {}",
+                    "TIME (UTC)", "EVENT", "USER", "OUTCOME", "UID", "PID", "BEST", "MS", "SERVICE");
+                for r in &records {
+                    println!("{:<20} {:<12} {:<16} {:<12} {:>6} {:>6} {:>8} {:>7}  {}",
+                        r.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                        format!("{:?}", r.event).to_lowercase(),
+                        r.username,
+                        r.outcome.to_string(),
+                        r.uid,
+                        r.pid,
+                        r.best_similarity.map(|s| format!("{:.3}", s)).unwrap_or_else(|| "-".to_string()),
+                        r.duration_ms,
+                        r.pam_service.as_deref().unwrap_or("-"));
+                }
+            }
+        }
+        Commands::Replay { file, frames } => {
+            let path = if !file.exists() && dev_mode.is_enabled() {
+                dev_mode.recordings_dir().join(&file)
+            } else {
+                file
+            };
+            let recording = camera::SessionRecording::load(&path)?;
+            let trace = recording.auth.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("{} holds frames only, not an authentication session", path.display()))?;
+            
+            let config = sup_linux::Config::load()?;
+            let detector = sup_linux::FaceDetector::new(&config)?;
+            let recognizer = sup_linux::FaceRecognizer::new(&config)?;
+            let liveness = sup_linux::LivenessDetector::new(&config)?;
+            
+            println!("Replaying {} frames of {} recorded {}", 
+                recording.frames.len(), trace.username, trace.recorded_at.format("%Y-%m-%d %H:%M:%S UTC"));
+            let replayed = recording::replay(&recording, &detector, &recognizer, &liveness, &config)?;
+            
+            println!("  Recorded: {} - {}", if trace.decision.success { "SUCCESS" } else { "FAILED" }, trace.decision.message);
+            println!("  Replayed: {} - {}", if replayed.decision.success { "SUCCESS" } else { "FAILED" }, replayed.decision.message);
+            
+            if frames {
+                println!("\n{:>5} {:>7} {:>6} {:>10} {:>5} {:>8}  {}",
+                    "FRAME", "MS", "FACES", "SIMILARITY", "LIVE", "COUNTED", "LIVENESS");
+                for (index, (frame, result)) in recording.frames.iter().zip(&replayed.frames).enumerate() {
+                    println!("{:>5} {:>7} {:>6} {:>10} {:>5} {:>8}  {}",
+                        index,
+                        frame.offset_ms,
+                        result.faces.len(),
+                        result.similarity.map(|s| format!("{:.3}", s)).unwrap_or_else(|| "-".to_string()),
+                        result.live.map(|l| if l { "yes" } else { "no" }).unwrap_or("-"),
+                        result.counted.map(|c| if c { "match" } else { "miss" }).unwrap_or("-"),
+                        result.liveness_reason.as_deref().unwrap_or(""));
+                }
+            }
+            
+            let differences = recording::diff(trace, &replayed, &config);
+            if differences.is_empty() {
+                println!("\n✅ Replay matches the recording");
+            } else {
+                println!("\n⚠️  {} differences from the recording:", differences.len());
+                for line in differences {
+                    println!("  {}", line);
+                }
+            }
+        }
+        Commands::Evaluate { dir, target_far } => {
+            let config = sup_linux::Config::load()?;
+            let detector = sup_linux::FaceDetector::new(&config)?;
+            let recognizer = sup_linux::FaceRecognizer::new(&config)?;
+            
+            let dataset = evaluation::load_dataset(&dir)?;
+            println!("Evaluating {} identities from {}", dataset.len(), dir.display());
+            let result = evaluation::evaluate(&dataset, &detector, &recognizer, &config)?;
+            
+            for (name, captures) in &result.enrolled {
+                println!("  Enrolled {} from {} images", name, captures);
+            }
+            if !result.skipped.is_empty() {
+                println!("  Skipped (not enough usable images): {}", result.skipped.join(", "));
+            }
+            if result.no_face > 0 {
+                println!("  {} images had no detectable face", result.no_face);
+            }
+            
+            let scores = &result.scores;
+            let threshold = config.auth.similarity_threshold;
+            println!("\nPer-frame scores: {} genuine, {} impostor", scores.genuine.len(), scores.impostor.len());
+            println!("  At similarity_threshold {:.3}: FAR {:.4}%, FRR {:.4}%",
+                threshold, scores.far(threshold) * 100.0, scores.frr(threshold) * 100.0);
+            
+            let eer = scores.eer();
+            if let Some(eer) = eer {
+                println!("  EER {:.4}% at threshold {:.3}", eer.far.max(eer.frr) * 100.0, eer.threshold);
+            }
+            
+            let recommended = scores.threshold_for_far(target_far);
+            match recommended {
+                Some(point) => println!("  Recommended similarity_threshold {:.3} (FAR {:.4}%, FRR {:.4}%, target FAR {}%)",
+                    point.threshold, point.far * 100.0, point.frr * 100.0, target_far * 100.0),
+                None => println!("  No threshold reaches a FAR of {}% on this dataset", target_far * 100.0),
+            }
+            
+            let (k, n) = (config.auth.k_required_matches, config.auth.n_total_attempts);
+            println!("\nAttempts with K-of-N {}/{}:", k, n);
+            let mut thresholds = vec![("configured", threshold)];
+            if let Some(point) = recommended {
+                thresholds.push(("recommended", point.threshold));
+            }
+            for (label, t) in thresholds {
+                let rates = result.attempt_rates(t, k, n);
+                println!("  {:<11} {:.3}: FAR {:.4}% ({}/{}), FRR {:.4}% ({}/{})",
+                    label, t,
+                    rates.far() * 100.0, rates.impostor_accepted, rates.impostor_attempts,
+                    rates.frr() * 100.0, rates.genuine_rejected, rates.genuine_attempts);
+            }
+            
+            let visualizer = visualization::Visualizer::new(&dev_mode)?;
+            let roc = scores.roc();
+            visualizer.export_roc_csv(&roc)?;
+            #[cfg(feature = "visualization")]
+            {
+                let mut marked = Vec::new();
+                if let Some(point) = eer {
+                    marked.push(("EER", point));
+                }
+                if let Some(point) = recommended {
+                    marked.push(("recommended", point));
+                }
+                visualizer.plot_roc(&roc, &marked)?;
+            }
+        }
         Commands::Visualize { username, command } => {
-            let store = storage::UserStore::new_with_dev_mode(&dev_mode)?;
+            let config = sup_linux::Config::load()?;
+            let store = storage::UserStore::new_with_dev_mode(&dev_mode, &config.storage)?;
             let visualizer = visualization::Visualizer::new(&dev_mode)?;
             
             match command.unwrap_or(VisualizeCommands::All) {
@@ -195,6 +575,59 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+// Accept full RFC 3339 timestamps or bare dates; a bare --until date covers the whole day
+fn parse_audit_time(value: &str, end_of_day: bool) -> Result<chrono::DateTime<chrono::Utc>> {
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&chrono::Utc));
+    }
+    let date = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| anyhow::anyhow!("Invalid time {:?}: expected RFC 3339 or YYYY-MM-DD", value))?;
+    let time = if end_of_day {
+        date.and_hms_nano_opt(23, 59, 59, 999_999_999)
+    } else {
+        date.and_hms_opt(0, 0, 0)
+    };
+    Ok(time.expect("valid time of day").and_utc())
+}
+
+fn print_status(status: &sup_linux::protocol::ServiceStatus) {
+    let utc = |time: std::time::SystemTime| {
+        let time: chrono::DateTime<chrono::Utc> = time.into();
+        time.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+    };
+    let uptime = status.uptime_secs;
+    println!("suplinux-service {} (protocol v{}), up {}d {:02}h {:02}m {:02}s since {}",
+        status.service_version, status.protocol_version,
+        uptime / 86400, uptime / 3600 % 24, uptime / 60 % 60, uptime % 60, utc(status.started));
+    println!("Workers: {}  Liveness: {}", status.workers, if status.liveness_enabled { "enabled" } else { "disabled" });
+    
+    println!("\nModels:");
+    for model in &status.models {
+        let hash = model.sha256.as_deref()
+            .or(model.error.as_deref())
+            .unwrap_or("-");
+        println!("  {:<11} {} ({} bytes)\n              sha256 {}", model.role, model.path.display(), model.size, hash);
+    }
+    
+    println!("\nCamera: {} (configured)", status.camera_config);
+    match status.camera {
+        Some(ref camera) => println!("  last opened {} as {} at {}", camera.device, camera.format, utc(camera.opened)),
+        None => println!("  not opened since the service started"),
+    }
+    
+    println!("\nRequests: {} authentications ({} successful), {} enrollments, {} enhancements",
+        status.authentications, status.auth_successes, status.enrollments, status.enhancements);
+    match status.last_error {
+        Some(ref error) => println!("Last error: {} at {}", error.message, utc(error.time)),
+        None => println!("Last error: none"),
+    }
+    
+    println!("\n{:<16} {:>8} {:>10} {:>10}", "STAGE", "SAMPLES", "AVG MS", "LAST MS");
+    for stage in &status.stages {
+        println!("{:<16} {:>8} {:>10.1} {:>10.1}", stage.stage, stage.samples, stage.average_ms, stage.last_ms);
+    }
+}
+
 fn setup_logging(dev_mode: bool) {
     if dev_mode {
         tracing_subscriber::fmt()
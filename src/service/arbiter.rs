@@ -0,0 +1,89 @@
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Serializes access to the camera between concurrently handled requests
+///
+/// Only one request can hold the camera at a time. Others wait up to a
+/// caller-chosen duration and then get `CameraBusy` describing the holder,
+/// which the service reports to the client instead of blocking it.
+pub struct CameraArbiter {
+    holder: Mutex<Option<Holder>>,
+    released: Condvar,
+}
+
+#[derive(Debug, Clone)]
+struct Holder {
+    purpose: String,
+    since: Instant,
+}
+
+/// Returned when the camera stayed in use for the whole wait
+#[derive(Debug, Clone)]
+pub struct CameraBusy {
+    pub holder: String,
+    pub held_for: Duration,
+}
+
+impl std::fmt::Display for CameraBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Camera busy: {} in progress for {}s", self.holder, self.held_for.as_secs())
+    }
+}
+
+/// Exclusive use of the camera, released on drop
+pub struct CameraLease<'a> {
+    arbiter: &'a CameraArbiter,
+}
+
+impl Drop for CameraLease<'_> {
+    fn drop(&mut self) {
+        *self.arbiter.lock() = None;
+        self.arbiter.released.notify_one();
+    }
+}
+
+impl CameraArbiter {
+    pub fn new() -> Self {
+        Self {
+            holder: Mutex::new(None),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Acquire the camera for `purpose`, waiting at most `wait` for the current holder
+    pub fn acquire(&self, purpose: &str, wait: Duration) -> Result<CameraLease<'_>, CameraBusy> {
+        let deadline = Instant::now() + wait;
+        let mut holder = self.lock();
+
+        while let Some(ref current) = *holder {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(CameraBusy {
+                    holder: current.purpose.clone(),
+                    held_for: current.since.elapsed(),
+                });
+            }
+            holder = self.released
+                .wait_timeout(holder, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+
+        *holder = Some(Holder {
+            purpose: purpose.to_string(),
+            since: Instant::now(),
+        });
+        Ok(CameraLease { arbiter: self })
+    }
+
+    // A panicking holder still releases through Drop, so poisoning is harmless
+    fn lock(&self) -> MutexGuard<'_, Option<Holder>> {
+        self.holder.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for CameraArbiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,100 @@
+use crate::service::protocol::{self, Request, MSG_TYPE_REQUEST};
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+
+/// Why a running request stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    ClientHangup,
+    CancelRequested,
+}
+
+impl std::fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CancelReason::ClientHangup => write!(f, "client hung up"),
+            CancelReason::CancelRequested => write!(f, "client sent Cancel"),
+        }
+    }
+}
+
+/// Watches a client connection for hang-up or `Request::Cancel` while a
+/// long-running request (authentication, enrollment) is being served
+///
+/// `check` never blocks when nothing has arrived, so it can be polled once
+/// per captured frame. Once cancelled, it stays cancelled.
+pub struct ClientWatch {
+    stream: UnixStream,
+    cancelled: Option<CancelReason>,
+}
+
+impl ClientWatch {
+    pub fn new(stream: &UnixStream) -> io::Result<Self> {
+        Ok(Self {
+            stream: stream.try_clone()?,
+            cancelled: None,
+        })
+    }
+
+    /// Poll the connection, returning the reason if the request should stop
+    pub fn check(&mut self) -> Option<CancelReason> {
+        if self.cancelled.is_none() {
+            self.cancelled = self.poll();
+        }
+        self.cancelled
+    }
+
+    /// The reason seen by the last `check`, without polling again
+    pub fn cancelled(&self) -> Option<CancelReason> {
+        self.cancelled
+    }
+
+    /// True if the client is gone, so there's no point sending a response
+    pub fn is_hung_up(&self) -> bool {
+        self.cancelled == Some(CancelReason::ClientHangup)
+    }
+
+    fn poll(&mut self) -> Option<CancelReason> {
+        let mut byte = 0u8;
+        let n = unsafe {
+            libc::recv(
+                self.stream.as_raw_fd(),
+                &mut byte as *mut u8 as *mut libc::c_void,
+                1,
+                libc::MSG_PEEK | libc::MSG_DONTWAIT,
+            )
+        };
+
+        if n == 0 {
+            return Some(CancelReason::ClientHangup);
+        }
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => None,
+                _ => Some(CancelReason::ClientHangup),
+            };
+        }
+
+        // Something arrived mid-request - the only thing a client may send now is Cancel
+        match protocol::read_frame(&mut self.stream) {
+            Ok(frame) if frame.msg_type == MSG_TYPE_REQUEST => match frame.decode::<Request>() {
+                Ok(Request::Cancel) => Some(CancelReason::CancelRequested),
+                Ok(other) => {
+                    tracing::warn!("Ignoring unexpected request while busy: {:?}", other);
+                    None
+                }
+                Err(e) => {
+                    tracing::warn!("Ignoring malformed message while busy: {}", e);
+                    None
+                }
+            },
+            Ok(frame) => {
+                tracing::warn!("Ignoring message type {} while busy", frame.msg_type);
+                None
+            }
+            Err(_) => Some(CancelReason::ClientHangup),
+        }
+    }
+}
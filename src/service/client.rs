@@ -1,10 +1,16 @@
 use crate::common::{FaceAuthError, Result};
 use crate::service::protocol::{
-    Request, Response, AuthRequest, EnrollRequest, EnhanceRequest,
-    StreamMessage, MSG_TYPE_RESPONSE, MSG_TYPE_STREAM
+    self, Request, Response, AuthRequest, EnrollRequest, EnhanceRequest, RotateKeyResponse, MigrateTemplatesResponse,
+    UnlockRequest, UnlockResponse, ResetAdaptiveRequest, ResetAdaptiveResponse, StreamMessage,
+    ProfilesRequest, ProfileInfo, DeleteProfileRequest, DeleteProfileResponse,
+    UserRequest, UserInfo, DeleteUserResponse, ServiceStatus,
+    MSG_TYPE_REQUEST, MSG_TYPE_RESPONSE, MSG_TYPE_STREAM,
+    FEATURE_KEY_ROTATION, FEATURE_LOCKOUT, FEATURE_STREAMING, FEATURE_GUIDED_ENROLL, FEATURE_ADAPTIVE,
+    FEATURE_PROFILES, FEATURE_USER_MANAGEMENT, FEATURE_STATUS, FEATURE_MIGRATE_TEMPLATES
 };
+use crate::storage::DEFAULT_PROFILE;
 use std::os::unix::net::UnixStream;
-use std::io::{self, Read, Write};
+use std::io::{self, Write};
 use std::time::{Duration, SystemTime};
 use std::path::Path;
 use std::process::{Command, Stdio};
@@ -27,16 +33,25 @@ impl ServiceClient {
     }
     
     pub fn enroll(&mut self, username: &str) -> Result<()> {
+        self.enroll_with_options(username, DEFAULT_PROFILE, false, false)
+    }
+    
+    /// Enroll `profile`, optionally guided through head poses (and again with glasses on)
+    pub fn enroll_with_options(&mut self, username: &str, profile: &str, guided: bool, with_glasses: bool) -> Result<()> {
         // Ensure service is running
         self.ensure_service_running()?;
         
         // Connect to service
-        let mut stream = self.connect_with_retry(3)?;
+        let required_features = if guided { FEATURE_STREAMING | FEATURE_GUIDED_ENROLL } else { FEATURE_STREAMING };
+        let mut stream = self.connect_with_retry(3, required_features)?;
         
         // Create enrollment request with preview enabled
         let request = Request::Enroll(EnrollRequest {
             username: username.to_string(),
+            profile: profile.to_string(),
             enable_preview: true,  // Always enable preview for better UX
+            guided,
+            with_glasses,
         });
         
         // Send request
@@ -57,6 +72,9 @@ impl ServiceClient {
             Response::Error(msg) => {
                 Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
             }
+            Response::CameraBusy(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("{} - try again once it finishes", msg)))
+            }
             _ => {
                 Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
             }
@@ -68,7 +86,7 @@ impl ServiceClient {
         self.ensure_service_running()?;
         
         // Connect to service
-        let mut stream = self.connect_with_retry(3)?;
+        let mut stream = self.connect_with_retry(3, 0)?;
         
         // Generate challenge
         let challenge = generate_challenge();
@@ -78,6 +96,10 @@ impl ServiceClient {
             username: username.to_string(),
             challenge: challenge.clone(),
             timestamp: SystemTime::now(),
+            pam_service: None,  // Not a PAM conversation
+            pam_tty: None,
+            pam_rhost: None,
+            stream_progress: false,
         });
         
         // Send request
@@ -97,6 +119,10 @@ impl ServiceClient {
                 eprintln!("Service error: {}", msg);
                 Ok(false)
             }
+            Response::CameraBusy(msg) => {
+                eprintln!("{}", msg);
+                Ok(false)
+            }
             _ => {
                 eprintln!("Unexpected response type");
                 Ok(false)
@@ -104,16 +130,17 @@ impl ServiceClient {
         }
     }
     
-    pub fn enhance(&mut self, username: &str, additional_captures: Option<u32>, replace_weak: bool) -> Result<()> {
+    pub fn enhance(&mut self, username: &str, profile: &str, additional_captures: Option<u32>, replace_weak: bool) -> Result<()> {
         // Ensure service is running
         self.ensure_service_running()?;
         
         // Connect to service
-        let mut stream = self.connect_with_retry(3)?;
+        let mut stream = self.connect_with_retry(3, FEATURE_STREAMING)?;
         
         // Create enhance request with preview enabled
         let request = Request::Enhance(EnhanceRequest {
             username: username.to_string(),
+            profile: profile.to_string(),
             additional_captures,
             replace_weak,
             enable_preview: true,  // Always enable preview for better UX
@@ -141,6 +168,319 @@ impl ServiceClient {
             Response::Error(msg) => {
                 Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
             }
+            Response::CameraBusy(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("{} - try again once it finishes", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    pub fn rotate_key(&mut self) -> Result<RotateKeyResponse> {
+        // Ensure service is running
+        self.ensure_service_running()?;
+        
+        // Connect to service
+        let mut stream = self.connect_with_retry(3, FEATURE_KEY_ROTATION)?;
+        
+        // Send request
+        self.send_request(&mut stream, &Request::RotateKey)?;
+        
+        // Read response
+        let response = self.read_response(&mut stream)?;
+        
+        match response {
+            Response::RotateKey(rotate_resp) => {
+                if rotate_resp.success {
+                    Ok(rotate_resp)
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(rotate_resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    pub fn migrate_templates(&mut self) -> Result<MigrateTemplatesResponse> {
+        // Ensure service is running
+        self.ensure_service_running()?;
+        
+        let mut stream = self.connect_with_retry(3, FEATURE_MIGRATE_TEMPLATES)?;
+        self.send_request(&mut stream, &Request::MigrateTemplates)?;
+        
+        match self.read_response(&mut stream)? {
+            Response::MigrateTemplates(migrate_resp) => {
+                if migrate_resp.success {
+                    Ok(migrate_resp)
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(migrate_resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    pub fn unlock(&mut self, username: &str) -> Result<UnlockResponse> {
+        // Ensure service is running
+        self.ensure_service_running()?;
+        
+        // Connect to service
+        let mut stream = self.connect_with_retry(3, FEATURE_LOCKOUT)?;
+        
+        // Send request
+        let request = Request::Unlock(UnlockRequest {
+            username: username.to_string(),
+        });
+        self.send_request(&mut stream, &request)?;
+        
+        // Read response
+        let response = self.read_response(&mut stream)?;
+        
+        match response {
+            Response::Unlock(unlock_resp) => {
+                if unlock_resp.success {
+                    Ok(unlock_resp)
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(unlock_resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    pub fn list_profiles(&mut self, username: &str) -> Result<Vec<ProfileInfo>> {
+        // Ensure service is running
+        self.ensure_service_running()?;
+        
+        // Connect to service
+        let mut stream = self.connect_with_retry(3, FEATURE_PROFILES)?;
+        
+        // Send request
+        let request = Request::ListProfiles(ProfilesRequest {
+            username: username.to_string(),
+        });
+        self.send_request(&mut stream, &request)?;
+        
+        // Read response
+        let response = self.read_response(&mut stream)?;
+        
+        match response {
+            Response::Profiles(profiles_resp) => {
+                if profiles_resp.success {
+                    Ok(profiles_resp.profiles)
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(profiles_resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    pub fn delete_profile(&mut self, username: &str, profile: &str) -> Result<DeleteProfileResponse> {
+        // Ensure service is running
+        self.ensure_service_running()?;
+        
+        // Connect to service
+        let mut stream = self.connect_with_retry(3, FEATURE_PROFILES)?;
+        
+        // Send request
+        let request = Request::DeleteProfile(DeleteProfileRequest {
+            username: username.to_string(),
+            profile: profile.to_string(),
+        });
+        self.send_request(&mut stream, &request)?;
+        
+        // Read response
+        let response = self.read_response(&mut stream)?;
+        
+        match response {
+            Response::DeleteProfile(delete_resp) => {
+                if delete_resp.success {
+                    Ok(delete_resp)
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(delete_resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    /// Enrolled users; only the caller unless running as root
+    pub fn list_users(&mut self) -> Result<Vec<String>> {
+        // Ensure service is running
+        self.ensure_service_running()?;
+        
+        // Connect to service
+        let mut stream = self.connect_with_retry(3, FEATURE_USER_MANAGEMENT)?;
+        
+        // Send request
+        self.send_request(&mut stream, &Request::ListUsers)?;
+        
+        // Read response
+        let response = self.read_response(&mut stream)?;
+        
+        match response {
+            Response::Users(resp) => {
+                if resp.success {
+                    Ok(resp.users)
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    pub fn user_info(&mut self, username: &str) -> Result<UserInfo> {
+        // Ensure service is running
+        self.ensure_service_running()?;
+        
+        // Connect to service
+        let mut stream = self.connect_with_retry(3, FEATURE_USER_MANAGEMENT)?;
+        
+        // Send request
+        self.send_request(&mut stream, &Request::GetUserInfo(UserRequest { username: username.to_string() }))?;
+        
+        // Read response
+        let response = self.read_response(&mut stream)?;
+        
+        match response {
+            Response::UserInfo(resp) => {
+                if resp.success {
+                    resp.info.ok_or_else(|| FaceAuthError::Other(anyhow::anyhow!("Service sent no user info")))
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    /// Health and diagnostics of the running service. Unlike the other
+    /// requests this never starts a dev service: a fresh one says nothing.
+    pub fn status(&mut self) -> Result<ServiceStatus> {
+        // Connect to service
+        let mut stream = self.connect_with_retry(1, FEATURE_STATUS)?;
+        
+        // Send request
+        self.send_request(&mut stream, &Request::Status)?;
+        
+        // Read response
+        let response = self.read_response(&mut stream)?;
+        
+        match response {
+            Response::Status(resp) => {
+                if resp.success {
+                    resp.status.ok_or_else(|| FaceAuthError::Other(anyhow::anyhow!("Service sent no status")))
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    /// Remove all templates, adaptive templates and enrollment images of `username`
+    pub fn delete_user(&mut self, username: &str) -> Result<DeleteUserResponse> {
+        // Ensure service is running
+        self.ensure_service_running()?;
+        
+        // Connect to service
+        let mut stream = self.connect_with_retry(3, FEATURE_USER_MANAGEMENT)?;
+        
+        // Send request
+        self.send_request(&mut stream, &Request::DeleteUser(UserRequest { username: username.to_string() }))?;
+        
+        // Read response
+        let response = self.read_response(&mut stream)?;
+        
+        match response {
+            Response::DeleteUser(resp) => {
+                if resp.success {
+                    Ok(resp)
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
+            _ => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
+            }
+        }
+    }
+    
+    /// Discard adaptive templates learned at or after `since`, or all of them
+    pub fn reset_adaptive(&mut self, username: &str, since: Option<SystemTime>) -> Result<ResetAdaptiveResponse> {
+        // Ensure service is running
+        self.ensure_service_running()?;
+        
+        // Connect to service
+        let mut stream = self.connect_with_retry(3, FEATURE_ADAPTIVE)?;
+        
+        // Send request
+        let request = Request::ResetAdaptive(ResetAdaptiveRequest {
+            username: username.to_string(),
+            since,
+        });
+        self.send_request(&mut stream, &request)?;
+        
+        // Read response
+        let response = self.read_response(&mut stream)?;
+        
+        match response {
+            Response::ResetAdaptive(reset_resp) => {
+                if reset_resp.success {
+                    Ok(reset_resp)
+                } else {
+                    Err(FaceAuthError::Other(anyhow::anyhow!(reset_resp.message)))
+                }
+            }
+            Response::Error(msg) => {
+                Err(FaceAuthError::Other(anyhow::anyhow!("Service error: {}", msg)))
+            }
             _ => {
                 Err(FaceAuthError::Other(anyhow::anyhow!("Unexpected response type")))
             }
@@ -200,13 +540,17 @@ impl ServiceClient {
         Err(FaceAuthError::Other(anyhow::anyhow!("Service failed to start within timeout")))
     }
     
-    fn connect_with_retry(&self, max_retries: u32) -> Result<UnixStream> {
+    fn connect_with_retry(&self, max_retries: u32, required_features: u32) -> Result<UnixStream> {
         for attempt in 0..max_retries {
             match UnixStream::connect(&self.socket_path) {
-                Ok(stream) => {
+                Ok(mut stream) => {
                     // Set timeout
                     stream.set_read_timeout(Some(Duration::from_secs(120)))?;
                     stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+                    
+                    // Make sure we speak the same protocol before sending anything real
+                    let client = format!("suplinux {}", env!("CARGO_PKG_VERSION"));
+                    protocol::client_handshake(&mut stream, &client, required_features)?;
                     return Ok(stream);
                 }
                 Err(e) if attempt < max_retries - 1 => {
@@ -224,72 +568,40 @@ impl ServiceClient {
     }
     
     fn send_request(&self, stream: &mut UnixStream, request: &Request) -> Result<()> {
-        let request_data = bincode::serialize(request)
-            .map_err(|e| FaceAuthError::Other(anyhow::anyhow!("Failed to serialize request: {}", e)))?;
-        let request_len = (request_data.len() as u32).to_le_bytes();
-        
-        stream.write_all(&request_len)?;
-        stream.write_all(&request_data)?;
-        stream.flush()?;
-        
-        Ok(())
+        protocol::write_frame(stream, MSG_TYPE_REQUEST, request)
     }
     
     fn read_response(&self, stream: &mut UnixStream) -> Result<Response> {
-        // Read response length
-        let mut len_buf = [0u8; 4];
-        stream.read_exact(&mut len_buf)?;
-        let response_len = u32::from_le_bytes(len_buf) as usize;
-        
-        if response_len > 1024 * 1024 {
-            return Err(FaceAuthError::Other(anyhow::anyhow!("Response too large")));
-        }
-        
-        // Read response
-        let mut response_buf = vec![0u8; response_len];
-        stream.read_exact(&mut response_buf)?;
-        
-        // Deserialize response
-        let response: Response = bincode::deserialize(&response_buf)
-            .map_err(|e| FaceAuthError::Other(anyhow::anyhow!("Failed to deserialize response: {}", e)))?;
-        
-        Ok(response)
+        protocol::read_frame(stream)?.into_response()
     }
     
     fn read_enrollment_with_preview(&self, stream: &mut UnixStream) -> Result<Response> {
         // Track the preview area
         let mut preview_height = 0;
         let mut first_frame = true;
+        // Latest status (pose prompt or quality hint), shown under the preview
+        let mut status: Option<String> = None;
         
         let result = (|| -> Result<Response> {
             loop {
-                // Read message type indicator
-                let mut type_buf = [0u8; 1];
-                stream.read_exact(&mut type_buf)?;
-                
-                // Read message length
-                let mut len_buf = [0u8; 4];
-                stream.read_exact(&mut len_buf)?;
-                let msg_len = u32::from_le_bytes(len_buf) as usize;
-                
-                if msg_len > 1024 * 1024 {
-                    return Err(FaceAuthError::Other(anyhow::anyhow!("Message too large")));
+                // Read next frame - either a stream update or the final response
+                let frame = protocol::read_frame(stream)?;
+                if !protocol::is_supported_version(frame.version) {
+                    return frame.into_response();  // Reports the mismatch
                 }
                 
-                // Read message data
-                let mut msg_buf = vec![0u8; msg_len];
-                stream.read_exact(&mut msg_buf)?;
-                
-                match type_buf[0] {
+                match frame.msg_type {
                     MSG_TYPE_STREAM => {
                         // Handle stream message
-                        let stream_msg: StreamMessage = bincode::deserialize(&msg_buf)
-                            .map_err(|e| FaceAuthError::Other(anyhow::anyhow!("Failed to deserialize stream message: {}", e)))?;
+                        let stream_msg: StreamMessage = frame.decode()?;
                         
                         match stream_msg {
                             StreamMessage::PreviewFrame { ascii, captured: _, total: _ } => {
                                 // Split ASCII into lines for proper handling
-                                let lines: Vec<&str> = ascii.lines().collect();
+                                let mut lines: Vec<&str> = ascii.lines().collect();
+                                if let Some(ref status) = status {
+                                    lines.push(status);
+                                }
                                 let frame_height = lines.len();
                                 
                                 if first_frame {
@@ -363,23 +675,23 @@ impl ServiceClient {
                                 
                                 io::stdout().flush().ok();
                             }
-                            StreamMessage::StatusUpdate { message: _ } => {
-                                // Status updates appear below the preview
-                                // Don't print during streaming to avoid disrupting the display
-                                // These will be shown in the final response
+                            StreamMessage::StatusUpdate { message } => {
+                                // Status updates appear below the preview as part of
+                                // the next frame, so they don't disrupt the display
+                                status = Some(format!("💬 {}", message));
                             }
                             StreamMessage::Complete => {
                                 // Move cursor below preview for final message
                                 println!("\n"); // Add spacing before final message
                                 continue;
                             }
+                            // Only sent to authentication clients
+                            StreamMessage::AuthProgress { .. } => {}
                         }
                     }
                     MSG_TYPE_RESPONSE => {
                         // Final response received
-                        let response: Response = bincode::deserialize(&msg_buf)
-                            .map_err(|e| FaceAuthError::Other(anyhow::anyhow!("Failed to deserialize response: {}", e)))?;
-                        return Ok(response);
+                        return frame.into_response();
                     }
                     _ => {
                         return Err(FaceAuthError::Other(anyhow::anyhow!("Unknown message type")));
@@ -1,5 +1,10 @@
+pub mod arbiter;
+pub mod cancel;
 pub mod client;
 pub mod protocol;
+pub mod signing;
+pub mod stats;
+pub mod systemd;
 
 pub use client::ServiceClient;
 pub use protocol::*;
\ No newline at end of file
@@ -1,12 +1,68 @@
-use serde::{Serialize, Deserialize};
+use crate::common::{FaceAuthError, Result};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use std::io::{Read, Write};
+use std::path::PathBuf;
 use std::time::SystemTime;
 
+// Wire format version. Only bump it when a message changes in a way older
+// peers can't decode - fields reordered, removed or retyped. Additive changes
+// keep the version and are advertised with a FEATURE_* bit instead:
+// - new Request/Response/StreamMessage variants go at the end of the enum
+// - new fields go at the end of the message's last struct. Older peers ignore
+//   the extra bytes, and a field an older peer leaves out decodes as zero,
+//   false, None or empty, so that value must mean "not sent".
+pub const PROTOCOL_VERSION: u16 = 1;
+
+// Oldest wire format still accepted
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+// Feature flags advertised in frame headers and negotiated by Hello
+pub const FEATURE_SIGNED_AUTH: u32 = 1 << 0;   // AuthResponse carries an HMAC signature
+pub const FEATURE_STREAMING: u32 = 1 << 1;     // Enroll/enhance stream preview frames
+pub const FEATURE_KEY_ROTATION: u32 = 1 << 2;  // Request::RotateKey
+pub const FEATURE_CANCEL: u32 = 1 << 3;        // Request::Cancel and hang-up detection
+pub const FEATURE_LOCKOUT: u32 = 1 << 4;       // AuthResponse::locked_until and Request::Unlock
+pub const FEATURE_LIVENESS: u32 = 1 << 5;      // AuthResponse::liveness_failures
+pub const FEATURE_GUIDED_ENROLL: u32 = 1 << 6; // EnrollRequest::guided pose prompts
+pub const FEATURE_ADAPTIVE: u32 = 1 << 7;      // Request::ResetAdaptive
+pub const FEATURE_PROFILES: u32 = 1 << 8;      // Named enrollment profiles, Request::ListProfiles/DeleteProfile
+pub const FEATURE_USER_MANAGEMENT: u32 = 1 << 9; // Request::ListUsers/GetUserInfo/DeleteUser
+pub const FEATURE_STATUS: u32 = 1 << 10;       // Request::Status
+pub const FEATURE_AUTH_PROGRESS: u32 = 1 << 11; // AuthRequest::stream_progress, StreamMessage::AuthProgress
+pub const FEATURE_MIGRATE_TEMPLATES: u32 = 1 << 12; // Request::MigrateTemplates
+pub const FEATURE_NOT_APPLICABLE: u32 = 1 << 13; // AuthResponse::not_applicable, AuthProgress::Started
+pub const SUPPORTED_FEATURES: u32 = FEATURE_SIGNED_AUTH | FEATURE_STREAMING | FEATURE_KEY_ROTATION
+    | FEATURE_CANCEL | FEATURE_LOCKOUT | FEATURE_LIVENESS | FEATURE_GUIDED_ENROLL | FEATURE_ADAPTIVE
+    | FEATURE_PROFILES | FEATURE_USER_MANAGEMENT | FEATURE_STATUS | FEATURE_AUTH_PROGRESS
+    | FEATURE_MIGRATE_TEMPLATES | FEATURE_NOT_APPLICABLE;
+
 // Request types
+//
+// Variant order is part of the wire format: only ever append new variants.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
     Authenticate(AuthRequest),
     Enroll(EnrollRequest),
     Enhance(EnhanceRequest),
+    RotateKey,
+    Hello(HelloRequest),
+    Cancel,  // Abort the request in progress on this connection
+    Unlock(UnlockRequest),
+    ResetAdaptive(ResetAdaptiveRequest),
+    ListProfiles(ProfilesRequest),
+    DeleteProfile(DeleteProfileRequest),
+    ListUsers,
+    GetUserInfo(UserRequest),
+    DeleteUser(UserRequest),
+    Status,
+    MigrateTemplates,  // Encrypt templates written before encryption (root only)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HelloRequest {
+    pub protocol_version: u16,
+    pub features: u32,
+    pub client: String,  // e.g. "pam_suplinux 0.1.0", for logs
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -14,29 +70,86 @@ pub struct AuthRequest {
     pub username: String,
     pub challenge: Vec<u8>,
     pub timestamp: SystemTime,
+    pub pam_service: Option<String>,  // PAM_SERVICE of the caller, selects the [policy.<service>] table
+    pub pam_tty: Option<String>,      // PAM_TTY, e.g. ":0" or "/dev/pts/2"
+    pub pam_rhost: Option<String>,    // PAM_RHOST, set for remote logins
+    pub stream_progress: bool,        // Send StatusUpdate messages (face detected...) before the response
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnlockRequest {
+    pub username: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResetAdaptiveRequest {
+    pub username: String,
+    pub since: Option<SystemTime>,  // Only discard templates learned at or after this; None for all
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserRequest {
+    pub username: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfilesRequest {
+    pub username: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeleteProfileRequest {
+    pub username: String,
+    pub profile: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EnrollRequest {
     pub username: String,
+    pub profile: String,       // Profile to create or replace, see storage::DEFAULT_PROFILE
     pub enable_preview: bool,  // Enable ASCII preview during enrollment
+    pub guided: bool,          // Prompt through head poses (needs enable_preview)
+    pub with_glasses: bool,    // Guided: repeat the poses with glasses on
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EnhanceRequest {
     pub username: String,
+    pub profile: String,       // Existing profile to add captures to
     pub additional_captures: Option<u32>,
     pub replace_weak: bool,
     pub enable_preview: bool,  // Enable ASCII preview during enhancement
 }
 
 // Response types
+//
+// Only ever append new variants. `Error` must stay at index 3: pre-framing
+// clients decode it, which is how they learn that they are out of date.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Response {
     Auth(AuthResponse),
     Enroll(EnrollResponse),
     Enhance(EnhanceResponse),
     Error(String),
+    RotateKey(RotateKeyResponse),
+    Hello(HelloResponse),
+    CameraBusy(String),  // Another request holds the camera; try again later
+    Unlock(UnlockResponse),
+    ResetAdaptive(ResetAdaptiveResponse),
+    Profiles(ProfilesResponse),
+    DeleteProfile(DeleteProfileResponse),
+    Users(UsersResponse),
+    UserInfo(UserInfoResponse),
+    DeleteUser(DeleteUserResponse),
+    Status(StatusResponse),
+    MigrateTemplates(MigrateTemplatesResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HelloResponse {
+    pub protocol_version: u16,
+    pub features: u32,           // Features supported by both sides
+    pub service_version: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -44,8 +157,136 @@ pub struct AuthResponse {
     pub success: bool,
     pub message: String,
     pub attempts: u32,
-    pub signature: Vec<u8>,
+    pub challenge: Vec<u8>,  // Echo of the request challenge, covered by the signature
+    pub signature: Vec<u8>,  // HMAC-SHA256, see service::signing
     pub timestamp: SystemTime,
+    pub locked_until: Option<SystemTime>,  // Set when too many failures locked the user out
+    pub liveness_failures: u32,  // Matching frames rejected by the anti-spoofing checks
+    pub not_applicable: bool,  // Face auth is off for this caller (policy) - not a failure
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnlockResponse {
+    pub success: bool,
+    pub message: String,
+    pub was_locked: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResetAdaptiveResponse {
+    pub success: bool,
+    pub message: String,
+    pub removed: usize,
+    pub remaining: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub camera: String,
+    pub created: SystemTime,
+    pub embeddings: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProfilesResponse {
+    pub success: bool,
+    pub message: String,
+    pub profiles: Vec<ProfileInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeleteProfileResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UsersResponse {
+    pub success: bool,
+    pub message: String,
+    pub users: Vec<String>,  // Everyone for root, otherwise only the caller if enrolled
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserInfo {
+    pub username: String,
+    pub storage_version: u32,
+    pub profiles: Vec<ProfileInfo>,
+    pub adaptive_templates: usize,
+    pub last_adaptive: Option<SystemTime>,  // When the newest adaptive template was learned
+    pub enrollment_images: usize,
+    pub failures: u32,                      // Failed attempts counting towards lockout
+    pub locked_until: Option<SystemTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserInfoResponse {
+    pub success: bool,
+    pub message: String,
+    pub info: Option<UserInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeleteUserResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelStatus {
+    pub role: String,            // "detector", "recognizer" or "liveness"
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: Option<String>,  // Hex digest taken when the models were loaded
+    pub error: Option<String>,   // Why the file couldn't be hashed
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CameraStatus {
+    pub device: String,  // Device actually opened, e.g. /dev/video2 for device_index = 999
+    pub format: String,  // Resolution and pixel format the driver settled on
+    pub opened: SystemTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StageStatus {
+    pub stage: String,
+    pub samples: u64,
+    pub average_ms: f64,
+    pub last_ms: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErrorStatus {
+    pub time: SystemTime,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceStatus {
+    pub service_version: String,
+    pub protocol_version: u16,
+    pub started: SystemTime,
+    pub uptime_secs: u64,
+    pub workers: usize,
+    pub models: Vec<ModelStatus>,
+    pub liveness_enabled: bool,
+    pub camera_config: String,           // Camera as configured, e.g. "auto-detect"
+    pub camera: Option<CameraStatus>,    // Last camera a request opened, None until one has
+    pub authentications: u64,
+    pub auth_successes: u64,
+    pub enrollments: u64,
+    pub enhancements: u64,
+    pub last_error: Option<ErrorStatus>, // Only reported to root
+    pub stages: Vec<StageStatus>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatusResponse {
+    pub success: bool,
+    pub message: String,
+    pub status: Option<ServiceStatus>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -63,6 +304,21 @@ pub struct EnhanceResponse {
     pub replaced_count: usize,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RotateKeyResponse {
+    pub success: bool,
+    pub message: String,
+    pub rotated_count: usize,
+    pub key_id: String,  // Hex id of the now-active key
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MigrateTemplatesResponse {
+    pub success: bool,
+    pub message: String,
+    pub migrated: usize,
+}
+
 // Streaming messages for real-time updates
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum StreamMessage {
@@ -74,12 +330,272 @@ pub enum StreamMessage {
     StatusUpdate {
         message: String,    // Status message to display
     },
-    Complete,              // Enrollment/enhancement complete, final response follows
+    Complete,              // Enrollment/enhancement/authentication complete, final response follows
+    AuthProgress {
+        step: AuthProgress,  // Authentication progress; the client picks the wording
+    },
+}
+
+// Progress of an authentication. Codes rather than text: stream messages are
+// unsigned, and the PAM module must not show text it didn't write itself.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthProgress {
+    FaceDetected,
+    FaceLost,
+    Started,  // Past the policy and lockout checks, the camera is running
 }
 
-// Message type indicators for stream protocol
+// Message type indicators carried in the frame header
 pub const MSG_TYPE_RESPONSE: u8 = 0;  // Final response
 pub const MSG_TYPE_STREAM: u8 = 1;    // Stream update
+pub const MSG_TYPE_REQUEST: u8 = 2;   // Client request
 
 // Socket path constant
-pub const SOCKET_PATH: &str = "/run/suplinux/service.sock";
\ No newline at end of file
+pub const SOCKET_PATH: &str = "/run/suplinux/service.sock";
+
+// Frame layout (integers little endian):
+//   magic[4] | version u16 | features u32 | msg_type u8 | length u32 | bincode payload
+// Magic and version sit at fixed offsets in every protocol version so that a
+// peer can always tell it is talking to an incompatible build.
+pub const FRAME_MAGIC: &[u8; 4] = b"SUPL";
+const FRAME_HEADER_SIZE: usize = 4 + 2 + 4 + 1 + 4;
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+// Zero bytes appended before decoding, standing in for fields added after the
+// peer was built (see PROTOCOL_VERSION)
+const MISSING_FIELDS_PADDING: usize = 256;
+
+/// Whether frames stamped with `version` can be decoded by this build
+pub fn is_supported_version(version: u16) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+/// A message read off the socket, not yet decoded
+pub struct Frame {
+    pub version: u16,
+    pub features: u32,
+    pub msg_type: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T> {
+        // bincode ignores trailing bytes, so the padding is only read when the
+        // peer's message ends before ours would
+        let mut payload = Vec::with_capacity(self.payload.len() + MISSING_FIELDS_PADDING);
+        payload.extend_from_slice(&self.payload);
+        payload.resize(self.payload.len() + MISSING_FIELDS_PADDING, 0);
+        bincode::deserialize(&payload).map_err(|e| FaceAuthError::Protocol(format!(
+            "Failed to decode message: {}", e
+        )))
+    }
+
+    /// Decode a final response, turning version mismatches into a readable error
+    pub fn into_response(self) -> Result<Response> {
+        if !is_supported_version(self.version) {
+            // Response::Error has the same encoding in every version
+            let detail = match self.decode::<Response>() {
+                Ok(Response::Error(msg)) => msg,
+                _ => "no further details".to_string(),
+            };
+            return Err(version_mismatch(self.version, &detail));
+        }
+        if self.msg_type != MSG_TYPE_RESPONSE {
+            return Err(FaceAuthError::Protocol(format!(
+                "Expected a response, got message type {}", self.msg_type
+            )));
+        }
+        self.decode()
+    }
+}
+
+/// Serialize `message` and write it as a single frame
+pub fn write_frame<W: Write, T: Serialize>(writer: &mut W, msg_type: u8, message: &T) -> Result<()> {
+    let payload = bincode::serialize(message).map_err(|e| FaceAuthError::Protocol(format!(
+        "Failed to encode message: {}", e
+    )))?;
+
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(FRAME_MAGIC);
+    frame.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    frame.extend_from_slice(&SUPPORTED_FEATURES.to_le_bytes());
+    frame.push(msg_type);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&payload);
+
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one frame. Fails with `FaceAuthError::Protocol` if the peer does not
+/// speak the framed protocol at all; version checks are left to the caller.
+pub fn read_frame<R: Read>(reader: &mut R) -> Result<Frame> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != FRAME_MAGIC {
+        return Err(FaceAuthError::Protocol(
+            "Peer does not use the framed protocol (built before protocol versioning?)".into()
+        ));
+    }
+
+    let mut header = [0u8; FRAME_HEADER_SIZE - 4];
+    reader.read_exact(&mut header)?;
+    let version = u16::from_le_bytes([header[0], header[1]]);
+    let features = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+    let msg_type = header[6];
+    let len = u32::from_le_bytes([header[7], header[8], header[9], header[10]]) as usize;
+
+    if len > MAX_FRAME_SIZE {
+        return Err(FaceAuthError::Protocol(format!("Message too large: {} bytes", len)));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+
+    Ok(Frame { version, features, msg_type, payload })
+}
+
+/// Exchange Hello messages and make sure the service offers `required_features`
+pub fn client_handshake<S: Read + Write>(stream: &mut S, client: &str, required_features: u32) -> Result<HelloResponse> {
+    let hello = Request::Hello(HelloRequest {
+        protocol_version: PROTOCOL_VERSION,
+        features: SUPPORTED_FEATURES,
+        client: client.to_string(),
+    });
+    write_frame(stream, MSG_TYPE_REQUEST, &hello)?;
+
+    let frame = match read_frame(stream) {
+        Ok(f) => f,
+        Err(FaceAuthError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            // Services that predate framing just drop the connection
+            return Err(FaceAuthError::Protocol(
+                "Service closed the connection during handshake - it is probably older than this client".into()
+            ));
+        }
+        Err(e) => return Err(e),
+    };
+
+    match frame.into_response()? {
+        Response::Hello(hello) => {
+            let missing = required_features & !hello.features;
+            if missing != 0 {
+                return Err(FaceAuthError::Protocol(format!(
+                    "Service {} lacks required features {:#x}", hello.service_version, missing
+                )));
+            }
+            Ok(hello)
+        }
+        Response::Error(msg) => Err(FaceAuthError::Protocol(format!("Handshake rejected: {}", msg))),
+        _ => Err(FaceAuthError::Protocol("Unexpected response to Hello".into())),
+    }
+}
+
+pub fn version_mismatch(peer_version: u16, detail: &str) -> FaceAuthError {
+    FaceAuthError::Protocol(format!(
+        "Protocol version mismatch: peer speaks v{}, this build speaks v{} to v{} ({}). \
+         Upgrade suplinux-service and pam_suplinux.so to compatible releases.",
+        peer_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, detail
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // Frame header by hand, for frames write_frame refuses to produce
+    fn raw_frame(magic: &[u8; 4], version: u16, len: u32, payload: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(magic);
+        frame.extend_from_slice(&version.to_le_bytes());
+        frame.extend_from_slice(&SUPPORTED_FEATURES.to_le_bytes());
+        frame.push(MSG_TYPE_RESPONSE);
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, MSG_TYPE_RESPONSE, &Response::Error("boom".into())).unwrap();
+
+        let frame = read_frame(&mut buffer.as_slice()).unwrap();
+        assert_eq!(frame.version, PROTOCOL_VERSION);
+        assert_eq!(frame.features, SUPPORTED_FEATURES);
+        match frame.into_response().unwrap() {
+            Response::Error(msg) => assert_eq!(msg, "boom"),
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        let frame = raw_frame(b"NOPE", PROTOCOL_VERSION, 0, &[]);
+        assert!(matches!(read_frame(&mut frame.as_slice()), Err(FaceAuthError::Protocol(_))));
+    }
+
+    #[test]
+    fn oversized_frame_is_rejected() {
+        let frame = raw_frame(FRAME_MAGIC, PROTOCOL_VERSION, MAX_FRAME_SIZE as u32 + 1, &[]);
+        match read_frame(&mut frame.as_slice()) {
+            Err(FaceAuthError::Protocol(msg)) => assert!(msg.contains("too large"), "{}", msg),
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("oversized frame accepted"),
+        }
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        let payload = bincode::serialize(&Response::Error("old".into())).unwrap();
+        for version in [MIN_PROTOCOL_VERSION - 1, PROTOCOL_VERSION + 1] {
+            let frame = raw_frame(FRAME_MAGIC, version, payload.len() as u32, &payload);
+            let frame = read_frame(&mut frame.as_slice()).unwrap();
+            match frame.into_response() {
+                // The peer's Error text survives the mismatch
+                Err(FaceAuthError::Protocol(msg)) => assert!(msg.contains("old"), "{}", msg),
+                other => panic!("v{} accepted: {:?}", version, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn older_peer_message_decodes_with_defaults() {
+        // AuthRequest as sent by a peer built before the PAM item fields
+        #[derive(Serialize)]
+        struct OldAuthRequest {
+            username: String,
+            challenge: Vec<u8>,
+            timestamp: SystemTime,
+            pam_service: Option<String>,
+        }
+        #[derive(Serialize)]
+        enum OldRequest {
+            Authenticate(OldAuthRequest),
+        }
+
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, MSG_TYPE_REQUEST, &OldRequest::Authenticate(OldAuthRequest {
+            username: "alice".into(),
+            challenge: vec![1, 2, 3],
+            timestamp,
+            pam_service: Some("sudo".into()),
+        })).unwrap();
+
+        let frame = read_frame(&mut buffer.as_slice()).unwrap();
+        match frame.decode::<Request>().unwrap() {
+            Request::Authenticate(request) => {
+                assert_eq!(request.username, "alice");
+                assert_eq!(request.challenge, vec![1, 2, 3]);
+                assert_eq!(request.timestamp, timestamp);
+                assert_eq!(request.pam_service.as_deref(), Some("sudo"));
+                assert_eq!(request.pam_tty, None);
+                assert_eq!(request.pam_rhost, None);
+                assert!(!request.stream_progress);
+            }
+            other => panic!("unexpected request {:?}", other),
+        }
+    }
+}
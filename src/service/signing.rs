@@ -0,0 +1,215 @@
+use crate::common::{FaceAuthError, Result};
+use crate::service::protocol::AuthResponse;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Secret shared by the PAM module and the service, owned by root and
+// readable by the service group only (root:suplinux 0640)
+pub const AUTH_SECRET_PATH: &str = "/etc/suplinux/auth.secret";
+pub const MIN_SECRET_LEN: usize = 32;
+
+// Domain separation so the MAC can't be confused with any other use of the secret
+const SIGNATURE_CONTEXT: &[u8] = b"suplinux-auth-response-v1";
+
+/// Load the shared authentication secret, refusing files with unsafe ownership or permissions
+pub fn load_auth_secret(path: &Path) -> Result<Vec<u8>> {
+    let metadata = fs::metadata(path).map_err(|e| FaceAuthError::Storage(format!(
+        "Failed to read auth secret {:?}: {}", path, e
+    )))?;
+
+    let euid = unsafe { libc::geteuid() };
+    if metadata.uid() != 0 && metadata.uid() != euid {
+        return Err(FaceAuthError::Storage(format!(
+            "Refusing auth secret {:?}: owned by UID {} instead of root", path, metadata.uid()
+        )));
+    }
+    if metadata.mode() & 0o027 != 0 {
+        return Err(FaceAuthError::Storage(format!(
+            "Refusing auth secret {:?}: permissions {:o} are too open (expected 640 or stricter)",
+            path, metadata.mode() & 0o777
+        )));
+    }
+
+    let secret = fs::read(path)?;
+    if secret.len() < MIN_SECRET_LEN {
+        return Err(FaceAuthError::Storage(format!(
+            "Auth secret {:?} is too short ({} bytes, need at least {})", path, secret.len(), MIN_SECRET_LEN
+        )));
+    }
+
+    Ok(secret)
+}
+
+/// HMAC-SHA256 over the challenge and every security-relevant response field
+pub fn sign_auth_response(secret: &[u8], username: &str, response: &AuthResponse) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(secret)
+        .expect("HMAC accepts keys of any length");
+    update_mac(&mut mac, username, response);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Verify a response signature in constant time
+pub fn verify_auth_response(secret: &[u8], username: &str, response: &AuthResponse) -> bool {
+    let mut mac = match HmacSha256::new_from_slice(secret) {
+        Ok(m) => m,
+        Err(_) => return false,
+    };
+    update_mac(&mut mac, username, response);
+    mac.verify_slice(&response.signature).is_ok()
+}
+
+/// Check the response timestamp lies between the request time and now (plus clock slack)
+/// and is no older than `max_age`
+pub fn is_fresh(response_time: SystemTime, request_time: SystemTime, max_age: Duration) -> bool {
+    let slack = Duration::from_secs(1);
+    let now = SystemTime::now();
+
+    if response_time + slack < request_time || response_time > now + slack {
+        return false;
+    }
+
+    match now.duration_since(response_time) {
+        Ok(age) => age <= max_age,
+        Err(_) => true,  // Within slack in the future
+    }
+}
+
+fn update_mac(mac: &mut HmacSha256, username: &str, response: &AuthResponse) {
+    mac.update(SIGNATURE_CONTEXT);
+    update_field(mac, &response.challenge);
+    update_field(mac, username.as_bytes());
+    mac.update(&[response.success as u8]);
+    mac.update(&response.attempts.to_le_bytes());
+    update_field(mac, response.message.as_bytes());
+
+    let since_epoch = response.timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    mac.update(&since_epoch.as_secs().to_le_bytes());
+    mac.update(&since_epoch.subsec_nanos().to_le_bytes());
+
+    match response.locked_until {
+        Some(until) => {
+            let until = until.duration_since(UNIX_EPOCH).unwrap_or_default();
+            mac.update(&[1]);
+            mac.update(&until.as_secs().to_le_bytes());
+            mac.update(&until.subsec_nanos().to_le_bytes());
+        }
+        None => mac.update(&[0]),
+    }
+
+    mac.update(&response.liveness_failures.to_le_bytes());
+
+    // Only when set, so responses from services without the flag still verify
+    if response.not_applicable {
+        mac.update(&[1]);
+    }
+}
+
+// Length-prefix variable-size fields so concatenations can't collide
+fn update_field(mac: &mut HmacSha256, data: &[u8]) {
+    mac.update(&(data.len() as u64).to_le_bytes());
+    mac.update(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+    fn signed_response(request_time: SystemTime) -> AuthResponse {
+        let mut response = AuthResponse {
+            success: true,
+            message: "Authenticated after 3 attempts".to_string(),
+            attempts: 3,
+            challenge: vec![42; 32],
+            signature: vec![],
+            timestamp: request_time,
+            locked_until: None,
+            liveness_failures: 0,
+            not_applicable: false,
+        };
+        response.signature = sign_auth_response(SECRET, "alice", &response);
+        response
+    }
+
+    #[test]
+    fn valid_signature_verifies() {
+        let response = signed_response(SystemTime::now());
+        assert!(verify_auth_response(SECRET, "alice", &response));
+    }
+
+    #[test]
+    fn bad_signature_is_rejected() {
+        let mut response = signed_response(SystemTime::now());
+        response.signature[0] ^= 1;
+        assert!(!verify_auth_response(SECRET, "alice", &response));
+
+        let response = signed_response(SystemTime::now());
+        assert!(!verify_auth_response(b"another secret of at least 32 bytes", "alice", &response));
+    }
+
+    #[test]
+    fn not_applicable_is_signed() {
+        let request_time = SystemTime::now();
+        let mut response = signed_response(request_time);
+        response.not_applicable = true;
+        assert!(!verify_auth_response(SECRET, "alice", &response));
+
+        response.signature = sign_auth_response(SECRET, "alice", &response);
+        assert!(verify_auth_response(SECRET, "alice", &response));
+        response.not_applicable = false;
+        assert!(!verify_auth_response(SECRET, "alice", &response));
+    }
+
+    #[test]
+    fn signed_fields_cannot_change() {
+        let response = signed_response(SystemTime::now());
+
+        let mut flipped = response.clone();
+        flipped.success = false;
+        assert!(!verify_auth_response(SECRET, "alice", &flipped));
+
+        let mut locked = response.clone();
+        locked.locked_until = Some(SystemTime::now());
+        assert!(!verify_auth_response(SECRET, "alice", &locked));
+
+        assert!(!verify_auth_response(SECRET, "bob", &response));
+    }
+
+    #[test]
+    fn wrong_challenge_is_rejected() {
+        let mut response = signed_response(SystemTime::now());
+        response.challenge[0] ^= 1;
+        assert!(!verify_auth_response(SECRET, "alice", &response));
+    }
+
+    #[test]
+    fn stale_response_is_not_fresh() {
+        let request_time = SystemTime::now() - Duration::from_secs(30);
+        assert!(!is_fresh(request_time, request_time, Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn response_before_request_is_not_fresh() {
+        let request_time = SystemTime::now();
+        let response_time = request_time - Duration::from_secs(3);
+        assert!(!is_fresh(response_time, request_time, Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn fresh_within_slack() {
+        let request_time = SystemTime::now();
+        // Clocks may disagree by up to a second either way
+        assert!(is_fresh(request_time - Duration::from_millis(500), request_time, Duration::from_secs(5)));
+        assert!(is_fresh(SystemTime::now() + Duration::from_millis(500), request_time, Duration::from_secs(5)));
+        assert!(!is_fresh(SystemTime::now() + Duration::from_secs(3), request_time, Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::camera::FrameSource;
+use crate::common::Config;
+use crate::service::protocol::{CameraStatus, ErrorStatus, ModelStatus, StageStatus};
+use crate::storage::encryption::hex;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Pipeline stages timed for `Request::Status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    CameraOpen,
+    Capture,
+    Detection,
+    Embedding,
+    Liveness,
+    Authentication,  // Whole request, camera open to decision
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::CameraOpen,
+        Stage::Capture,
+        Stage::Detection,
+        Stage::Embedding,
+        Stage::Liveness,
+        Stage::Authentication,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::CameraOpen => "camera_open",
+            Stage::Capture => "capture",
+            Stage::Detection => "detection",
+            Stage::Embedding => "embedding",
+            Stage::Liveness => "liveness",
+            Stage::Authentication => "authentication",
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct StageTotals {
+    samples: u64,
+    total: Duration,
+    last: Duration,
+}
+
+#[derive(Default)]
+struct Counters {
+    authentications: u64,
+    auth_successes: u64,
+    enrollments: u64,
+    enhancements: u64,
+    models: Vec<ModelStatus>,
+    last_error: Option<ErrorStatus>,
+    camera: Option<CameraStatus>,
+    stages: [StageTotals; Stage::ALL.len()],
+}
+
+/// The loaded models plus request counts, timings and the last error since
+/// the service started, shared by all workers
+pub struct ServiceStats {
+    started: Instant,
+    started_at: SystemTime,
+    workers: usize,
+    counters: Mutex<Counters>,
+}
+
+impl ServiceStats {
+    /// `models` as returned by `model_status` for the models the service loaded,
+    /// `workers` the size of the pool it started with
+    pub fn new(models: Vec<ModelStatus>, workers: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+            workers,
+            counters: Mutex::new(Counters { models, ..Counters::default() }),
+        }
+    }
+
+    fn counters(&self) -> std::sync::MutexGuard<'_, Counters> {
+        // Counters stay usable even if a worker panicked while holding them
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn started_at(&self) -> SystemTime {
+        self.started_at
+    }
+
+    /// Fixed at startup; a reloaded `worker_threads` only applies after a restart
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn models(&self) -> Vec<ModelStatus> {
+        self.counters().models.clone()
+    }
+
+    /// Replace the reported models after a config reload
+    pub fn set_models(&self, models: Vec<ModelStatus>) {
+        self.counters().models = models;
+    }
+
+    pub fn record_stage(&self, stage: Stage, elapsed: Duration) {
+        let mut counters = self.counters();
+        let totals = &mut counters.stages[stage as usize];
+        totals.samples += 1;
+        totals.total += elapsed;
+        totals.last = elapsed;
+    }
+
+    /// Run `f` and add its duration to `stage`
+    pub fn time<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.record_stage(stage, started.elapsed());
+        result
+    }
+
+    pub fn record_authentication(&self, success: bool) {
+        let mut counters = self.counters();
+        counters.authentications += 1;
+        if success {
+            counters.auth_successes += 1;
+        }
+    }
+
+    pub fn record_enrollment(&self) {
+        self.counters().enrollments += 1;
+    }
+
+    pub fn record_enhancement(&self) {
+        self.counters().enhancements += 1;
+    }
+
+    pub fn record_error(&self, message: impl Into<String>) {
+        self.counters().last_error = Some(ErrorStatus {
+            time: SystemTime::now(),
+            message: message.into(),
+        });
+    }
+
+    /// Remember which device a request actually opened, e.g. what
+    /// `device_index = 999` resolved to
+    pub fn record_camera(&self, source: &dyn FrameSource) {
+        self.counters().camera = Some(CameraStatus {
+            device: source.label(),
+            format: source.format(),
+            opened: SystemTime::now(),
+        });
+    }
+
+    pub fn authentications(&self) -> (u64, u64) {
+        let counters = self.counters();
+        (counters.authentications, counters.auth_successes)
+    }
+
+    pub fn enrollments(&self) -> (u64, u64) {
+        let counters = self.counters();
+        (counters.enrollments, counters.enhancements)
+    }
+
+    pub fn last_error(&self) -> Option<ErrorStatus> {
+        self.counters().last_error.clone()
+    }
+
+    pub fn camera(&self) -> Option<CameraStatus> {
+        self.counters().camera.clone()
+    }
+
+    pub fn stages(&self) -> Vec<StageStatus> {
+        let counters = self.counters();
+        Stage::ALL.iter()
+            .map(|stage| {
+                let totals = counters.stages[*stage as usize];
+                StageStatus {
+                    stage: stage.name().to_string(),
+                    samples: totals.samples,
+                    average_ms: if totals.samples > 0 {
+                        totals.total.as_secs_f64() * 1000.0 / totals.samples as f64
+                    } else {
+                        0.0
+                    },
+                    last_ms: totals.last.as_secs_f64() * 1000.0,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Paths and SHA-256 hashes of the models `config` loads. Hashing failures
+/// are reported in place of the hash rather than failing the caller.
+pub fn model_status(config: &Config) -> Vec<ModelStatus> {
+    let mut models = vec![
+        ("detector", config.models.detector_path.as_path()),
+        ("recognizer", config.models.recognizer_path.as_path()),
+    ];
+    if let Some(ref path) = config.models.liveness_path {
+        models.push(("liveness", path.as_path()));
+    }
+
+    models.into_iter()
+        .map(|(role, path)| {
+            let (sha256, error) = match hash_file(path) {
+                Ok(hash) => (Some(hash), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            ModelStatus {
+                role: role.to_string(),
+                path: path.to_path_buf(),
+                size: std::fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+                sha256,
+                error,
+            }
+        })
+        .collect()
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
@@ -0,0 +1,69 @@
+use std::env;
+use std::io;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener};
+use std::time::Duration;
+
+// First file descriptor passed by socket activation, see sd_listen_fds(3)
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Take over the listening socket passed by a systemd `.socket` unit, if
+/// this process was socket activated. The environment variables are cleared
+/// so the descriptor isn't claimed twice or leaked to children.
+pub fn listen_fd() -> io::Result<Option<UnixListener>> {
+    let for_us = env::var("LISTEN_PID").ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .map_or(false, |pid| pid == std::process::id());
+    let count = env::var("LISTEN_FDS").ok()
+        .and_then(|n| n.parse::<i32>().ok())
+        .unwrap_or(0);
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    if !for_us || count == 0 {
+        return Ok(None);
+    }
+    if count > 1 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "Expected one socket from systemd, got {}", count
+        )));
+    }
+
+    let fd = LISTEN_FDS_START;
+    // systemd passes descriptors without close-on-exec
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(unsafe { UnixListener::from_raw_fd(fd) }))
+}
+
+/// Send a state update such as "READY=1" to the service manager, see
+/// sd_notify(3). Does nothing when not run under a `Type=notify` unit.
+pub fn notify(state: &str) -> io::Result<()> {
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let path = path.to_string_lossy();
+
+    let socket = UnixDatagram::unbound()?;
+    let addr = match path.strip_prefix('@') {
+        // Abstract namespace socket
+        Some(name) => SocketAddr::from_abstract_name(name.as_bytes())?,
+        None => SocketAddr::from_pathname(&*path)?,
+    };
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+/// How often to send "WATCHDOG=1" when the unit sets `WatchdogSec=`: half
+/// the configured timeout, as recommended by sd_watchdog_enabled(3)
+pub fn watchdog_interval() -> Option<Duration> {
+    let for_us = env::var("WATCHDOG_PID").ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .map_or(true, |pid| pid == std::process::id());
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (for_us && usec > 0).then(|| Duration::from_micros(usec / 2))
+}
@@ -0,0 +1,211 @@
+use crate::common::{FaceAuthError, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const LOG_FILE_NAME: &str = "audit.jsonl";
+
+/// What the service was asked to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEvent {
+    Authenticate,
+    Enroll,
+    Enhance,
+    Unlock,
+    ResetAdaptive,
+    DeleteProfile,
+    DeleteUser,
+}
+
+/// How the request ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+    LockedOut,
+    Cancelled,
+    Denied,
+    CameraBusy,
+    Error,
+}
+
+impl std::fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+            AuditOutcome::LockedOut => "locked_out",
+            AuditOutcome::Cancelled => "cancelled",
+            AuditOutcome::Denied => "denied",
+            AuditOutcome::CameraBusy => "camera_busy",
+            AuditOutcome::Error => "error",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    pub event: AuditEvent,
+    pub uid: u32,
+    pub pid: u32,
+    pub pam_service: Option<String>,
+    #[serde(default)]
+    pub pam_tty: Option<String>,
+    /// Remote host for remote logins, e.g. over SSH
+    #[serde(default)]
+    pub pam_rhost: Option<String>,
+    pub username: String,
+    pub outcome: AuditOutcome,
+    pub best_similarity: Option<f32>,
+    pub attempts: u32,
+    /// Matching frames rejected by the liveness checks
+    #[serde(default)]
+    pub liveness_failures: u32,
+    pub duration_ms: u64,
+    pub camera_device: Option<String>,
+    pub message: String,
+}
+
+/// Filter for `AuditLog::query`; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct AuditQuery {
+    pub username: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl AuditQuery {
+    fn matches(&self, record: &AuditRecord) -> bool {
+        self.username.as_ref().map_or(true, |u| *u == record.username)
+            && self.since.map_or(true, |t| record.timestamp >= t)
+            && self.until.map_or(true, |t| record.timestamp <= t)
+    }
+}
+
+/// Append-only JSON-lines audit log, rotated by size
+///
+/// The live file is `audit.jsonl`; when it would grow past `max_file_bytes`
+/// it becomes `audit.jsonl.1`, older files shift up, and anything beyond
+/// `max_files` rotated files is deleted.
+pub struct AuditLog {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    write_lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(dir: PathBuf, max_file_bytes: u64, max_files: usize) -> Self {
+        Self {
+            dir,
+            max_file_bytes,
+            max_files,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Open a log for reading only
+    pub fn open(dir: &Path) -> Self {
+        Self::new(dir.to_path_buf(), u64::MAX, 0)
+    }
+
+    pub fn append(&self, record: &AuditRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)
+            .map_err(|e| FaceAuthError::Storage(format!("Failed to serialize audit record: {}", e)))?;
+        line.push(b'\n');
+
+        // Workers log concurrently; rotation must not interleave with writes
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(LOG_FILE_NAME);
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.max_file_bytes {
+            self.rotate()?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(&path)?;
+        file.write_all(&line)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Read matching records from all files, oldest first
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+        let mut records = Vec::new();
+
+        for path in self.files_oldest_first()? {
+            let file = fs::File::open(&path)?;
+            for (line_no, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<AuditRecord>(&line) {
+                    Ok(record) if query.matches(&record) => records.push(record),
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Skipping malformed audit line {}:{}: {}", path.display(), line_no + 1, e),
+                }
+            }
+        }
+
+        Ok(records)
+    }
+
+    fn rotate(&self) -> Result<()> {
+        let rotated = |n: usize| self.dir.join(format!("{}.{}", LOG_FILE_NAME, n));
+
+        if self.max_files == 0 {
+            return Ok(fs::remove_file(self.dir.join(LOG_FILE_NAME))?);
+        }
+
+        let oldest = rotated(self.max_files);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for n in (1..self.max_files).rev() {
+            if rotated(n).exists() {
+                fs::rename(rotated(n), rotated(n + 1))?;
+            }
+        }
+        fs::rename(self.dir.join(LOG_FILE_NAME), rotated(1))?;
+        Ok(())
+    }
+
+    fn files_oldest_first(&self) -> Result<Vec<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        // Rotated files are numbered newest = 1; the live file is newest of all
+        let mut rotated: Vec<(usize, PathBuf)> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let n = name.strip_prefix(LOG_FILE_NAME)?.strip_prefix('.')?.parse().ok()?;
+                Some((n, entry.path()))
+            })
+            .collect();
+        rotated.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let mut files: Vec<PathBuf> = rotated.into_iter().map(|(_, p)| p).collect();
+        let live = self.dir.join(LOG_FILE_NAME);
+        if live.exists() {
+            files.push(live);
+        }
+        Ok(files)
+    }
+}
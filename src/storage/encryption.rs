@@ -0,0 +1,234 @@
+use crate::common::{FaceAuthError, Result};
+use aes_gcm::{Aes256Gcm, Key, Nonce, aead::{Aead, KeyInit, Payload}};
+use rand::{Rng, thread_rng};
+use sha2::{Sha256, Digest};
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+// Envelope layout (all integers little endian):
+//   magic[4] | version u8 | algorithm u8 | key_id[8] | nonce[12] | ciphertext + tag
+// The header and the owning username are bound as associated data, so a file
+// can neither be edited nor moved to another user's name without detection.
+const ENVELOPE_MAGIC: &[u8; 4] = b"SUPT";
+const ENVELOPE_VERSION: u8 = 1;
+const ALGORITHM_AES_256_GCM: u8 = 1;
+
+pub const KEY_SIZE: usize = 32;
+pub const KEY_ID_SIZE: usize = 8;
+const NONCE_SIZE: usize = 12;
+const HEADER_SIZE: usize = 4 + 1 + 1 + KEY_ID_SIZE + NONCE_SIZE;
+
+pub type KeyId = [u8; KEY_ID_SIZE];
+
+#[derive(Debug, Clone)]
+pub struct EnvelopeHeader {
+    pub version: u8,
+    pub algorithm: u8,
+    pub key_id: KeyId,
+    pub nonce: [u8; NONCE_SIZE],
+}
+
+impl EnvelopeHeader {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.extend_from_slice(ENVELOPE_MAGIC);
+        bytes.push(self.version);
+        bytes.push(self.algorithm);
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.nonce);
+        bytes
+    }
+
+    fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_SIZE {
+            return Err(FaceAuthError::TemplateTampered("envelope header truncated".into()));
+        }
+
+        let version = data[4];
+        if version != ENVELOPE_VERSION {
+            return Err(FaceAuthError::Storage(format!(
+                "Unsupported template envelope version {}", version
+            )));
+        }
+
+        let algorithm = data[5];
+        if algorithm != ALGORITHM_AES_256_GCM {
+            return Err(FaceAuthError::Storage(format!(
+                "Unsupported template encryption algorithm {}", algorithm
+            )));
+        }
+
+        let mut key_id = [0u8; KEY_ID_SIZE];
+        key_id.copy_from_slice(&data[6..6 + KEY_ID_SIZE]);
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce.copy_from_slice(&data[6 + KEY_ID_SIZE..HEADER_SIZE]);
+
+        Ok(Self { version, algorithm, key_id, nonce })
+    }
+}
+
+/// Returns true if the data starts with the encrypted template envelope magic
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(ENVELOPE_MAGIC)
+}
+
+/// AES-256-GCM cipher for serialized user templates
+pub struct TemplateCipher {
+    cipher: Aes256Gcm,
+    key_id: KeyId,
+}
+
+impl TemplateCipher {
+    pub fn new(key: &[u8; KEY_SIZE]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+            key_id: derive_key_id(key),
+        }
+    }
+
+    pub fn key_id(&self) -> KeyId {
+        self.key_id
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8], username: &str) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        thread_rng().fill(&mut nonce[..]);
+
+        let header = EnvelopeHeader {
+            version: ENVELOPE_VERSION,
+            algorithm: ALGORITHM_AES_256_GCM,
+            key_id: self.key_id,
+            nonce,
+        };
+        let mut envelope = header.to_bytes();
+        let aad = associated_data(&envelope, username);
+
+        let ciphertext = self.cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+            .map_err(|_| FaceAuthError::Storage("Failed to encrypt user data".into()))?;
+
+        envelope.extend_from_slice(&ciphertext);
+        Ok(envelope)
+    }
+
+    pub fn decrypt(&self, envelope: &[u8], username: &str) -> Result<Vec<u8>> {
+        if !is_encrypted(envelope) {
+            return Err(FaceAuthError::TemplateTampered("missing envelope header".into()));
+        }
+
+        let header = EnvelopeHeader::parse(envelope)?;
+        if header.key_id != self.key_id {
+            return Err(FaceAuthError::Storage(format!(
+                "Template was encrypted with key {} but the active key is {}",
+                hex(&header.key_id), hex(&self.key_id)
+            )));
+        }
+
+        let aad = associated_data(&envelope[..HEADER_SIZE], username);
+        self.cipher
+            .decrypt(Nonce::from_slice(&header.nonce), Payload { msg: &envelope[HEADER_SIZE..], aad: &aad })
+            .map_err(|_| FaceAuthError::TemplateTampered(format!(
+                "authentication tag mismatch for user {}", username
+            )))
+    }
+}
+
+/// Read the envelope header without decrypting
+pub fn read_header(envelope: &[u8]) -> Result<EnvelopeHeader> {
+    if !is_encrypted(envelope) {
+        return Err(FaceAuthError::TemplateTampered("missing envelope header".into()));
+    }
+    EnvelopeHeader::parse(envelope)
+}
+
+pub fn derive_key_id(key: &[u8]) -> KeyId {
+    let digest = Sha256::digest(key);
+    let mut key_id = [0u8; KEY_ID_SIZE];
+    key_id.copy_from_slice(&digest[..KEY_ID_SIZE]);
+    key_id
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Write a file readable only by its owner, replacing any existing file atomically
+pub fn write_private_file(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+    {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp_path)?;
+        // mode() only applies on creation - tighten a stale temp file too
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn associated_data(header: &[u8], username: &str) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    aad.extend_from_slice(username.as_bytes());
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> TemplateCipher {
+        TemplateCipher::new(&[7u8; KEY_SIZE])
+    }
+
+    #[test]
+    fn round_trip() {
+        let envelope = cipher().encrypt(b"template", "alice").unwrap();
+        assert!(is_encrypted(&envelope));
+        assert_eq!(cipher().decrypt(&envelope, "alice").unwrap(), b"template");
+    }
+
+    #[test]
+    fn modified_ciphertext_is_tampered() {
+        let mut envelope = cipher().encrypt(b"template", "alice").unwrap();
+        let last = envelope.len() - 1;
+        envelope[last] ^= 1;
+        assert!(matches!(cipher().decrypt(&envelope, "alice"), Err(FaceAuthError::TemplateTampered(_))));
+    }
+
+    #[test]
+    fn modified_header_is_tampered() {
+        let mut envelope = cipher().encrypt(b"template", "alice").unwrap();
+        // Last nonce byte, covered by the associated data
+        envelope[HEADER_SIZE - 1] ^= 1;
+        assert!(matches!(cipher().decrypt(&envelope, "alice"), Err(FaceAuthError::TemplateTampered(_))));
+    }
+
+    #[test]
+    fn wrong_username_is_tampered() {
+        let envelope = cipher().encrypt(b"template", "alice").unwrap();
+        assert!(matches!(cipher().decrypt(&envelope, "bob"), Err(FaceAuthError::TemplateTampered(_))));
+    }
+
+    #[test]
+    fn plaintext_is_tampered() {
+        assert!(matches!(cipher().decrypt(b"template", "alice"), Err(FaceAuthError::TemplateTampered(_))));
+    }
+
+    #[test]
+    fn other_key_is_reported() {
+        let envelope = cipher().encrypt(b"template", "alice").unwrap();
+        let other = TemplateCipher::new(&[8u8; KEY_SIZE]);
+        assert!(matches!(other.decrypt(&envelope, "alice"), Err(FaceAuthError::Storage(_))));
+    }
+}
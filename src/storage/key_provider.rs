@@ -0,0 +1,351 @@
+use crate::common::{FaceAuthError, Result};
+use crate::common::config::{StorageConfig, KeyProviderKind};
+use crate::storage::encryption::{self, KEY_SIZE};
+use rand::{Rng, thread_rng};
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+pub type TemplateKey = [u8; KEY_SIZE];
+
+/// Source of the template encryption key used by `UserStore`
+///
+/// Providers keep the current key plus, while a rotation is in progress, the
+/// previous one so that files not yet re-encrypted can still be read.
+pub trait KeyProvider: Send + Sync {
+    /// Short human-readable description for logs
+    fn describe(&self) -> String;
+
+    /// Return the current key, generating and storing one on first use
+    fn current_key(&self) -> Result<TemplateKey>;
+
+    /// Return the previous key if a rotation has not completed
+    fn previous_key(&self) -> Result<Option<TemplateKey>>;
+
+    /// Make `key` current, keeping the old current key as previous
+    fn install_key(&self, key: &TemplateKey) -> Result<()>;
+
+    /// Forget the previous key once every file uses the current one
+    fn clear_previous(&self) -> Result<()>;
+}
+
+pub fn generate_key() -> TemplateKey {
+    let mut key = [0u8; KEY_SIZE];
+    thread_rng().fill(&mut key[..]);
+    key
+}
+
+/// Build the provider selected by `[storage] key_provider`
+pub fn from_config(storage: &StorageConfig, data_dir: &Path) -> Result<Box<dyn KeyProvider>> {
+    let key_file = storage.key_file.clone()
+        .unwrap_or_else(|| default_key_file(data_dir));
+
+    match storage.key_provider {
+        KeyProviderKind::File => Ok(Box::new(FileKeyProvider::new(key_file))),
+        KeyProviderKind::Keyring => {
+            let fallback = if storage.keyring_file_fallback { Some(key_file) } else { None };
+            Ok(Box::new(KeyringKeyProvider::new(&storage.keyring_description, fallback)?))
+        }
+    }
+}
+
+/// Default key location: `<data_dir>/keys/template.key`
+pub fn default_key_file(data_dir: &Path) -> PathBuf {
+    data_dir.join("keys").join("template.key")
+}
+
+// ---------------------------------------------------------------------------
+// Key file provider
+// ---------------------------------------------------------------------------
+
+/// Stores the key in a file that must be owned by root (or the service user)
+/// and must not be readable by group or others
+pub struct FileKeyProvider {
+    path: PathBuf,
+}
+
+impl FileKeyProvider {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    fn previous_path(&self) -> PathBuf {
+        self.path.with_extension("key.previous")
+    }
+
+    fn read_key(path: &Path) -> Result<Option<TemplateKey>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let metadata = fs::metadata(path)?;
+        let euid = unsafe { libc::geteuid() };
+        if metadata.uid() != 0 && metadata.uid() != euid {
+            return Err(FaceAuthError::Storage(format!(
+                "Refusing to use key file {:?}: owned by UID {} (expected root or UID {})",
+                path, metadata.uid(), euid
+            )));
+        }
+        if metadata.mode() & 0o077 != 0 {
+            return Err(FaceAuthError::Storage(format!(
+                "Refusing to use key file {:?}: permissions {:o} allow group/other access (expected 600)",
+                path, metadata.mode() & 0o777
+            )));
+        }
+
+        let data = fs::read(path)?;
+        if data.len() != KEY_SIZE {
+            return Err(FaceAuthError::Storage(format!(
+                "Key file {:?} has invalid length {} (expected {})", path, data.len(), KEY_SIZE
+            )));
+        }
+
+        let mut key = [0u8; KEY_SIZE];
+        key.copy_from_slice(&data);
+        Ok(Some(key))
+    }
+
+    fn write_key(path: &Path, key: &TemplateKey) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(parent, fs::Permissions::from_mode(0o700))?;
+        }
+        encryption::write_private_file(path, key)
+    }
+}
+
+impl KeyProvider for FileKeyProvider {
+    fn describe(&self) -> String {
+        format!("key file {}", self.path.display())
+    }
+
+    fn current_key(&self) -> Result<TemplateKey> {
+        if let Some(key) = Self::read_key(&self.path)? {
+            return Ok(key);
+        }
+
+        let key = generate_key();
+        Self::write_key(&self.path, &key)?;
+        tracing::info!("Generated new template encryption key at {:?}", self.path);
+        Ok(key)
+    }
+
+    fn previous_key(&self) -> Result<Option<TemplateKey>> {
+        Self::read_key(&self.previous_path())
+    }
+
+    fn install_key(&self, key: &TemplateKey) -> Result<()> {
+        // Keep the old key around until every file has been re-encrypted
+        if let Some(old) = Self::read_key(&self.path)? {
+            Self::write_key(&self.previous_path(), &old)?;
+        }
+        Self::write_key(&self.path, key)
+    }
+
+    fn clear_previous(&self) -> Result<()> {
+        let previous = self.previous_path();
+        if previous.exists() {
+            fs::remove_file(previous)?;
+        }
+        Ok(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Kernel keyring provider
+// ---------------------------------------------------------------------------
+
+// keyctl(2) constants, defined here because older libc releases lack them
+const KEY_SPEC_USER_KEYRING: libc::c_long = -4;
+const KEYCTL_UNLINK: libc::c_long = 9;
+const KEYCTL_SEARCH: libc::c_long = 10;
+const KEYCTL_READ: libc::c_long = 11;
+
+/// Stores the key as a `user` key in the service user's kernel keyring
+///
+/// Keyring contents do not survive a reboot. Without TPM sealing the key has
+/// to come from somewhere at boot, so an optional key file acts as fallback:
+/// the key is loaded from it when missing from the keyring, and written to it
+/// whenever a new key is generated or installed.
+pub struct KeyringKeyProvider {
+    description: String,
+    fallback: Option<FileKeyProvider>,
+}
+
+impl KeyringKeyProvider {
+    pub fn new(description: &str, fallback_file: Option<PathBuf>) -> Result<Self> {
+        if description.is_empty() || description.contains('\0') {
+            return Err(FaceAuthError::Storage("Invalid keyring key description".into()));
+        }
+        Ok(Self {
+            description: description.to_string(),
+            fallback: fallback_file.map(FileKeyProvider::new),
+        })
+    }
+
+    fn previous_description(&self) -> String {
+        format!("{}.previous", self.description)
+    }
+
+    fn search(description: &str) -> Result<Option<libc::c_long>> {
+        let key_type = CString::new("user").unwrap();
+        let desc = CString::new(description)
+            .map_err(|_| FaceAuthError::Storage("Invalid keyring key description".into()))?;
+
+        let serial = unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_SEARCH,
+                KEY_SPEC_USER_KEYRING,
+                key_type.as_ptr(),
+                desc.as_ptr(),
+                0 as libc::c_long,
+            )
+        };
+
+        if serial < 0 {
+            let err = std::io::Error::last_os_error();
+            return match err.raw_os_error() {
+                Some(libc::ENOKEY) => Ok(None),
+                _ => Err(FaceAuthError::Storage(format!(
+                    "Keyring search for {} failed: {}", description, err
+                ))),
+            };
+        }
+
+        Ok(Some(serial))
+    }
+
+    fn read(description: &str) -> Result<Option<TemplateKey>> {
+        let serial = match Self::search(description)? {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+
+        let mut key = [0u8; KEY_SIZE];
+        let len = unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_READ,
+                serial,
+                key.as_mut_ptr(),
+                KEY_SIZE,
+            )
+        };
+
+        if len < 0 {
+            return Err(FaceAuthError::Storage(format!(
+                "Failed to read key {} from keyring: {}", description, std::io::Error::last_os_error()
+            )));
+        }
+        if len as usize != KEY_SIZE {
+            return Err(FaceAuthError::Storage(format!(
+                "Keyring key {} has invalid length {} (expected {})", description, len, KEY_SIZE
+            )));
+        }
+
+        Ok(Some(key))
+    }
+
+    fn write(description: &str, key: &TemplateKey) -> Result<()> {
+        let key_type = CString::new("user").unwrap();
+        let desc = CString::new(description)
+            .map_err(|_| FaceAuthError::Storage("Invalid keyring key description".into()))?;
+
+        // add_key(2) replaces the payload of an existing key with the same description
+        let serial = unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                key_type.as_ptr(),
+                desc.as_ptr(),
+                key.as_ptr(),
+                KEY_SIZE,
+                KEY_SPEC_USER_KEYRING,
+            )
+        };
+
+        if serial < 0 {
+            return Err(FaceAuthError::Storage(format!(
+                "Failed to add key {} to keyring: {}", description, std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    fn unlink(description: &str) -> Result<()> {
+        if let Some(serial) = Self::search(description)? {
+            let ret = unsafe {
+                libc::syscall(libc::SYS_keyctl, KEYCTL_UNLINK, serial, KEY_SPEC_USER_KEYRING)
+            };
+            if ret < 0 {
+                return Err(FaceAuthError::Storage(format!(
+                    "Failed to unlink key {} from keyring: {}", description, std::io::Error::last_os_error()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl KeyProvider for KeyringKeyProvider {
+    fn describe(&self) -> String {
+        match self.fallback {
+            Some(ref file) => format!("kernel keyring key {} (fallback: {})", self.description, file.describe()),
+            None => format!("kernel keyring key {}", self.description),
+        }
+    }
+
+    fn current_key(&self) -> Result<TemplateKey> {
+        if let Some(key) = Self::read(&self.description)? {
+            return Ok(key);
+        }
+
+        match self.fallback {
+            Some(ref file) => {
+                // current_key() on the file provider generates a key on first use
+                let key = file.current_key()?;
+                Self::write(&self.description, &key)?;
+                tracing::info!("Loaded template key into kernel keyring from {}", file.describe());
+                Ok(key)
+            }
+            None => {
+                let key = generate_key();
+                Self::write(&self.description, &key)?;
+                tracing::warn!("Generated new template key in kernel keyring only - it will be lost on reboot \
+                                unless keyring_file_fallback is enabled");
+                Ok(key)
+            }
+        }
+    }
+
+    fn previous_key(&self) -> Result<Option<TemplateKey>> {
+        if let Some(key) = Self::read(&self.previous_description())? {
+            return Ok(Some(key));
+        }
+        match self.fallback {
+            Some(ref file) => file.previous_key(),
+            None => Ok(None),
+        }
+    }
+
+    fn install_key(&self, key: &TemplateKey) -> Result<()> {
+        if let Some(old) = Self::read(&self.description)? {
+            Self::write(&self.previous_description(), &old)?;
+        }
+        Self::write(&self.description, key)?;
+        if let Some(ref file) = self.fallback {
+            file.install_key(key)?;
+        }
+        Ok(())
+    }
+
+    fn clear_previous(&self) -> Result<()> {
+        Self::unlink(&self.previous_description())?;
+        if let Some(ref file) = self.fallback {
+            file.clear_previous()?;
+        }
+        Ok(())
+    }
+}
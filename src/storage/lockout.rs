@@ -0,0 +1,189 @@
+use crate::common::{FaceAuthError, Result};
+use crate::common::config::AuthConfig;
+use crate::storage::encryption::write_private_file;
+use crate::storage::user_store::check_username;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Per-user failure state, persisted so restarting the service doesn't reset it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LockoutRecord {
+    /// Failed attempts since the last success or lockout
+    pub failures: u32,
+    /// Consecutive lockouts, drives the exponential backoff
+    pub lockouts: u32,
+    pub locked_until: Option<SystemTime>,
+    pub last_failure: Option<SystemTime>,
+}
+
+impl LockoutRecord {
+    /// Time left on the current lockout, if any
+    pub fn remaining(&self) -> Option<Duration> {
+        self.locked_until
+            .and_then(|until| until.duration_since(SystemTime::now()).ok())
+            .filter(|d| !d.is_zero())
+    }
+}
+
+/// Failure counters stored as one JSON file per user under `<data_dir>/lockout`
+pub struct LockoutStore {
+    dir: PathBuf,
+}
+
+impl LockoutStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn get(&self, username: &str) -> Result<LockoutRecord> {
+        let path = self.record_path(username)?;
+        if !path.exists() {
+            return Ok(LockoutRecord::default());
+        }
+
+        let data = fs::read(&path)?;
+        serde_json::from_slice(&data).map_err(|e| FaceAuthError::Storage(format!(
+            "Corrupt lockout record {:?}: {}", path, e
+        )))
+    }
+
+    /// Remaining lockout for `username`, or None if they may authenticate
+    pub fn check(&self, username: &str) -> Result<Option<Duration>> {
+        Ok(self.get(username)?.remaining())
+    }
+
+    /// Count a failed attempt. Returns the lockout duration if this failure
+    /// triggered one. A `max_failures` of 0 disables lockout.
+    pub fn record_failure(&self, username: &str, auth: &AuthConfig) -> Result<Option<Duration>> {
+        if auth.max_failures == 0 {
+            return Ok(None);
+        }
+
+        let mut record = self.get(username)?;
+        record.failures += 1;
+        record.last_failure = Some(SystemTime::now());
+
+        let mut lockout = None;
+        if record.failures >= auth.max_failures {
+            // lockout_seconds, then * backoff for every consecutive lockout, capped
+            let factor = auth.lockout_backoff.powi(record.lockouts.min(32) as i32) as f64;
+            let secs = (auth.lockout_seconds as f64 * factor).min(auth.max_lockout_seconds as f64);
+            let duration = Duration::from_secs(secs as u64);
+
+            record.failures = 0;
+            record.lockouts += 1;
+            record.locked_until = Some(SystemTime::now() + duration);
+            lockout = Some(duration);
+
+            tracing::warn!("User {} locked out of face authentication for {}s after {} failures (lockout #{})",
+                username, duration.as_secs(), auth.max_failures, record.lockouts);
+        }
+
+        self.save(username, &record)?;
+        Ok(lockout)
+    }
+
+    /// A successful authentication clears the failure history
+    pub fn record_success(&self, username: &str) -> Result<()> {
+        self.clear(username).map(|_| ())
+    }
+
+    /// Remove any failure state. Returns true if the user was locked out.
+    pub fn clear(&self, username: &str) -> Result<bool> {
+        let path = self.record_path(username)?;
+        if !path.exists() {
+            return Ok(false);
+        }
+
+        let was_locked = self.get(username).map(|r| r.remaining().is_some()).unwrap_or(false);
+        fs::remove_file(&path)?;
+        Ok(was_locked)
+    }
+
+    fn save(&self, username: &str, record: &LockoutRecord) -> Result<()> {
+        let data = serde_json::to_vec_pretty(record)
+            .map_err(|e| FaceAuthError::Storage(format!("Failed to serialize lockout record: {}", e)))?;
+        write_private_file(&self.record_path(username)?, &data)
+    }
+
+    fn record_path(&self, username: &str) -> Result<PathBuf> {
+        check_username(username)?;
+        Ok(self.dir.join(format!("{}.json", username)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth_config(max_failures: u32) -> AuthConfig {
+        AuthConfig {
+            similarity_threshold: 0.6,
+            timeout_seconds: 5,
+            detection_confidence: 0.5,
+            k_required_matches: 2,
+            n_total_attempts: 3,
+            embedding_buffer_size: 3,
+            use_embedding_fusion: true,
+            lost_face_timeout: 3,
+            max_failures,
+            lockout_seconds: 60,
+            lockout_backoff: 2.0,
+            max_lockout_seconds: 300,
+        }
+    }
+
+    fn store(name: &str) -> LockoutStore {
+        let dir = std::env::temp_dir().join(format!("suplinux-lockout-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        LockoutStore::new(dir)
+    }
+
+    #[test]
+    fn lockout_after_max_failures() {
+        let store = store("threshold");
+        let auth = auth_config(3);
+
+        assert_eq!(store.record_failure("alice", &auth).unwrap(), None);
+        assert_eq!(store.record_failure("alice", &auth).unwrap(), None);
+        assert!(store.check("alice").unwrap().is_none());
+        assert_eq!(store.record_failure("alice", &auth).unwrap(), Some(Duration::from_secs(60)));
+        assert!(store.check("alice").unwrap().is_some());
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let store = store("backoff");
+        let auth = auth_config(1);
+
+        let lockouts: Vec<_> = (0..5)
+            .map(|_| store.record_failure("alice", &auth).unwrap().unwrap().as_secs())
+            .collect();
+        assert_eq!(lockouts, [60, 120, 240, 300, 300]);
+    }
+
+    #[test]
+    fn zero_max_failures_disables_lockout() {
+        let store = store("disabled");
+        let auth = auth_config(0);
+
+        for _ in 0..10 {
+            assert_eq!(store.record_failure("alice", &auth).unwrap(), None);
+        }
+        assert!(store.check("alice").unwrap().is_none());
+        assert_eq!(store.get("alice").unwrap().failures, 0);
+    }
+
+    #[test]
+    fn success_clears_failures() {
+        let store = store("success");
+        let auth = auth_config(1);
+
+        store.record_failure("alice", &auth).unwrap();
+        store.record_success("alice").unwrap();
+        assert!(store.check("alice").unwrap().is_none());
+        assert_eq!(store.get("alice").unwrap().lockouts, 0);
+    }
+}
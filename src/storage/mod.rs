@@ -1,3 +1,11 @@
+pub mod audit;
+pub mod encryption;
+pub mod key_provider;
+pub mod lockout;
 pub mod user_store;
 
-pub use user_store::{UserStore, UserData};
\ No newline at end of file
+pub use audit::{AuditLog, AuditRecord, AuditQuery, AuditEvent, AuditOutcome};
+pub use encryption::TemplateCipher;
+pub use key_provider::{KeyProvider, FileKeyProvider, KeyringKeyProvider};
+pub use lockout::LockoutStore;
+pub use user_store::{UserStore, UserData, EnrollmentProfile, AdaptiveTemplate, DEFAULT_PROFILE, is_valid_profile_name};
\ No newline at end of file
@@ -1,58 +1,266 @@
 use crate::common::{FaceAuthError, Result, DevMode};
+use crate::common::config::StorageConfig;
 use crate::core::recognizer::Embedding;
+use crate::core::enrollment::{self, PoseTag};
+use crate::storage::encryption::{self, TemplateCipher};
+use crate::storage::key_provider::{self, KeyProvider};
 use directories::ProjectDirs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::sync::{PoisonError, RwLock};
+use std::time::SystemTime;
 use serde::{Serialize, Deserialize};
 
-const STORAGE_VERSION: u32 = 1;
+pub const STORAGE_VERSION: u32 = 3;
 
-#[derive(Serialize, Deserialize)]
+/// Profile enrollments go to unless another is named
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Taken shared by every write and exclusively by key rotation, so no store
+// opened before a rotation can write with the key it is about to discard
+static ROTATION_LOCK: RwLock<()> = RwLock::new(());
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct UserData {
     pub version: u32,
     pub username: String,
+    /// Separate enrollments, e.g. with and without glasses; authentication
+    /// matches against all of them
+    pub profiles: Vec<EnrollmentProfile>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EnrollmentProfile {
+    pub name: String,
+    /// Camera the profile was enrolled with
+    pub camera: String,
+    pub created: SystemTime,
     pub embeddings: Vec<Embedding>,
-    #[serde(default)]
     pub averaged_embedding: Option<Embedding>,
-    #[serde(default)]
     pub embedding_qualities: Option<Vec<f32>>,
+    /// Pose of each embedding from guided enrollment, None for unguided captures
+    pub embedding_poses: Option<Vec<Option<PoseTag>>>,
+}
+
+// Storage version 1, before pose tags
+#[derive(Deserialize)]
+struct UserDataV1 {
+    version: u32,
+    username: String,
+    embeddings: Vec<Embedding>,
+    averaged_embedding: Option<Embedding>,
+    embedding_qualities: Option<Vec<f32>>,
+}
+
+// Storage version 2, a single unnamed enrollment
+#[derive(Deserialize)]
+struct UserDataV2 {
+    version: u32,
+    username: String,
+    embeddings: Vec<Embedding>,
+    averaged_embedding: Option<Embedding>,
+    embedding_qualities: Option<Vec<f32>>,
+    embedding_poses: Option<Vec<Option<PoseTag>>>,
+}
+
+impl From<UserDataV1> for UserDataV2 {
+    fn from(v1: UserDataV1) -> Self {
+        Self {
+            version: v1.version,
+            username: v1.username,
+            embeddings: v1.embeddings,
+            averaged_embedding: v1.averaged_embedding,
+            embedding_qualities: v1.embedding_qualities,
+            embedding_poses: None,
+        }
+    }
+}
+
+impl From<UserDataV2> for UserData {
+    // The creation time is filled in from the file by `get_user`
+    fn from(v2: UserDataV2) -> Self {
+        Self {
+            version: v2.version,
+            username: v2.username,
+            profiles: vec![EnrollmentProfile {
+                name: DEFAULT_PROFILE.to_string(),
+                camera: "unknown".to_string(),
+                created: SystemTime::UNIX_EPOCH,
+                embeddings: v2.embeddings,
+                averaged_embedding: v2.averaged_embedding,
+                embedding_qualities: v2.embedding_qualities,
+                embedding_poses: v2.embedding_poses,
+            }],
+        }
+    }
+}
+
+/// A template learned from a successful authentication, stored apart from
+/// the enrollment in `<data_dir>/adaptive/<user>.bincode`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AdaptiveTemplate {
+    pub embedding: Embedding,
+    /// Similarity to the enrolled templates when it was learned
+    pub similarity: f32,
+    pub quality: f32,
+    pub added: SystemTime,
+    /// Enrolled profile it matched best; re-enrolling or deleting that
+    /// profile drops it
+    pub profile: String,
+}
+
+// Name of the pseudo-profile `with_adaptive` adds
+const ADAPTIVE_PROFILE: &str = "adaptive";
+
+impl UserData {
+    /// A user with no profiles yet
+    pub fn new(username: &str) -> Self {
+        Self {
+            version: STORAGE_VERSION,
+            username: username.to_string(),
+            profiles: Vec::new(),
+        }
+    }
+    
+    pub fn profile(&self, name: &str) -> Option<&EnrollmentProfile> {
+        self.profiles.iter().find(|p| p.name == name)
+    }
+    
+    pub fn profile_mut(&mut self, name: &str) -> Option<&mut EnrollmentProfile> {
+        self.profiles.iter_mut().find(|p| p.name == name)
+    }
+    
+    /// Add `profile`, replacing any profile of the same name
+    pub fn set_profile(&mut self, profile: EnrollmentProfile) {
+        match self.profile_mut(&profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+    
+    /// Remove the profile called `name`. Returns whether it existed.
+    pub fn remove_profile(&mut self, name: &str) -> bool {
+        let before = self.profiles.len();
+        self.profiles.retain(|p| p.name != name);
+        self.profiles.len() != before
+    }
+    
+    /// Embeddings across all profiles
+    pub fn embedding_count(&self) -> usize {
+        self.profiles.iter().map(|p| p.embeddings.len()).sum()
+    }
+    
+    /// The enrolled profiles plus `adaptive` as one more profile without an
+    /// averaged embedding, for matching
+    pub fn with_adaptive(&self, adaptive: &[AdaptiveTemplate]) -> UserData {
+        let mut combined = self.clone();
+        if let Some(latest) = adaptive.iter().map(|t| t.added).max() {
+            combined.profiles.push(EnrollmentProfile {
+                name: ADAPTIVE_PROFILE.to_string(),
+                camera: String::new(),
+                created: latest,
+                embeddings: adaptive.iter().map(|t| t.embedding.clone()).collect(),
+                averaged_embedding: None,
+                embedding_qualities: Some(adaptive.iter().map(|t| t.quality).collect()),
+                embedding_poses: None,
+            });
+        }
+        combined
+    }
+}
+
+// Usernames come straight off the socket - keep them inside our directories
+pub(crate) fn check_username(username: &str) -> Result<()> {
+    if username.is_empty() || username.starts_with('.') || username.contains(['/', '\0']) {
+        return Err(FaceAuthError::Storage(format!("Invalid username {:?}", username)));
+    }
+    Ok(())
+}
+
+/// Whether `name` can be used as a profile name; it doubles as a directory name
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name != ADAPTIVE_PROFILE
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+}
+
+/// Outcome of `UserStore::rotate_key`
+#[derive(Debug, Default)]
+pub struct KeyRotation {
+    /// Files re-encrypted with the new key
+    pub rotated: usize,
+    /// Files that could not be read and still use the old key: usernames,
+    /// or `adaptive/<user>` for adaptive templates
+    pub skipped: Vec<String>,
+}
+
+impl KeyRotation {
+    // Tampered, corrupt or never-migrated files can't be rewritten and will
+    // never become readable - note them and carry on. Anything else (I/O)
+    // might be transient, so it aborts the rotation.
+    fn skip(&mut self, file: String, error: FaceAuthError) -> Result<()> {
+        match error {
+            FaceAuthError::TemplateTampered(_) | FaceAuthError::Storage(_) => {
+                tracing::warn!("Leaving unreadable {} under the old template key: {}", file, error);
+                self.skipped.push(file);
+                Ok(())
+            }
+            FaceAuthError::UserNotFound(_) => Ok(()),  // Deleted meanwhile
+            error => Err(error),
+        }
+    }
 }
 
 pub struct UserStore {
     data_dir: PathBuf,
     enrollment_images_dir: PathBuf,
+    key_provider: Box<dyn KeyProvider>,
+    cipher: TemplateCipher,
+    previous_cipher: Option<TemplateCipher>,
 }
 
 impl UserStore {
-    #[allow(dead_code)]
-    pub fn new_with_paths(data_dir: PathBuf, enrollment_images_dir: PathBuf) -> Result<Self> {
+    /// Open the store with the key provider selected by `[storage]`
+    pub fn new_with_paths(data_dir: PathBuf, enrollment_images_dir: PathBuf, storage: &StorageConfig) -> Result<Self> {
+        let key_provider = key_provider::from_config(storage, &data_dir)?;
+        Self::new_with_key_provider(data_dir, enrollment_images_dir, key_provider)
+    }
+    
+    pub fn new_with_key_provider(
+        data_dir: PathBuf,
+        enrollment_images_dir: PathBuf,
+        key_provider: Box<dyn KeyProvider>,
+    ) -> Result<Self> {
         fs::create_dir_all(&data_dir)?;
         fs::create_dir_all(&enrollment_images_dir)?;
         
+        let cipher = TemplateCipher::new(&key_provider.current_key()?);
+        let previous_cipher = key_provider.previous_key()?
+            .map(|key| TemplateCipher::new(&key));
+        
+        tracing::debug!("UserStore using {} (key id {})", 
+                        key_provider.describe(), encryption::hex(&cipher.key_id()));
+        
         Ok(Self { 
             data_dir,
             enrollment_images_dir,
+            key_provider,
+            cipher,
+            previous_cipher,
         })
     }
     
     #[allow(dead_code)]
-    pub fn new() -> Result<Self> {
+    pub fn new(storage: &StorageConfig) -> Result<Self> {
         let dirs = ProjectDirs::from("com", "faceauth", "FaceAuth")
             .ok_or_else(|| FaceAuthError::Storage("Failed to get project dirs".into()))?;
 
         let data_dir = dirs.data_dir().to_path_buf();
         let enrollment_images_dir = data_dir.join("enrollment_images");
         
-        fs::create_dir_all(&data_dir)?;
-        fs::create_dir_all(&enrollment_images_dir)?;
-
-        Ok(Self { 
-            data_dir,
-            enrollment_images_dir,
-        })
+        Self::new_with_paths(data_dir, enrollment_images_dir, storage)
     }
     
-    pub fn new_with_dev_mode(dev_mode: &DevMode) -> Result<Self> {
+    pub fn new_with_dev_mode(dev_mode: &DevMode, storage: &StorageConfig) -> Result<Self> {
         let (data_dir, enrollment_images_dir) = if dev_mode.is_enabled() {
             (
                 dev_mode.data_dir(),
@@ -69,54 +277,325 @@ impl UserStore {
             (data_dir, enrollment_images_dir)
         };
         
-        fs::create_dir_all(&data_dir)?;
-        fs::create_dir_all(&enrollment_images_dir)?;
-        
         if dev_mode.is_enabled() {
             tracing::debug!("UserStore using dev directories: {:?}", data_dir);
         }
         
-        Ok(Self { 
-            data_dir,
-            enrollment_images_dir,
-        })
+        Self::new_with_paths(data_dir, enrollment_images_dir, storage)
+    }
+
+    /// Hex id of the key new files are encrypted with
+    pub fn active_key_id(&self) -> String {
+        encryption::hex(&self.cipher.key_id())
     }
 
     pub fn save_user_data(&self, user_data: &UserData) -> Result<()> {
-        let user_file = self.data_dir.join(format!("{}.bincode", user_data.username));
+        let _rotation = ROTATION_LOCK.read().unwrap_or_else(PoisonError::into_inner);
+        self.write_user(user_data, &self.current_cipher()?)
+    }
+
+    fn write_user(&self, user_data: &UserData, cipher: &TemplateCipher) -> Result<()> {
+        let user_file = self.user_path(&user_data.username)?;
         let encoded = bincode::serialize(user_data)
             .map_err(|e| FaceAuthError::Storage(format!("Failed to serialize: {}", e)))?;
-        fs::write(user_file, encoded)?;
+        let encrypted = cipher.encrypt(&encoded, &user_data.username)?;
+        encryption::write_private_file(&user_file, &encrypted)?;
         Ok(())
     }
 
     pub fn get_user(&self, username: &str) -> Result<UserData> {
-        let user_file = self.data_dir.join(format!("{}.bincode", username));
+        let user_file = self.user_path(username)?;
 
         if !user_file.exists() {
             return Err(FaceAuthError::UserNotFound(username.to_string()));
         }
 
-        let data = fs::read(user_file)?;
-        let mut user_data: UserData = bincode::deserialize(&data)
-            .map_err(|e| FaceAuthError::Storage(format!("Failed to deserialize: {}", e)))?;
+        let data = fs::read(&user_file)?;
+        if !encryption::is_encrypted(&data) {
+            // Anyone able to write here could plant a plaintext template, so
+            // never trust one implicitly - see `migrate_plaintext`
+            return Err(FaceAuthError::TemplateTampered(format!(
+                "{:?} is not encrypted; if it predates template encryption, run `sudo suplinux migrate-templates`",
+                user_file
+            )));
+        }
+        let plaintext = self.decrypt(&data, username)?;
+        Self::decode_user(&plaintext, username, &user_file)
+    }
+
+    /// Encrypt user files written before template encryption. The plaintext
+    /// on disk is trusted as is, so this only runs when root asks for it
+    /// (`suplinux migrate-templates`) right after upgrading.
+    pub fn migrate_plaintext(&self) -> Result<usize> {
+        let mut migrated = 0;
+        for username in self.list_users()? {
+            let user_file = self.user_path(&username)?;
+            let data = fs::read(&user_file)?;
+            if encryption::is_encrypted(&data) {
+                continue;
+            }
+
+            let user_data = Self::decode_user(&data, &username, &user_file)?;
+            self.save_user_data(&user_data)?;
+            tracing::info!("Encrypted plaintext template {:?}", user_file);
+            migrated += 1;
+        }
+        Ok(migrated)
+    }
+
+    // Parse a decrypted user file of any storage version
+    fn decode_user(plaintext: &[u8], username: &str, user_file: &Path) -> Result<UserData> {
+        // The version comes first, so peek at it to pick the layout
+        let deserialize_error = |e| FaceAuthError::Storage(format!("Failed to deserialize: {}", e));
+        let stored_version: u32 = bincode::deserialize(plaintext).map_err(deserialize_error)?;
+        let mut user_data: UserData = match stored_version {
+            0 | 1 => UserDataV2::from(bincode::deserialize::<UserDataV1>(plaintext).map_err(deserialize_error)?).into(),
+            2 => bincode::deserialize::<UserDataV2>(plaintext).map_err(deserialize_error)?.into(),
+            _ => bincode::deserialize(plaintext).map_err(deserialize_error)?,
+        };
+
+        if user_data.username != username {
+            return Err(FaceAuthError::TemplateTampered(format!(
+                "file for {} contains data for {}", username, user_data.username
+            )));
+        }
 
         // Handle version migration if needed
+        // (the file keeps its old layout until the next save)
         if user_data.version < STORAGE_VERSION {
-            // Future migration logic would go here
+            if user_data.version < 3 {
+                // Best guess at when the single pre-profile enrollment was made
+                let created = fs::metadata(user_file).and_then(|m| m.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                for profile in &mut user_data.profiles {
+                    profile.created = created;
+                }
+            }
             user_data.version = STORAGE_VERSION;
         }
 
         Ok(user_data)
     }
 
+    /// Adaptive templates learned for `username`, oldest first
+    pub fn get_adaptive(&self, username: &str) -> Result<Vec<AdaptiveTemplate>> {
+        let path = self.adaptive_path(username)?;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        
+        let data = fs::read(&path)?;
+        let plaintext = self.decrypt(&data, &Self::adaptive_context(username))?;
+        bincode::deserialize(&plaintext)
+            .map_err(|e| FaceAuthError::Storage(format!("Failed to deserialize adaptive templates: {}", e)))
+    }
+    
+    /// Replace the adaptive templates of `username`; an empty set removes the file
+    pub fn save_adaptive(&self, username: &str, templates: &[AdaptiveTemplate]) -> Result<()> {
+        let _rotation = ROTATION_LOCK.read().unwrap_or_else(PoisonError::into_inner);
+        self.write_adaptive(username, templates, &self.current_cipher()?)
+    }
+
+    /// Drop the adaptive templates learned against `profile`. Returns how many went.
+    pub fn clear_adaptive_profile(&self, username: &str, profile: &str) -> Result<usize> {
+        let mut templates = self.get_adaptive(username)?;
+        let before = templates.len();
+        templates.retain(|t| t.profile != profile);
+        if templates.len() != before {
+            self.save_adaptive(username, &templates)?;
+        }
+        Ok(before - templates.len())
+    }
+
+    fn write_adaptive(&self, username: &str, templates: &[AdaptiveTemplate], cipher: &TemplateCipher) -> Result<()> {
+        let path = self.adaptive_path(username)?;
+        if templates.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            return Ok(());
+        }
+        
+        fs::create_dir_all(self.data_dir.join("adaptive"))?;
+        let encoded = bincode::serialize(templates)
+            .map_err(|e| FaceAuthError::Storage(format!("Failed to serialize: {}", e)))?;
+        let encrypted = cipher.encrypt(&encoded, &Self::adaptive_context(username))?;
+        encryption::write_private_file(&path, &encrypted)?;
+        Ok(())
+    }
+    
+    fn adaptive_path(&self, username: &str) -> Result<PathBuf> {
+        check_username(username)?;
+        Ok(self.data_dir.join("adaptive").join(format!("{}.bincode", username)))
+    }
+    
+    fn user_path(&self, username: &str) -> Result<PathBuf> {
+        check_username(username)?;
+        Ok(self.data_dir.join(format!("{}.bincode", username)))
+    }
+    
+    /// Remove everything stored for `username`: templates, adaptive templates
+    /// and enrollment images. Returns false if the user was not enrolled.
+    pub fn delete_user(&self, username: &str) -> Result<bool> {
+        let user_file = self.user_path(username)?;
+        let existed = user_file.exists();
+        if existed {
+            fs::remove_file(&user_file)?;
+        }
+        
+        let adaptive_file = self.adaptive_path(username)?;
+        if adaptive_file.exists() {
+            fs::remove_file(&adaptive_file)?;
+        }
+        
+        let images_dir = self.get_enrollment_images_dir(username)?;
+        if images_dir.exists() {
+            fs::remove_dir_all(&images_dir)?;
+        }
+        
+        Ok(existed)
+    }
+    
+    // Bound into the ciphertext so an adaptive file can't pass for an enrollment
+    fn adaptive_context(username: &str) -> String {
+        format!("adaptive/{}", username)
+    }
+    
+    // Pick the current or (mid-rotation) previous key based on the envelope key id
+    fn decrypt(&self, data: &[u8], context: &str) -> Result<Vec<u8>> {
+        let header = encryption::read_header(data)?;
+        if header.key_id == self.cipher.key_id() {
+            return self.cipher.decrypt(data, context);
+        }
+        match self.previous_cipher {
+            Some(ref previous) if previous.key_id() == header.key_id => previous.decrypt(data, context),
+            // Possibly rotated since this store was opened
+            _ => self.current_cipher()?.decrypt(data, context),
+        }
+    }
+
+    // The provider's key at the time of the write, which a rotation since
+    // this store was opened may have replaced
+    fn current_cipher(&self) -> Result<TemplateCipher> {
+        Ok(TemplateCipher::new(&self.key_provider.current_key()?))
+    }
+
+    /// Names of all users with a stored template
+    pub fn list_users(&self) -> Result<Vec<String>> {
+        let mut users = Vec::new();
+        for entry in fs::read_dir(&self.data_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("bincode") {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
+                users.push(name.to_string());
+            }
+        }
+        users.sort();
+        Ok(users)
+    }
+
+    /// Generate a new key and re-encrypt every user file with it
+    ///
+    /// The old key stays available as "previous" until all files are rewritten,
+    /// so an interrupted rotation is completed by the next call. Files that
+    /// can't be read are left as they are and reported in `KeyRotation::skipped`.
+    pub fn rotate_key(&mut self) -> Result<KeyRotation> {
+        let _rotation = ROTATION_LOCK.write().unwrap_or_else(PoisonError::into_inner);
+
+        // Another rotation may have finished since this store was opened
+        self.cipher = self.current_cipher()?;
+        self.previous_cipher = self.key_provider.previous_key()?
+            .map(|key| TemplateCipher::new(&key));
+
+        if self.previous_cipher.is_some() {
+            tracing::warn!("Completing interrupted key rotation before rotating again");
+            self.reencrypt_all(&mut KeyRotation::default())?;
+            self.key_provider.clear_previous()?;
+            self.previous_cipher = None;
+        }
+
+        let new_key = key_provider::generate_key();
+        self.key_provider.install_key(&new_key)?;
+        let old_cipher = std::mem::replace(&mut self.cipher, TemplateCipher::new(&new_key));
+        self.previous_cipher = Some(old_cipher);
+
+        let mut report = KeyRotation::default();
+        self.reencrypt_all(&mut report)?;
+
+        self.key_provider.clear_previous()?;
+        self.previous_cipher = None;
+
+        tracing::info!("Rotated template key to {} ({} files re-encrypted, {} unreadable)", 
+                       self.active_key_id(), report.rotated, report.skipped.len());
+        Ok(report)
+    }
+
+    // Rewrite every file with the current key. Unreadable files are skipped,
+    // but a failed write stops the rotation so the previous key is kept.
+    fn reencrypt_all(&self, report: &mut KeyRotation) -> Result<()> {
+        for username in self.list_users()? {
+            match self.get_user(&username) {
+                Ok(user_data) => {
+                    self.write_user(&user_data, &self.cipher)?;
+                    report.rotated += 1;
+                }
+                Err(e) => report.skip(username.clone(), e)?,
+            }
+
+            let adaptive_context = Self::adaptive_context(&username);
+            match self.get_adaptive(&username) {
+                Ok(adaptive) if adaptive.is_empty() => {}
+                Ok(adaptive) => {
+                    self.write_adaptive(&username, &adaptive, &self.cipher)?;
+                    report.rotated += 1;
+                }
+                Err(e) => report.skip(adaptive_context, e)?,
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_enrollment_images_dir(&self, username: &str) -> Result<PathBuf> {
+        check_username(username)?;
         let user_dir = self.enrollment_images_dir.join(username);
         Ok(user_dir)
     }
+    
+    /// Images of the default profile stay directly in the user's directory
+    pub fn get_profile_images_dir(&self, username: &str, profile: &str) -> Result<PathBuf> {
+        let user_dir = self.get_enrollment_images_dir(username)?;
+        if profile == DEFAULT_PROFILE {
+            Ok(user_dir)
+        } else {
+            Ok(user_dir.join(profile))
+        }
+    }
+    
+    /// Delete the enrollment images of one profile
+    pub fn remove_profile_images(&self, username: &str, profile: &str) -> Result<()> {
+        let dir = self.get_profile_images_dir(username, profile)?;
+        if !dir.exists() {
+            return Ok(());
+        }
+        if profile != DEFAULT_PROFILE {
+            fs::remove_dir_all(&dir)?;
+            return Ok(());
+        }
+        
+        // Other profiles' directories live alongside the default profile's images
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
 
-    /// Merge new embeddings with existing user data
-    pub fn merge_user_data(&self, existing: &mut UserData, new_embeddings: Vec<Embedding>, 
+    /// Merge new embeddings into an existing profile
+    pub fn merge_profile(&self, existing: &mut EnrollmentProfile, new_embeddings: Vec<Embedding>, 
                           new_qualities: Vec<f32>, replace_weak: bool) -> (usize, usize) {
         let initial_count = existing.embeddings.len();
         let mut replaced_count = 0;
@@ -140,6 +619,9 @@ impl UserStore {
                         if let Some(ref mut quals) = existing.embedding_qualities {
                             quals[idx] = *new_qual;
                         }
+                        if let Some(pose) = existing.embedding_poses.as_mut().and_then(|poses| poses.get_mut(idx)) {
+                            *pose = None;
+                        }
                         replaced = true;
                         replaced_count += 1;
                         break;
@@ -163,32 +645,110 @@ impl UserStore {
             }
         }
         
+        // Enhancement captures are unguided
+        if let Some(poses) = existing.embedding_poses.as_mut() {
+            poses.resize(existing.embeddings.len(), None);
+        }
+        
         // Recalculate averaged embedding
-        existing.averaged_embedding = Some(Self::average_embeddings(&existing.embeddings));
+        existing.averaged_embedding = enrollment::average_embeddings(&existing.embeddings);
         
         let final_count = existing.embeddings.len();
         (final_count - initial_count, replaced_count)
     }
-    
-    fn average_embeddings(embeddings: &[Embedding]) -> Embedding {
-        if embeddings.is_empty() {
-            return vec![];
-        }
-        
-        let embedding_size = embeddings[0].len();
-        let mut averaged = vec![0.0f32; embedding_size];
-        
-        for embedding in embeddings {
-            for (i, &value) in embedding.iter().enumerate() {
-                averaged[i] += value;
-            }
-        }
-        
-        let count = embeddings.len() as f32;
-        for value in &mut averaged {
-            *value /= count;
-        }
-        
-        averaged
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::key_provider::FileKeyProvider;
+
+    fn store(name: &str) -> UserStore {
+        let dir = std::env::temp_dir().join(format!("suplinux-store-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        open(&dir)
+    }
+
+    // Another handle on the same files, like a second service worker
+    fn open(dir: &Path) -> UserStore {
+        let key_provider = Box::new(FileKeyProvider::new(key_provider::default_key_file(dir)));
+        UserStore::new_with_key_provider(dir.to_path_buf(), dir.join("enrollment_images"), key_provider).unwrap()
+    }
+
+    fn plant_plaintext(store: &UserStore, username: &str) {
+        let data = bincode::serialize(&UserData::new(username)).unwrap();
+        fs::write(store.user_path(username).unwrap(), data).unwrap();
+    }
+
+    #[test]
+    fn plaintext_template_is_refused() {
+        let store = store("plaintext");
+        plant_plaintext(&store, "alice");
+
+        assert!(matches!(store.get_user("alice"), Err(FaceAuthError::TemplateTampered(_))));
+        // Still plaintext - refusing it must not seal it under the real key
+        let data = fs::read(store.user_path("alice").unwrap()).unwrap();
+        assert!(!encryption::is_encrypted(&data));
+    }
+
+    #[test]
+    fn migration_encrypts_plaintext_templates() {
+        let store = store("migrate");
+        plant_plaintext(&store, "alice");
+        store.save_user_data(&UserData::new("bob")).unwrap();
+
+        assert_eq!(store.migrate_plaintext().unwrap(), 1);
+        assert_eq!(store.get_user("alice").unwrap().username, "alice");
+        assert_eq!(store.get_user("bob").unwrap().username, "bob");
+        assert_eq!(store.migrate_plaintext().unwrap(), 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn clearing_a_profile_keeps_other_adaptive_templates() {
+        let store = store("adaptive-profiles");
+        let learned = |profile: &str| AdaptiveTemplate {
+            embedding: vec![1.0, 0.0],
+            similarity: 0.8,
+            quality: 0.9,
+            added: SystemTime::now(),
+            profile: profile.to_string(),
+        };
+        store.save_adaptive("alice", &[learned("default"), learned("glasses"), learned("default")]).unwrap();
+
+        assert_eq!(store.clear_adaptive_profile("alice", "default").unwrap(), 2);
+        let kept = store.get_adaptive("alice").unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].profile, "glasses");
+        assert_eq!(store.clear_adaptive_profile("alice", "office").unwrap(), 0);
+    }
+
+    #[test]
+    fn rotation_skips_unreadable_files() {
+        let mut store = store("rotate-skip");
+        plant_plaintext(&store, "alice");
+        store.save_user_data(&UserData::new("bob")).unwrap();
+        let old_key = store.active_key_id();
+
+        let report = store.rotate_key().unwrap();
+        assert_eq!(report.rotated, 1);
+        assert_eq!(report.skipped, ["alice"]);
+        assert_ne!(store.active_key_id(), old_key);
+        assert!(store.key_provider.previous_key().unwrap().is_none());
+        assert_eq!(open(&store.data_dir).get_user("bob").unwrap().username, "bob");
+    }
+
+    #[test]
+    fn store_opened_before_rotation_writes_with_new_key() {
+        let mut rotator = store("rotate-stale");
+        let stale = open(&rotator.data_dir);
+        stale.save_user_data(&UserData::new("bob")).unwrap();
+
+        rotator.rotate_key().unwrap();
+        stale.save_user_data(&UserData::new("carol")).unwrap();
+
+        // The old key is gone, so both files must be under the new one
+        let fresh = open(&rotator.data_dir);
+        assert_eq!(fresh.get_user("bob").unwrap().username, "bob");
+        assert_eq!(fresh.get_user("carol").unwrap().username, "carol");
+        assert_eq!(stale.get_user("carol").unwrap().username, "carol");
+    }
+}
@@ -19,6 +19,17 @@ fi
 
 echo "Removing SupLinux..."
 
+# Stop and disable the socket first so nothing starts the service again
+if systemctl is-active --quiet suplinux.socket; then
+    echo "Stopping socket..."
+    systemctl stop suplinux.socket
+fi
+
+if systemctl is-enabled --quiet suplinux.socket 2>/dev/null; then
+    echo "Disabling socket..."
+    systemctl disable suplinux.socket
+fi
+
 # Stop and disable systemd service if running
 if systemctl is-active --quiet suplinux; then
     echo "Stopping service..."
@@ -30,10 +41,11 @@ if systemctl is-enabled --quiet suplinux 2>/dev/null; then
     systemctl disable suplinux
 fi
 
-# Remove systemd service file
-if [ -f /etc/systemd/system/suplinux.service ]; then
+# Remove systemd service and socket files
+if [ -f /etc/systemd/system/suplinux.service ] || [ -f /etc/systemd/system/suplinux.socket ]; then
     echo "Removing systemd service..."
     rm -f /etc/systemd/system/suplinux.service
+    rm -f /etc/systemd/system/suplinux.socket
     systemctl daemon-reload
 fi
 